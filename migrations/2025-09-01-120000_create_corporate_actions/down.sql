@@ -0,0 +1,3 @@
+-- Drop corporate action tracking tables
+DROP TABLE IF EXISTS symbol_history;
+DROP TABLE IF EXISTS corporate_actions;
@@ -0,0 +1,43 @@
+-- Corporate actions and symbol lifecycle tracking
+-- Records splits, dividends, renames and delistings so candle and trade history can be adjusted
+
+CREATE TABLE corporate_actions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    security_id UUID NOT NULL REFERENCES securities (security_id),
+    symbol VARCHAR(20) NOT NULL, -- Symbol in effect when the action was announced
+    action_type VARCHAR(20) NOT NULL CHECK (action_type IN ('split', 'dividend', 'rename', 'delist')),
+    effective_date TIMESTAMPTZ NOT NULL, -- Ex-date for splits and dividends
+    split_ratio NUMERIC(20, 8), -- New shares per old share, e.g. 4 for a 4:1 split
+    dividend_amount NUMERIC(20, 8), -- Cash paid per share
+    new_symbol VARCHAR(20), -- Target symbol for renames
+    notes TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (security_id, action_type, effective_date),
+    CONSTRAINT valid_split CHECK (action_type <> 'split' OR (split_ratio IS NOT NULL AND split_ratio > 0)),
+    CONSTRAINT valid_dividend CHECK (action_type <> 'dividend' OR (dividend_amount IS NOT NULL AND dividend_amount > 0)),
+    CONSTRAINT valid_rename CHECK (action_type <> 'rename' OR new_symbol IS NOT NULL)
+);
+
+-- Symbol validity windows per security (a NULL valid_to marks the current symbol)
+CREATE TABLE symbol_history (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    security_id UUID NOT NULL REFERENCES securities (security_id),
+    symbol VARCHAR(20) NOT NULL,
+    valid_from TIMESTAMPTZ NOT NULL,
+    valid_to TIMESTAMPTZ,
+    status VARCHAR(20) NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'renamed', 'delisted')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (security_id, valid_from),
+    CONSTRAINT valid_symbol_window CHECK (valid_to IS NULL OR valid_to >= valid_from)
+);
+
+-- Seed the history with the symbols already on file
+INSERT INTO symbol_history (security_id, symbol, valid_from)
+SELECT security_id, symbol, created_at FROM securities;
+
+CREATE INDEX idx_corporate_actions_security_date ON corporate_actions (security_id, effective_date DESC);
+CREATE INDEX idx_corporate_actions_symbol ON corporate_actions (symbol);
+CREATE INDEX idx_symbol_history_symbol ON symbol_history (symbol, valid_from DESC);
+CREATE INDEX idx_symbol_history_security_id ON symbol_history (security_id);
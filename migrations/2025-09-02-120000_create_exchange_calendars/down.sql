@@ -0,0 +1,4 @@
+-- Drop exchange calendar tables
+DROP TABLE IF EXISTS exchange_holidays;
+DROP TABLE IF EXISTS exchange_sessions;
+DROP TABLE IF EXISTS exchange_calendars;
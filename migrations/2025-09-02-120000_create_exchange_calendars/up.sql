@@ -0,0 +1,41 @@
+-- Trading calendars and session metadata per exchange
+-- Sessions are stored in venue-local time and converted with the calendar's IANA timezone
+
+CREATE TABLE exchange_calendars (
+    exchange_id UUID PRIMARY KEY REFERENCES exchanges (exchange_id) ON DELETE CASCADE,
+    timezone VARCHAR(64) NOT NULL DEFAULT 'UTC', -- IANA name, e.g. 'America/New_York'
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Weekly session template. A close_time at or before open_time closes on the following day;
+-- '24:00' closes at the next midnight, which is how 24/7 venues are described.
+CREATE TABLE exchange_sessions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    exchange_id UUID NOT NULL REFERENCES exchange_calendars (exchange_id) ON DELETE CASCADE,
+    day_of_week SMALLINT NOT NULL CHECK (day_of_week BETWEEN 1 AND 7), -- ISO: 1 = Monday, 7 = Sunday
+    session_type VARCHAR(20) NOT NULL CHECK (session_type IN ('pre_market', 'regular', 'post_market')),
+    open_time TIME NOT NULL,
+    close_time TIME NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (exchange_id, day_of_week, session_type)
+);
+
+-- Full closures (early_close IS NULL) and half days (early_close set)
+CREATE TABLE exchange_holidays (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    exchange_id UUID NOT NULL REFERENCES exchange_calendars (exchange_id) ON DELETE CASCADE,
+    holiday_date DATE NOT NULL, -- Venue-local date
+    name VARCHAR(255) NOT NULL,
+    early_close TIME,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (exchange_id, holiday_date)
+);
+
+CREATE INDEX idx_exchange_sessions_exchange_id ON exchange_sessions (exchange_id, day_of_week);
+CREATE INDEX idx_exchange_holidays_exchange_date ON exchange_holidays (exchange_id, holiday_date);
+
+SELECT diesel_manage_updated_at('exchange_calendars');
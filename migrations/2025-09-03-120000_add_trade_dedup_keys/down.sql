@@ -0,0 +1,3 @@
+-- Revert trade deduplication registry and ingest timestamp
+DROP TABLE IF EXISTS trade_keys;
+ALTER TABLE trades DROP COLUMN IF EXISTS ingested_at;
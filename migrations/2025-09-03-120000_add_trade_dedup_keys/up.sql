@@ -0,0 +1,30 @@
+-- Separate exchange event time from ingest time on trades and enforce one row per exchange trade
+-- created_at (the hypertable time column) now carries the exchange's event time when the feed provides it;
+-- ingested_at records when the row reached the database. Rows written before this migration have a NULL
+-- ingested_at, and their created_at is the ingest time.
+
+-- Added without a default first so existing compressed chunks are not rewritten
+ALTER TABLE trades ADD COLUMN ingested_at TIMESTAMPTZ;
+ALTER TABLE trades ALTER COLUMN ingested_at SET DEFAULT NOW();
+
+-- Unique indexes on a hypertable must include its time column, so uniqueness of (exchange_id, trade_id)
+-- is enforced through this registry instead. Ingest claims a key before inserting the trade.
+CREATE TABLE trade_keys (
+    exchange_id UUID NOT NULL REFERENCES exchanges (exchange_id),
+    trade_id VARCHAR(255) NOT NULL,
+    event_time TIMESTAMPTZ NOT NULL, -- created_at of the stored trade, locates its chunk
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    duplicate_count INTEGER NOT NULL DEFAULT 0, -- Times the trade was received again after the first
+    PRIMARY KEY (exchange_id, trade_id)
+);
+
+-- Pruned alongside the 90 day trades retention
+CREATE INDEX idx_trade_keys_event_time ON trade_keys (event_time);
+
+-- Backfill from existing trades, keeping the earliest copy of each exchange trade
+INSERT INTO trade_keys (exchange_id, trade_id, event_time, first_seen_at, last_seen_at, duplicate_count)
+SELECT exchange_id, trade_id, MIN(created_at), MIN(created_at), MAX(created_at), COUNT(*) - 1
+FROM trades
+GROUP BY exchange_id, trade_id
+ON CONFLICT DO NOTHING;
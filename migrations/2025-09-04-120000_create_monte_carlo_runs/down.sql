@@ -0,0 +1,3 @@
+-- Drop Monte Carlo tables
+DROP TABLE IF EXISTS monte_carlo_paths;
+DROP TABLE IF EXISTS monte_carlo_runs;
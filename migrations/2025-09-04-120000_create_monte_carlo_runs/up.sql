@@ -0,0 +1,35 @@
+-- Monte Carlo run configuration and the paths generated for it
+-- Seeds are u64 values stored bit-for-bit in BIGINT columns, so large seeds read back negative in SQL
+
+CREATE TABLE monte_carlo_runs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    strategy_name VARCHAR(255) NOT NULL,
+    symbol VARCHAR(50) NOT NULL,
+    exchange VARCHAR(50) NOT NULL,
+    data_source VARCHAR(20) NOT NULL CHECK (data_source IN ('orders', 'snapshots', 'candles', 'trades')),
+    resampling_scheme VARCHAR(50) NOT NULL, -- 'simple', 'fixed_block', 'circular_block', 'stationary', 'window_shuffle', ...
+    scheme_parameters JSONB NOT NULL DEFAULT '{}', -- e.g. {"block_size": 50} or {"window_minutes": 30}
+    base_seed BIGINT, -- Seed path seeds were derived from, NULL if paths were seeded individually
+    num_paths INTEGER NOT NULL CHECK (num_paths > 0),
+    status VARCHAR(20) NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'completed', 'failed')),
+    notes TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+-- One row per simulated path. backtest_id is filled in once the path's backtest result is stored.
+CREATE TABLE monte_carlo_paths (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    run_id UUID NOT NULL REFERENCES monte_carlo_runs (id) ON DELETE CASCADE,
+    path_index INTEGER NOT NULL CHECK (path_index >= 0),
+    seed BIGINT NOT NULL,
+    resampling_scheme VARCHAR(50) NOT NULL,
+    scheme_parameters JSONB NOT NULL DEFAULT '{}',
+    backtest_id UUID REFERENCES backtest_results (backtest_id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (run_id, path_index)
+);
+
+CREATE INDEX idx_monte_carlo_runs_strategy ON monte_carlo_runs (strategy_name, created_at DESC);
+CREATE INDEX idx_monte_carlo_paths_backtest_id ON monte_carlo_paths (backtest_id);
@@ -0,0 +1,2 @@
+-- Drop backtest tags
+DROP TABLE IF EXISTS backtest_tags;
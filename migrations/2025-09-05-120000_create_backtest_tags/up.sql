@@ -0,0 +1,12 @@
+-- Free-form labels on backtests, used to pin backtests or select them for garbage collection
+-- Not tied to backtest_results: scratch-only backtests (sim orders/trades, no result) can be tagged too
+
+CREATE TABLE backtest_tags (
+    backtest_id UUID NOT NULL,
+    tag VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (backtest_id, tag)
+);
+
+CREATE INDEX idx_backtest_tags_tag ON backtest_tags (tag);
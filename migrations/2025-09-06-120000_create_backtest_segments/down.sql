@@ -0,0 +1,2 @@
+-- Drop walk-forward segments
+DROP TABLE IF EXISTS backtest_segments;
@@ -0,0 +1,23 @@
+-- Walk-forward sequences: each fold pairs an in-sample (optimisation) result with the
+-- out-of-sample result that validated the parameters chosen on it.
+-- Rows sharing walk_forward_id form one sequence; fold_index orders its folds.
+
+CREATE TABLE backtest_segments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    walk_forward_id UUID NOT NULL,
+    strategy_instance_id UUID REFERENCES strategy_instances (id) ON DELETE SET NULL,
+    window_type VARCHAR(10) NOT NULL CHECK (window_type IN ('anchored', 'rolling')),
+    fold_index INTEGER NOT NULL CHECK (fold_index >= 0),
+    segment_type VARCHAR(20) NOT NULL CHECK (segment_type IN ('in_sample', 'out_of_sample')),
+    backtest_result_id UUID NOT NULL REFERENCES backtest_results (id) ON DELETE CASCADE,
+    window_start TIMESTAMPTZ NOT NULL,
+    window_end TIMESTAMPTZ NOT NULL,
+    parameters JSONB, -- Parameters chosen in-sample and carried into the out-of-sample run
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT valid_segment_window CHECK (window_end > window_start),
+    UNIQUE (walk_forward_id, fold_index, segment_type)
+);
+
+CREATE INDEX idx_backtest_segments_backtest_result_id ON backtest_segments (backtest_result_id);
+CREATE INDEX idx_backtest_segments_strategy_instance_id ON backtest_segments (strategy_instance_id);
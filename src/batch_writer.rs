@@ -0,0 +1,347 @@
+use crate::errors::BatchWriterError;
+use crate::models::{historical_order::NewHistoricalOrder, trade::NewTrade};
+use crate::ops::{bulk_ingest_ops, historical_order_ops, trades_ops};
+use crate::order_lifecycle::ValidationMode;
+use async_trait::async_trait;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::AsyncPgConnection;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio_retry::RetryIf;
+use tracing::{info, error, debug, warn};
+
+/// Destination for batches flushed by a `BatchWriter`
+#[async_trait]
+pub trait BatchSink<T>: Send + Sync + 'static {
+    async fn write_batch(&self, batch: &[T]) -> anyhow::Result<()>;
+
+    /// Most rows `write_batch` accepts at once, if the sink has a limit
+    fn max_batch_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Writes trades through `ingest_trades`, deduplicating on `(exchange_id, trade_id)`
+pub struct TradeSink {
+    pub pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+}
+
+#[async_trait]
+impl BatchSink<NewTrade> for TradeSink {
+    async fn write_batch(&self, batch: &[NewTrade]) -> anyhow::Result<()> {
+        trades_ops::ingest_trades(self.pool.clone(), batch.to_vec()).await?;
+        Ok(())
+    }
+
+    fn max_batch_size(&self) -> Option<usize> {
+        Some(trades_ops::MAX_INGEST_BATCH_SIZE)
+    }
+}
+
+/// Writes historical order events through `create_validated_historical_orders`
+pub struct HistoricalOrderSink {
+    pub pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    pub mode: ValidationMode,
+}
+
+#[async_trait]
+impl BatchSink<NewHistoricalOrder> for HistoricalOrderSink {
+    async fn write_batch(&self, batch: &[NewHistoricalOrder]) -> anyhow::Result<()> {
+        historical_order_ops::create_validated_historical_orders(self.pool.clone(), batch.to_vec(), self.mode).await?;
+        Ok(())
+    }
+
+    fn max_batch_size(&self) -> Option<usize> {
+        Some(historical_order_ops::MAX_HISTORICAL_ORDER_BATCH_SIZE)
+    }
+}
+
+/// Writes trades through the binary COPY path, for feeds that outrun `ingest_trades`
+pub struct BulkTradeSink {
+    pub client: Mutex<tokio_postgres::Client>,
+}
+
+#[async_trait]
+impl BatchSink<NewTrade> for BulkTradeSink {
+    async fn write_batch(&self, batch: &[NewTrade]) -> anyhow::Result<()> {
+        let mut client = self.client.lock().await;
+        bulk_ingest_ops::bulk_ingest_trades(&mut client, batch).await?;
+        Ok(())
+    }
+}
+
+/// Writes historical order events through the binary COPY path, without lifecycle checks
+pub struct BulkHistoricalOrderSink {
+    pub client: Mutex<tokio_postgres::Client>,
+}
+
+#[async_trait]
+impl BatchSink<NewHistoricalOrder> for BulkHistoricalOrderSink {
+    async fn write_batch(&self, batch: &[NewHistoricalOrder]) -> anyhow::Result<()> {
+        let mut client = self.client.lock().await;
+        bulk_ingest_ops::bulk_ingest_historical_orders(&mut client, batch).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchWriterConfig {
+    /// Flush as soon as this many rows are buffered
+    pub max_batch_size: usize,
+    /// Flush whatever is buffered at least this often
+    pub flush_interval: Duration,
+    /// Rows that may wait in the channel before `write` starts blocking
+    pub channel_capacity: usize,
+    /// Attempts per batch after the first failure before it is dropped. Errors that cannot
+    /// succeed on a retry, such as rejected input or a rolled back transaction, drop the
+    /// batch straight away.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each subsequent one
+    pub retry_backoff: Duration,
+}
+
+impl Default for BatchWriterConfig {
+    fn default() -> Self {
+        BatchWriterConfig {
+            max_batch_size: 1000,
+            flush_interval: Duration::from_millis(500),
+            channel_capacity: 10000,
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(50),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct BatchWriterCounters {
+    rows_written: AtomicU64,
+    rows_dropped: AtomicU64,
+    batches_flushed: AtomicU64,
+    batches_failed: AtomicU64,
+    retries: AtomicU64,
+    last_flush_latency_us: AtomicU64,
+    max_flush_latency_us: AtomicU64,
+    total_flush_latency_us: AtomicU64,
+}
+
+/// Point-in-time view of a writer's queue and flush behaviour
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BatchWriterMetrics {
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    pub rows_written: u64,
+    pub rows_dropped: u64,
+    pub batches_flushed: u64,
+    pub batches_failed: u64,
+    pub retries: u64,
+    pub last_flush_latency_us: u64,
+    pub max_flush_latency_us: u64,
+    pub avg_flush_latency_us: u64,
+}
+
+/// Buffers rows sent over a bounded channel and flushes them to a `BatchSink` when
+/// `max_batch_size` rows are pending or `flush_interval` elapses. `write` waits while
+/// the channel is full, so producers slow down when the database falls behind.
+/// `max_batch_size` may not exceed the sink's own limit, or every full batch would fail.
+pub struct BatchWriter<T: Send + Sync + 'static> {
+    sender: mpsc::Sender<T>,
+    counters: Arc<BatchWriterCounters>,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + Sync + 'static> BatchWriter<T> {
+    pub fn new<S: BatchSink<T>>(sink: S, config: BatchWriterConfig) -> Result<BatchWriter<T>, BatchWriterError> {
+        if let Some(limit) = sink.max_batch_size().filter(|limit| config.max_batch_size > *limit) {
+            error!("Batch writer max_batch_size {} exceeds the sink limit of {}", config.max_batch_size, limit);
+            return Err(BatchWriterError::InvalidConfig(format!(
+                "max_batch_size {} exceeds the sink limit of {} rows", config.max_batch_size, limit
+            )));
+        }
+
+        let (sender, receiver) = mpsc::channel(config.channel_capacity.max(1));
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let counters = Arc::new(BatchWriterCounters::default());
+
+        let handle = tokio::spawn(run_writer(sink, config, receiver, shutdown_rx, counters.clone()));
+
+        Ok(BatchWriter {
+            sender,
+            counters,
+            shutdown: Some(shutdown_tx),
+            handle: Some(handle),
+        })
+    }
+
+    /// Queue a row, waiting for space if the channel is full
+    pub async fn write(&self, row: T) -> Result<(), BatchWriterError> {
+        self.sender.send(row).await.map_err(|_| BatchWriterError::Closed)
+    }
+
+    /// Queue a row without waiting; fails with `QueueFull` instead of applying backpressure
+    pub fn try_write(&self, row: T) -> Result<(), BatchWriterError> {
+        self.sender.try_send(row).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => BatchWriterError::QueueFull,
+            mpsc::error::TrySendError::Closed(_) => BatchWriterError::Closed,
+        })
+    }
+
+    /// A handle producers can clone onto other tasks
+    pub fn sender(&self) -> mpsc::Sender<T> {
+        self.sender.clone()
+    }
+
+    pub fn metrics(&self) -> BatchWriterMetrics {
+        let batches_flushed = self.counters.batches_flushed.load(Ordering::Relaxed);
+        let batches_failed = self.counters.batches_failed.load(Ordering::Relaxed);
+        let attempts = batches_flushed + batches_failed;
+        let total_latency = self.counters.total_flush_latency_us.load(Ordering::Relaxed);
+
+        BatchWriterMetrics {
+            queue_depth: self.sender.max_capacity() - self.sender.capacity(),
+            queue_capacity: self.sender.max_capacity(),
+            rows_written: self.counters.rows_written.load(Ordering::Relaxed),
+            rows_dropped: self.counters.rows_dropped.load(Ordering::Relaxed),
+            batches_flushed,
+            batches_failed,
+            retries: self.counters.retries.load(Ordering::Relaxed),
+            last_flush_latency_us: self.counters.last_flush_latency_us.load(Ordering::Relaxed),
+            max_flush_latency_us: self.counters.max_flush_latency_us.load(Ordering::Relaxed),
+            avg_flush_latency_us: total_latency.checked_div(attempts).unwrap_or(0),
+        }
+    }
+
+    /// Stop accepting rows, flush everything already queued and wait for the writer task.
+    /// Rows sent through cloned senders after this call are rejected.
+    pub async fn shutdown(mut self) -> Result<BatchWriterMetrics, BatchWriterError> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.await.map_err(|e| BatchWriterError::TaskFailed(e.to_string()))?;
+        }
+        Ok(self.metrics())
+    }
+}
+
+async fn run_writer<T: Send + Sync + 'static, S: BatchSink<T>>(
+    sink: S,
+    config: BatchWriterConfig,
+    mut receiver: mpsc::Receiver<T>,
+    mut shutdown: oneshot::Receiver<()>,
+    counters: Arc<BatchWriterCounters>,
+) {
+    let max_batch_size = config.max_batch_size.max(1);
+    let mut buffer: Vec<T> = Vec::with_capacity(max_batch_size);
+    let mut ticker = tokio::time::interval(config.flush_interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            received = receiver.recv() => match received {
+                Some(row) => {
+                    buffer.push(row);
+                    if buffer.len() >= max_batch_size {
+                        flush(&sink, &config, &mut buffer, &counters).await;
+                    }
+                },
+                // Every sender is gone, nothing more can arrive
+                None => break,
+            },
+            _ = ticker.tick() => {
+                if !buffer.is_empty() {
+                    flush(&sink, &config, &mut buffer, &counters).await;
+                }
+            },
+            _ = &mut shutdown => {
+                receiver.close();
+                while let Some(row) = receiver.recv().await {
+                    buffer.push(row);
+                    if buffer.len() >= max_batch_size {
+                        flush(&sink, &config, &mut buffer, &counters).await;
+                    }
+                }
+                break;
+            },
+        }
+    }
+
+    if !buffer.is_empty() {
+        flush(&sink, &config, &mut buffer, &counters).await;
+    }
+    info!("Batch writer stopped after writing {} rows", counters.rows_written.load(Ordering::Relaxed));
+}
+
+async fn flush<T: Send + Sync + 'static, S: BatchSink<T>>(
+    sink: &S,
+    config: &BatchWriterConfig,
+    buffer: &mut Vec<T>,
+    counters: &BatchWriterCounters,
+) {
+    let rows = buffer.len() as u64;
+    let start_time = Instant::now();
+    let batch: &[T] = buffer;
+    let delays = (0..config.max_retries).map(|i| config.retry_backoff.saturating_mul(2u32.saturating_pow(i)));
+    let mut attempts = 0u32;
+
+    let outcome = RetryIf::spawn(
+        delays,
+        || {
+            attempts += 1;
+            if attempts > 1 {
+                counters.retries.fetch_add(1, Ordering::Relaxed);
+            }
+            sink.write_batch(batch)
+        },
+        |e: &anyhow::Error| {
+            let retry = is_retryable(e);
+            if retry {
+                warn!("Batch of {} rows failed, retrying: {}", rows, e);
+            }
+            retry
+        },
+    ).await;
+
+    let latency_us = start_time.elapsed().as_micros() as u64;
+    counters.last_flush_latency_us.store(latency_us, Ordering::Relaxed);
+    counters.max_flush_latency_us.fetch_max(latency_us, Ordering::Relaxed);
+    counters.total_flush_latency_us.fetch_add(latency_us, Ordering::Relaxed);
+
+    match outcome {
+        Ok(()) => {
+            counters.rows_written.fetch_add(rows, Ordering::Relaxed);
+            counters.batches_flushed.fetch_add(1, Ordering::Relaxed);
+            debug!("Flushed {} rows in {}us", rows, latency_us);
+        },
+        Err(e) => {
+            counters.rows_dropped.fetch_add(rows, Ordering::Relaxed);
+            counters.batches_failed.fetch_add(1, Ordering::Relaxed);
+            error!("Dropping batch of {} rows after {} attempts: {}", rows, attempts, e);
+        },
+    }
+
+    buffer.clear();
+}
+
+/// Whether a failed write could succeed if repeated. Rejected input and rolled back
+/// transactions fail the same way every time; the database ops already retry connection
+/// errors themselves, but a pool that stays exhausted is worth another attempt here.
+fn is_retryable(error: &anyhow::Error) -> bool {
+    use diesel::result::{DatabaseErrorKind, Error};
+
+    match error.downcast_ref::<Error>() {
+        Some(Error::RollbackTransaction) => false,
+        Some(Error::DatabaseError(kind, _)) => !matches!(
+            kind,
+            DatabaseErrorKind::UniqueViolation
+                | DatabaseErrorKind::ForeignKeyViolation
+                | DatabaseErrorKind::NotNullViolation
+                | DatabaseErrorKind::CheckViolation
+        ),
+        _ => true,
+    }
+}
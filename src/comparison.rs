@@ -0,0 +1,470 @@
+use crate::metrics::{self, covariance, mean, std_dev, EquityData};
+use crate::models::strategy::NewStrategyComparison;
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Version of the `results`/`summary` layout written to `strategy_comparisons`
+pub const COMPARISON_SCHEMA_VERSION: u32 = 1;
+
+const EULER_MASCHERONI: f64 = 0.5772156649015329;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ComparisonConfig {
+    /// Return periods per year used to annualize. Inferred from the aligned curve when `None`.
+    pub periods_per_year: Option<f64>,
+    /// Number of strategy variants tried when searching for these, for the deflated Sharpe
+    /// ratio. Defaults to the number of backtests compared.
+    pub trials: Option<usize>,
+    /// Two-sided level at which a Sharpe difference counts as significant
+    pub significance_level: f64,
+}
+
+impl Default for ComparisonConfig {
+    fn default() -> Self {
+        ComparisonConfig { periods_per_year: None, trials: None, significance_level: 0.05 }
+    }
+}
+
+/// One backtest entering the comparison
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonInput {
+    pub backtest_result_id: Uuid,
+    pub strategy_name: String,
+    pub strategy_instance_id: Option<Uuid>,
+    pub benchmark: Option<String>,
+    pub equity: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl ComparisonInput {
+    pub fn new<E: EquityData>(
+        backtest_result_id: Uuid,
+        strategy_name: &str,
+        strategy_instance_id: Option<Uuid>,
+        benchmark: Option<String>,
+        equity: &[E],
+    ) -> ComparisonInput {
+        ComparisonInput {
+            backtest_result_id,
+            strategy_name: strategy_name.to_string(),
+            strategy_instance_id,
+            benchmark,
+            equity: metrics::equity_points(equity),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyStatistics {
+    pub backtest_result_id: Uuid,
+    pub strategy_name: String,
+    /// Over the aligned window, not the whole backtest
+    pub total_return: f64,
+    pub annualized_volatility: f64,
+    pub sharpe_ratio: Option<f64>,
+    pub max_drawdown: f64,
+    pub skewness: Option<f64>,
+    /// Non-excess kurtosis (3 for a normal distribution)
+    pub kurtosis: Option<f64>,
+    /// Probability that the true Sharpe ratio exceeds the expected maximum of `trials`
+    /// unskilled strategies (Bailey and Lopez de Prado)
+    pub deflated_sharpe_ratio: Option<f64>,
+}
+
+/// Jobson-Korkie test with Memmel's correction for `H0: SR(a) = SR(b)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharpeDifferenceTest {
+    pub a: Uuid,
+    pub b: Uuid,
+    /// Annualized
+    pub sharpe_a: Option<f64>,
+    /// Annualized
+    pub sharpe_b: Option<f64>,
+    pub correlation: Option<f64>,
+    pub z_statistic: Option<f64>,
+    /// Two-sided
+    pub p_value: Option<f64>,
+    pub significant: bool,
+}
+
+/// Worst drawdown of `a`'s equity measured in units of `b`'s equity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelativeDrawdown {
+    pub a: Uuid,
+    pub b: Uuid,
+    pub max_drawdown: f64,
+    pub peak: Option<DateTime<Utc>>,
+    pub trough: Option<DateTime<Utc>>,
+    pub recovery: Option<DateTime<Utc>>,
+}
+
+/// Typed layout of `strategy_comparisons.results`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonResults {
+    pub schema_version: u32,
+    pub backtest_result_ids: Vec<Uuid>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Return periods between the timestamps present in every equity curve
+    pub observations: usize,
+    pub periods_per_year: f64,
+    pub trials: usize,
+    /// Annualized Sharpe ratio the best of `trials` unskilled strategies would reach by luck
+    pub expected_max_sharpe: f64,
+    /// Pearson correlation of period returns, rows and columns in `backtest_result_ids` order
+    pub correlation_matrix: Vec<Vec<Option<f64>>>,
+    pub strategies: Vec<StrategyStatistics>,
+    pub sharpe_tests: Vec<SharpeDifferenceTest>,
+    /// Every ordered pair
+    pub relative_drawdowns: Vec<RelativeDrawdown>,
+}
+
+/// Typed layout of `strategy_comparisons.summary`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonSummary {
+    pub schema_version: u32,
+    /// Best first; strategies without a Sharpe ratio come last
+    pub ranking_by_sharpe: Vec<Uuid>,
+    pub ranking_by_deflated_sharpe: Vec<Uuid>,
+    /// `(better, worse)` pairs whose Sharpe difference is significant
+    pub significant_differences: Vec<(Uuid, Uuid)>,
+    /// Strategies whose deflated Sharpe ratio is at least `1 - significance_level`
+    pub passing_deflated_sharpe: Vec<Uuid>,
+    pub most_correlated_pair: Option<(Uuid, Uuid, f64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestComparison {
+    pub results: ComparisonResults,
+    pub summary: ComparisonSummary,
+    pub strategy_instance_ids: Vec<Uuid>,
+    /// Set when every compared backtest used the same benchmark
+    pub benchmark_symbol: Option<String>,
+}
+
+impl BacktestComparison {
+    pub fn to_new_comparison(&self, comparison_name: &str, description: Option<&str>, created_by: Option<&str>) -> Result<NewStrategyComparison, Error> {
+        Ok(NewStrategyComparison {
+            comparison_name: comparison_name.to_string(),
+            description: description.map(|d| d.to_string()),
+            strategies: serde_json::to_value(&self.strategy_instance_ids)?,
+            comparison_period: Some(serde_json::json!({ "start": self.results.start, "end": self.results.end })),
+            benchmark_symbol: self.benchmark_symbol.clone(),
+            results: Some(serde_json::to_value(&self.results)?),
+            summary: Some(serde_json::to_value(&self.summary)?),
+            created_by: created_by.map(|c| c.to_string()),
+        })
+    }
+}
+
+/// Align the equity curves on the timestamps they all share and compare their returns:
+/// correlation matrix, pairwise Sharpe difference tests, deflated Sharpe ratios and
+/// relative drawdowns.
+pub fn compare_backtests(inputs: &[ComparisonInput], config: &ComparisonConfig) -> Result<BacktestComparison, Error> {
+    if inputs.len() < 2 {
+        return Err(anyhow::anyhow!("At least two backtests are needed for a comparison"));
+    }
+
+    let (timestamps, values) = align_curves(inputs);
+    if timestamps.len() < 3 {
+        return Err(anyhow::anyhow!("Equity curves share only {} timestamps; at least 3 are needed", timestamps.len()));
+    }
+
+    let returns: Vec<Vec<f64>> = values
+        .iter()
+        .map(|curve| curve.windows(2).map(|w| w[1] / w[0] - 1.0).collect())
+        .collect();
+    let periods_per_year = config.periods_per_year.filter(|p| *p > 0.0).unwrap_or_else(|| infer_periods_per_year(&timestamps));
+    let annualizer = periods_per_year.sqrt();
+    let observations = returns[0].len();
+
+    // Per-period Sharpe ratios; the tests below work on these, only reporting annualizes
+    let sharpes: Vec<Option<f64>> = returns
+        .iter()
+        .map(|r| match (mean(r), std_dev(r)) {
+            (Some(m), Some(s)) if s > 0.0 => Some(m / s),
+            _ => None,
+        })
+        .collect();
+
+    let correlation_matrix: Vec<Vec<Option<f64>>> = returns
+        .iter()
+        .map(|a| returns.iter().map(|b| correlation(a, b)).collect())
+        .collect();
+
+    let trials = config.trials.unwrap_or(inputs.len()).max(1);
+    let observed: Vec<f64> = sharpes.iter().flatten().copied().collect();
+    let expected_max_sharpe = expected_max_sharpe(&observed, trials);
+
+    let strategies: Vec<StrategyStatistics> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let curve: Vec<(DateTime<Utc>, f64)> = timestamps.iter().copied().zip(values[i].iter().copied()).collect();
+            let (skewness, kurtosis) = moments(&returns[i]);
+            StrategyStatistics {
+                backtest_result_id: input.backtest_result_id,
+                strategy_name: input.strategy_name.clone(),
+                total_return: values[i][values[i].len() - 1] / values[i][0] - 1.0,
+                annualized_volatility: std_dev(&returns[i]).map(|s| s * annualizer).unwrap_or(0.0),
+                sharpe_ratio: sharpes[i].map(|s| s * annualizer),
+                max_drawdown: metrics::find_drawdown_periods(&curve).iter().map(|d| d.magnitude).fold(0.0, f64::max),
+                skewness,
+                kurtosis,
+                deflated_sharpe_ratio: match (sharpes[i], skewness, kurtosis) {
+                    (Some(sr), Some(skew), Some(kurt)) => deflated_sharpe_ratio(sr, expected_max_sharpe, observations, skew, kurt),
+                    _ => None,
+                },
+            }
+        })
+        .collect();
+
+    let mut sharpe_tests = Vec::new();
+    for i in 0..inputs.len() {
+        for j in (i + 1)..inputs.len() {
+            let rho = correlation_matrix[i][j];
+            let z = match (sharpes[i], sharpes[j], rho) {
+                (Some(a), Some(b), Some(rho)) => memmel_z(a, b, rho, observations),
+                _ => None,
+            };
+            let p_value = z.map(|z| 2.0 * (1.0 - normal_cdf(z.abs())));
+            sharpe_tests.push(SharpeDifferenceTest {
+                a: inputs[i].backtest_result_id,
+                b: inputs[j].backtest_result_id,
+                sharpe_a: sharpes[i].map(|s| s * annualizer),
+                sharpe_b: sharpes[j].map(|s| s * annualizer),
+                correlation: rho,
+                z_statistic: z,
+                p_value,
+                significant: p_value.is_some_and(|p| p < config.significance_level),
+            });
+        }
+    }
+
+    let mut relative_drawdowns = Vec::new();
+    for i in 0..inputs.len() {
+        for j in 0..inputs.len() {
+            if i == j {
+                continue;
+            }
+            let relative: Vec<(DateTime<Utc>, f64)> = timestamps
+                .iter()
+                .enumerate()
+                .map(|(t, ts)| (*ts, values[i][t] / values[j][t]))
+                .collect();
+            let periods = metrics::find_drawdown_periods(&relative);
+            let worst = periods.iter().max_by(|x, y| x.magnitude.total_cmp(&y.magnitude));
+            relative_drawdowns.push(RelativeDrawdown {
+                a: inputs[i].backtest_result_id,
+                b: inputs[j].backtest_result_id,
+                max_drawdown: worst.map(|d| d.magnitude).unwrap_or(0.0),
+                peak: worst.map(|d| d.peak),
+                trough: worst.map(|d| d.trough),
+                recovery: worst.and_then(|d| d.recovery),
+            });
+        }
+    }
+
+    let summary = summarize(&strategies, &sharpe_tests, &correlation_matrix, inputs, config.significance_level);
+
+    let mut strategy_instance_ids: Vec<Uuid> = inputs.iter().filter_map(|i| i.strategy_instance_id).collect();
+    strategy_instance_ids.sort();
+    strategy_instance_ids.dedup();
+    let benchmark_symbol = match inputs[0].benchmark.as_ref() {
+        Some(b) if inputs.iter().all(|i| i.benchmark.as_ref() == Some(b)) => Some(b.clone()),
+        _ => None,
+    };
+
+    Ok(BacktestComparison {
+        results: ComparisonResults {
+            schema_version: COMPARISON_SCHEMA_VERSION,
+            backtest_result_ids: inputs.iter().map(|i| i.backtest_result_id).collect(),
+            start: timestamps[0],
+            end: timestamps[timestamps.len() - 1],
+            observations,
+            periods_per_year,
+            trials,
+            expected_max_sharpe: expected_max_sharpe * annualizer,
+            correlation_matrix,
+            strategies,
+            sharpe_tests,
+            relative_drawdowns,
+        },
+        summary,
+        strategy_instance_ids,
+        benchmark_symbol,
+    })
+}
+
+fn summarize(
+    strategies: &[StrategyStatistics],
+    sharpe_tests: &[SharpeDifferenceTest],
+    correlation_matrix: &[Vec<Option<f64>>],
+    inputs: &[ComparisonInput],
+    significance_level: f64,
+) -> ComparisonSummary {
+    let rank = |key: fn(&StrategyStatistics) -> Option<f64>| {
+        let mut ranked: Vec<&StrategyStatistics> = strategies.iter().collect();
+        ranked.sort_by(|x, y| match (key(x), key(y)) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+        ranked.iter().map(|s| s.backtest_result_id).collect::<Vec<_>>()
+    };
+
+    let significant_differences = sharpe_tests
+        .iter()
+        .filter(|t| t.significant)
+        .filter_map(|t| match (t.sharpe_a, t.sharpe_b) {
+            (Some(a), Some(b)) if a >= b => Some((t.a, t.b)),
+            (Some(_), Some(_)) => Some((t.b, t.a)),
+            _ => None,
+        })
+        .collect();
+
+    let mut most_correlated_pair: Option<(Uuid, Uuid, f64)> = None;
+    for i in 0..inputs.len() {
+        for j in (i + 1)..inputs.len() {
+            if let Some(rho) = correlation_matrix[i][j] {
+                if most_correlated_pair.is_none_or(|(_, _, best)| rho > best) {
+                    most_correlated_pair = Some((inputs[i].backtest_result_id, inputs[j].backtest_result_id, rho));
+                }
+            }
+        }
+    }
+
+    ComparisonSummary {
+        schema_version: COMPARISON_SCHEMA_VERSION,
+        ranking_by_sharpe: rank(|s| s.sharpe_ratio),
+        ranking_by_deflated_sharpe: rank(|s| s.deflated_sharpe_ratio),
+        significant_differences,
+        passing_deflated_sharpe: strategies
+            .iter()
+            .filter(|s| s.deflated_sharpe_ratio.is_some_and(|d| d >= 1.0 - significance_level))
+            .map(|s| s.backtest_result_id)
+            .collect(),
+        most_correlated_pair,
+    }
+}
+
+/// Keep the timestamps present in every curve, returning them with each curve's values
+fn align_curves(inputs: &[ComparisonInput]) -> (Vec<DateTime<Utc>>, Vec<Vec<f64>>) {
+    let maps: Vec<BTreeMap<DateTime<Utc>, f64>> = inputs.iter().map(|i| i.equity.iter().copied().collect()).collect();
+    let timestamps: Vec<DateTime<Utc>> = maps[0]
+        .keys()
+        .filter(|ts| maps[1..].iter().all(|m| m.contains_key(ts)))
+        .copied()
+        .collect();
+    let values = maps.iter().map(|m| timestamps.iter().map(|ts| m[ts]).collect()).collect();
+    (timestamps, values)
+}
+
+fn infer_periods_per_year(timestamps: &[DateTime<Utc>]) -> f64 {
+    let mut gaps: Vec<i64> = timestamps.windows(2).map(|w| (w[1] - w[0]).num_seconds()).filter(|g| *g > 0).collect();
+    if gaps.is_empty() {
+        return 252.0;
+    }
+    gaps.sort_unstable();
+    365.25 * 86400.0 / gaps[gaps.len() / 2] as f64
+}
+
+fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    match (covariance(a, b), std_dev(a), std_dev(b)) {
+        (Some(c), Some(sa), Some(sb)) if sa > 0.0 && sb > 0.0 => Some(c / (sa * sb)),
+        _ => None,
+    }
+}
+
+/// Sample skewness and non-excess kurtosis
+fn moments(values: &[f64]) -> (Option<f64>, Option<f64>) {
+    let (Some(m), Some(s)) = (mean(values), std_dev(values)) else {
+        return (None, None);
+    };
+    if s <= 0.0 {
+        return (None, None);
+    }
+    let n = values.len() as f64;
+    let skew = values.iter().map(|v| ((v - m) / s).powi(3)).sum::<f64>() / n;
+    let kurt = values.iter().map(|v| ((v - m) / s).powi(4)).sum::<f64>() / n;
+    (Some(skew), Some(kurt))
+}
+
+/// Memmel-corrected Jobson-Korkie statistic for per-period Sharpe ratios `a` and `b`
+fn memmel_z(a: f64, b: f64, rho: f64, observations: usize) -> Option<f64> {
+    let variance = (2.0 * (1.0 - rho) + 0.5 * (a * a + b * b - 2.0 * a * b * rho * rho)) / observations as f64;
+    if variance > 0.0 { Some((a - b) / variance.sqrt()) } else { None }
+}
+
+/// Expected maximum per-period Sharpe ratio among `trials` strategies with zero true Sharpe,
+/// using the cross-sectional variance of the observed ratios
+fn expected_max_sharpe(observed: &[f64], trials: usize) -> f64 {
+    if trials < 2 {
+        return 0.0;
+    }
+    let Some(sd) = std_dev(observed) else {
+        return 0.0;
+    };
+    let n = trials as f64;
+    sd * ((1.0 - EULER_MASCHERONI) * inverse_normal_cdf(1.0 - 1.0 / n)
+        + EULER_MASCHERONI * inverse_normal_cdf(1.0 - 1.0 / (n * std::f64::consts::E)))
+}
+
+fn deflated_sharpe_ratio(sharpe: f64, benchmark: f64, observations: usize, skewness: f64, kurtosis: f64) -> Option<f64> {
+    let denominator = 1.0 - skewness * sharpe + (kurtosis - 1.0) / 4.0 * sharpe * sharpe;
+    if denominator <= 0.0 || observations < 2 {
+        return None;
+    }
+    Some(normal_cdf((sharpe - benchmark) * ((observations - 1) as f64).sqrt() / denominator.sqrt()))
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+/// Complementary error function, fractional error below 1.2e-7
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let ans = t * (-z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+        .exp();
+    if x >= 0.0 { ans } else { 2.0 - ans }
+}
+
+/// Acklam's rational approximation of the standard normal quantile, relative error below 1.2e-9
+fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02, 1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00];
+    const B: [f64; 5] = [-5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02, 6.680131188771972e+01, -1.328068155288572e+01];
+    const C: [f64; 6] = [-7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00, -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00];
+    const D: [f64; 4] = [7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00, 3.754408661907416e+00];
+    const P_LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    }
+}
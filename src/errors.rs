@@ -42,3 +42,18 @@ impl From<diesel_async::pooled_connection::deadpool::PoolError> for DatabaseErro
         DatabaseError::ConnectionError(error.to_string())
     }
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum BatchWriterError {
+    #[error("Batch writer is shut down")]
+    Closed,
+
+    #[error("Batch writer queue is full")]
+    QueueFull,
+
+    #[error("Batch writer task failed: {0}")]
+    TaskFailed(String),
+
+    #[error("Invalid batch writer config: {0}")]
+    InvalidConfig(String),
+}
@@ -2,6 +2,15 @@ pub mod ops;
 pub mod schema;
 pub mod models;
 pub mod errors;
+pub mod batch_writer;
+pub mod comparison;
+pub mod matching_engine;
+pub mod metrics;
+pub mod order_lifecycle;
+pub mod replay;
+pub mod report;
+pub mod report_storage;
+pub mod resampling;
 
 use anyhow::Result;
 use diesel_async::AsyncPgConnection;
@@ -24,6 +33,24 @@ pub fn create_timescale_connection_pool() -> deadpool::Pool<AsyncPgConnection> {
         .expect("Failed to create database pool")
 }
 
+/// Function to open a dedicated tokio-postgres connection for COPY-based bulk ingestion,
+/// which the pooled diesel-async connections don't expose
+pub async fn create_timescale_copy_client() -> Result<tokio_postgres::Client> {
+    dotenv().ok();
+    println!("Opening database connection for bulk ingestion");
+
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let (client, connection) = tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("Bulk ingestion connection error: {}", e);
+        }
+    });
+
+    Ok(client)
+}
+
 /// Function to get a connection from the pool
 pub async fn get_timescale_connection(
     pool: Arc<deadpool::Pool<AsyncPgConnection>>,
@@ -0,0 +1,524 @@
+use crate::models::sim_open_buy_order::{NewSimOpenBuyOrder, SimOpenBuyOrder};
+use crate::models::sim_open_sell_order::{NewSimOpenSellOrder, SimOpenSellOrder};
+use crate::models::sim_trade::NewSimTrade;
+use crate::ops::{sim_open_buy_order_ops, sim_open_sell_order_ops, sim_trade_ops};
+use anyhow::Error;
+use bigdecimal::{BigDecimal, Zero};
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::AsyncPgConnection;
+use serde::{Deserialize, Serialize};
+use std::cmp::{min, Reverse};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl OrderSide {
+    /// Value stored in `sim_trades.side`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        }
+    }
+
+    pub fn opposite(&self) -> OrderSide {
+        match self {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MatchRequest {
+    /// Match against the opposite side up to `price`, then rest the remainder
+    Limit { order_id: String, side: OrderSide, price: BigDecimal, quantity: BigDecimal },
+    /// Match against the opposite side at any price; whatever cannot fill is dropped
+    Market { order_id: String, side: OrderSide, quantity: BigDecimal },
+    /// Remove a resting order from either side of the book
+    Cancel { order_id: String },
+}
+
+/// Fee rates as fractions of notional. Negative maker rates are rebates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeRates {
+    pub maker: BigDecimal,
+    pub taker: BigDecimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    pub default: FeeRates,
+    /// Rates replacing `default` for specific symbols
+    pub symbol_overrides: HashMap<String, FeeRates>,
+}
+
+impl FeeSchedule {
+    pub fn new(maker: BigDecimal, taker: BigDecimal) -> FeeSchedule {
+        FeeSchedule {
+            default: FeeRates { maker, taker },
+            symbol_overrides: HashMap::new(),
+        }
+    }
+
+    pub fn zero() -> FeeSchedule {
+        FeeSchedule::new(BigDecimal::zero(), BigDecimal::zero())
+    }
+
+    pub fn with_override(mut self, symbol: &str, maker: BigDecimal, taker: BigDecimal) -> FeeSchedule {
+        self.symbol_overrides.insert(symbol.to_string(), FeeRates { maker, taker });
+        self
+    }
+
+    pub fn rates_for(&self, symbol: &str) -> &FeeRates {
+        self.symbol_overrides.get(symbol).unwrap_or(&self.default)
+    }
+}
+
+/// One execution between an incoming (taker) order and a resting (maker) order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fill {
+    /// Shared stem of the two `sim_trades` rows written for this fill
+    pub fill_id: String,
+    pub taker_order_id: String,
+    pub maker_order_id: String,
+    pub taker_side: OrderSide,
+    /// Always the maker's price
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub taker_fee: BigDecimal,
+    pub maker_fee: BigDecimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchStatus {
+    Filled,
+    /// Partly filled with the remainder resting on the book
+    PartiallyFilled,
+    /// Nothing filled; the whole order rests on the book
+    Resting,
+    /// Market order remainder that found no liquidity
+    Expired,
+    Canceled,
+    /// Cancel for an order that is not on the book
+    NotFound,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchOutcome {
+    pub order_id: String,
+    pub status: MatchStatus,
+    pub fills: Vec<Fill>,
+    pub filled_quantity: BigDecimal,
+    /// Quantity left resting (limit) or dropped (market)
+    pub remaining_quantity: BigDecimal,
+}
+
+/// Resting order fields the matching loop needs from either sim order type
+trait RestingOrder {
+    fn order_id(&self) -> &str;
+    fn price(&self) -> &BigDecimal;
+    fn quantity(&self) -> &BigDecimal;
+    fn set_remaining(&mut self, quantity: &BigDecimal);
+}
+
+impl RestingOrder for SimOpenBuyOrder {
+    fn order_id(&self) -> &str { self.get_unique_id() }
+    fn price(&self) -> &BigDecimal { self.get_price_level() }
+    fn quantity(&self) -> &BigDecimal { self.get_quantity() }
+    fn set_remaining(&mut self, quantity: &BigDecimal) { self.set_quantity(quantity) }
+}
+
+impl RestingOrder for SimOpenSellOrder {
+    fn order_id(&self) -> &str { self.get_unique_id() }
+    fn price(&self) -> &BigDecimal { self.get_price_level() }
+    fn quantity(&self) -> &BigDecimal { self.get_quantity() }
+    fn set_remaining(&mut self, quantity: &BigDecimal) { self.set_quantity(quantity) }
+}
+
+/// A maker order touched by a match and what is left of it
+struct MakerUpdate {
+    order_id: String,
+    remaining: BigDecimal,
+}
+
+/// Walk price levels best-first, filling `quantity` against makers in time priority
+fn plan_fills<'a, O: RestingOrder + 'a>(
+    levels: impl Iterator<Item = &'a Vec<O>>,
+    crosses: impl Fn(&BigDecimal) -> bool,
+    quantity: &BigDecimal,
+) -> Vec<(MakerUpdate, BigDecimal, BigDecimal)> {
+    let mut remaining = quantity.clone();
+    let mut planned = Vec::new();
+
+    'levels: for level in levels {
+        for maker in level {
+            if remaining.is_zero() || !crosses(maker.price()) {
+                break 'levels;
+            }
+            let executed = min(remaining.clone(), maker.quantity().clone());
+            remaining -= &executed;
+            planned.push((
+                MakerUpdate { order_id: maker.order_id().to_string(), remaining: maker.quantity() - &executed },
+                maker.price().clone(),
+                executed,
+            ));
+        }
+    }
+
+    planned
+}
+
+/// Apply planned maker updates to an in-memory side of the book
+fn apply_maker_updates<K: Ord + Clone, O: RestingOrder>(book: &mut BTreeMap<K, Vec<O>>, updates: &[MakerUpdate]) {
+    for update in updates {
+        let mut emptied = None;
+        for (key, level) in book.iter_mut() {
+            if let Some(pos) = level.iter().position(|o| o.order_id() == update.order_id) {
+                if update.remaining.is_zero() {
+                    level.remove(pos);
+                } else {
+                    level[pos].set_remaining(&update.remaining);
+                }
+                if level.is_empty() {
+                    emptied = Some(key.clone());
+                }
+                break;
+            }
+        }
+        if let Some(key) = emptied {
+            book.remove(&key);
+        }
+    }
+}
+
+fn remove_order<K: Ord + Clone, O: RestingOrder>(book: &mut BTreeMap<K, Vec<O>>, order_id: &str) -> bool {
+    let mut found = None;
+    for (key, level) in book.iter_mut() {
+        if let Some(pos) = level.iter().position(|o| o.order_id() == order_id) {
+            level.remove(pos);
+            found = Some((key.clone(), level.is_empty()));
+            break;
+        }
+    }
+    match found {
+        Some((key, true)) => {
+            book.remove(&key);
+            true
+        },
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Price-time priority matching engine for one symbol on one exchange within a backtest.
+///
+/// The book is held in memory and mirrored to `sim_open_buy_orders`/`sim_open_sell_orders`
+/// through the sim order ops; fills are written to `sim_trades` as two rows each, the taker's
+/// (`matched_trader = false`) and the maker's (`matched_trader = true`), with trade ids
+/// `<fill_id>-T` and `<fill_id>-M`. Fees are returned on the `Fill`s; `sim_trades` has no
+/// fee column.
+///
+/// Each request is planned against the in-memory book, persisted, and only then applied in
+/// memory. If persisting fails part way the tables may be ahead of the book; `reload`
+/// re-reads the book from the tables.
+///
+/// Fill ids end in a per-symbol sequence number that `load` resumes after the fills already
+/// in `sim_trades`, so an engine reloaded for the same backtest does not reuse trade ids.
+pub struct SimMatchingEngine {
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    backtest_id: Uuid,
+    symbol: String,
+    exchange: String,
+    fees: FeeSchedule,
+    bids: BTreeMap<Reverse<BigDecimal>, Vec<SimOpenBuyOrder>>,
+    asks: BTreeMap<BigDecimal, Vec<SimOpenSellOrder>>,
+    fill_seq: u64,
+}
+
+impl SimMatchingEngine {
+    /// Create an engine over whatever the sim tables already hold for this backtest and symbol
+    pub async fn load(
+        pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+        backtest_id: Uuid,
+        symbol: &str,
+        exchange: &str,
+        fees: FeeSchedule,
+    ) -> Result<SimMatchingEngine, Error> {
+        let mut engine = SimMatchingEngine {
+            pool,
+            backtest_id,
+            symbol: symbol.to_string(),
+            exchange: exchange.to_string(),
+            fees,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            fill_seq: 0,
+        };
+        engine.reload().await?;
+        let recorded_fills = sim_trade_ops::count_sim_taker_trades(engine.pool.clone(), symbol, backtest_id).await?;
+        engine.fill_seq = recorded_fills as u64;
+        Ok(engine)
+    }
+
+    /// Re-read both sides of the book from the sim tables
+    pub async fn reload(&mut self) -> Result<(), Error> {
+        let mut bids = sim_open_buy_order_ops::get_sim_open_buy_orders_by_symbol(self.pool.clone(), self.backtest_id, &self.symbol).await?;
+        let mut asks = sim_open_sell_order_ops::get_sim_open_sell_orders_by_symbol(self.pool.clone(), self.backtest_id, &self.symbol).await?;
+
+        for level in bids.values_mut() {
+            level.retain(|o| o.get_exchange() == self.exchange);
+        }
+        bids.retain(|_, level| !level.is_empty());
+        for level in asks.values_mut() {
+            level.retain(|o| o.get_exchange() == self.exchange);
+        }
+        asks.retain(|_, level| !level.is_empty());
+
+        self.bids = bids;
+        self.asks = asks;
+        debug!("Loaded sim book for {} {}/{}: {} bid levels, {} ask levels",
+            self.backtest_id, self.symbol, self.exchange, self.bids.len(), self.asks.len());
+        Ok(())
+    }
+
+    pub fn bids(&self) -> &BTreeMap<Reverse<BigDecimal>, Vec<SimOpenBuyOrder>> {
+        &self.bids
+    }
+
+    pub fn asks(&self) -> &BTreeMap<BigDecimal, Vec<SimOpenSellOrder>> {
+        &self.asks
+    }
+
+    pub fn best_bid(&self) -> Option<&BigDecimal> {
+        self.bids.keys().next().map(|Reverse(p)| p)
+    }
+
+    pub fn best_ask(&self) -> Option<&BigDecimal> {
+        self.asks.keys().next()
+    }
+
+    pub fn fees(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    fn contains_order(&self, order_id: &str) -> bool {
+        self.bids.values().flatten().any(|o| o.get_unique_id() == order_id)
+            || self.asks.values().flatten().any(|o| o.get_unique_id() == order_id)
+    }
+
+    pub async fn submit(&mut self, request: MatchRequest) -> Result<MatchOutcome, Error> {
+        match request {
+            MatchRequest::Limit { order_id, side, price, quantity } => {
+                if price <= BigDecimal::zero() {
+                    return Err(anyhow::anyhow!("Limit price must be positive, got {}", price));
+                }
+                self.execute(order_id, side, Some(price), quantity).await
+            },
+            MatchRequest::Market { order_id, side, quantity } => self.execute(order_id, side, None, quantity).await,
+            MatchRequest::Cancel { order_id } => self.cancel(order_id).await,
+        }
+    }
+
+    async fn execute(
+        &mut self,
+        order_id: String,
+        side: OrderSide,
+        limit: Option<BigDecimal>,
+        quantity: BigDecimal,
+    ) -> Result<MatchOutcome, Error> {
+        if order_id.is_empty() || order_id.len() > 255 {
+            return Err(anyhow::anyhow!("Order ID must be between 1 and 255 characters"));
+        }
+        if quantity <= BigDecimal::zero() {
+            return Err(anyhow::anyhow!("Order quantity must be positive, got {}", quantity));
+        }
+        if self.contains_order(&order_id) {
+            return Err(anyhow::anyhow!("Order {} is already on the book", order_id));
+        }
+
+        let planned = match side {
+            OrderSide::Buy => plan_fills(
+                self.asks.values(),
+                |ask| limit.as_ref().is_none_or(|l| ask <= l),
+                &quantity,
+            ),
+            OrderSide::Sell => plan_fills(
+                self.bids.values(),
+                |bid| limit.as_ref().is_none_or(|l| bid >= l),
+                &quantity,
+            ),
+        };
+
+        let rates = self.fees.rates_for(&self.symbol).clone();
+        let mut fills = Vec::with_capacity(planned.len());
+        let mut trades = Vec::with_capacity(planned.len() * 2);
+        let mut filled = BigDecimal::zero();
+        for (update, price, executed) in &planned {
+            self.fill_seq += 1;
+            let fill_id = format!("{}:{}:{}", order_id, update.order_id, self.fill_seq);
+            let notional = price * executed;
+            filled += executed;
+
+            for (suffix, trade_side, matched_trader) in [("T", side, false), ("M", side.opposite(), true)] {
+                trades.push(NewSimTrade {
+                    backtest_id: self.backtest_id,
+                    trade_id: format!("{}-{}", fill_id, suffix),
+                    symbol: self.symbol.clone(),
+                    exchange: self.exchange.clone(),
+                    side: trade_side.as_str().to_string(),
+                    price: price.clone(),
+                    quantity: executed.clone(),
+                    matched_trader,
+                });
+            }
+
+            fills.push(Fill {
+                fill_id,
+                taker_order_id: order_id.clone(),
+                maker_order_id: update.order_id.clone(),
+                taker_side: side,
+                price: price.clone(),
+                quantity: executed.clone(),
+                taker_fee: &notional * &rates.taker,
+                maker_fee: &notional * &rates.maker,
+            });
+        }
+        let remaining = &quantity - &filled;
+        let updates: Vec<MakerUpdate> = planned.into_iter().map(|(u, _, _)| u).collect();
+
+        // Persist: trades, then maker updates, then the resting remainder
+        if !trades.is_empty() {
+            sim_trade_ops::create_sim_trades(self.pool.clone(), trades).await?;
+        }
+        self.persist_maker_updates(side.opposite(), &updates).await?;
+
+        let rests = limit.is_some() && !remaining.is_zero();
+        if rests {
+            let price = limit.clone().unwrap_or_default();
+            match side {
+                OrderSide::Buy => {
+                    let created = sim_open_buy_order_ops::create_sim_open_buy_order(self.pool.clone(), NewSimOpenBuyOrder {
+                        backtest_id: self.backtest_id,
+                        symbol: self.symbol.clone(),
+                        exchange: self.exchange.clone(),
+                        unique_id: order_id.clone(),
+                        price_level: price.clone(),
+                        buy_quantity: remaining.clone(),
+                        created_id: None,
+                    }).await?;
+                    apply_maker_updates(&mut self.asks, &updates);
+                    self.bids.entry(Reverse(price)).or_default().push(created);
+                },
+                OrderSide::Sell => {
+                    let created = sim_open_sell_order_ops::create_sim_open_sell_order(self.pool.clone(), NewSimOpenSellOrder {
+                        backtest_id: self.backtest_id,
+                        symbol: self.symbol.clone(),
+                        exchange: self.exchange.clone(),
+                        unique_id: order_id.clone(),
+                        price_level: price.clone(),
+                        sell_quantity: remaining.clone(),
+                        created_id: None,
+                    }).await?;
+                    apply_maker_updates(&mut self.bids, &updates);
+                    self.asks.entry(price).or_default().push(created);
+                },
+            }
+        } else {
+            match side {
+                OrderSide::Buy => apply_maker_updates(&mut self.asks, &updates),
+                OrderSide::Sell => apply_maker_updates(&mut self.bids, &updates),
+            }
+        }
+
+        let status = match (filled.is_zero(), remaining.is_zero(), rests) {
+            (_, true, _) => MatchStatus::Filled,
+            (false, false, true) => MatchStatus::PartiallyFilled,
+            (true, false, true) => MatchStatus::Resting,
+            (_, false, false) => MatchStatus::Expired,
+        };
+
+        debug!("{} {} {} {}: {:?} with {} fills", self.backtest_id, side.as_str(), quantity, order_id, status, fills.len());
+        Ok(MatchOutcome {
+            order_id,
+            status,
+            fills,
+            filled_quantity: filled,
+            remaining_quantity: remaining,
+        })
+    }
+
+    async fn persist_maker_updates(&self, maker_side: OrderSide, updates: &[MakerUpdate]) -> Result<(), Error> {
+        let filled_ids: Vec<String> = updates
+            .iter()
+            .filter(|u| u.remaining.is_zero())
+            .map(|u| u.order_id.clone())
+            .collect();
+        let partial: Vec<&MakerUpdate> = updates.iter().filter(|u| !u.remaining.is_zero()).collect();
+
+        match maker_side {
+            OrderSide::Buy => {
+                if !filled_ids.is_empty() {
+                    sim_open_buy_order_ops::delete_sim_open_buy_orders(self.pool.clone(), self.backtest_id, &filled_ids).await?;
+                }
+                for update in partial {
+                    let price = self.bids.values().flatten()
+                        .find(|o| o.get_unique_id() == update.order_id)
+                        .map(|o| o.get_price_level().clone())
+                        .unwrap_or_default();
+                    sim_open_buy_order_ops::modify_sim_open_buy_order(self.pool.clone(), self.backtest_id, &update.order_id, &price, &update.remaining).await?;
+                }
+            },
+            OrderSide::Sell => {
+                if !filled_ids.is_empty() {
+                    sim_open_sell_order_ops::delete_sim_open_sell_orders(self.pool.clone(), self.backtest_id, &filled_ids).await?;
+                }
+                for update in partial {
+                    let price = self.asks.values().flatten()
+                        .find(|o| o.get_unique_id() == update.order_id)
+                        .map(|o| o.get_price_level().clone())
+                        .unwrap_or_default();
+                    sim_open_sell_order_ops::modify_sim_open_sell_order(self.pool.clone(), self.backtest_id, &update.order_id, &price, &update.remaining).await?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    async fn cancel(&mut self, order_id: String) -> Result<MatchOutcome, Error> {
+        let bid = self.bids.values().flatten().find(|o| o.get_unique_id() == order_id).map(|o| o.get_quantity().clone());
+        let ask = self.asks.values().flatten().find(|o| o.get_unique_id() == order_id).map(|o| o.get_quantity().clone());
+
+        let remaining = match (bid, ask) {
+            (Some(quantity), _) => {
+                sim_open_buy_order_ops::delete_sim_open_buy_order(self.pool.clone(), &order_id, self.backtest_id).await?;
+                remove_order(&mut self.bids, &order_id);
+                Some(quantity)
+            },
+            (None, Some(quantity)) => {
+                sim_open_sell_order_ops::delete_sim_open_sell_order(self.pool.clone(), &order_id, self.backtest_id).await?;
+                remove_order(&mut self.asks, &order_id);
+                Some(quantity)
+            },
+            (None, None) => None,
+        };
+
+        if remaining.is_some() {
+            info!("Canceled sim order {} in backtest {}", order_id, self.backtest_id);
+        }
+        Ok(MatchOutcome {
+            status: if remaining.is_some() { MatchStatus::Canceled } else { MatchStatus::NotFound },
+            order_id,
+            fills: Vec::new(),
+            filled_quantity: BigDecimal::zero(),
+            remaining_quantity: remaining.unwrap_or_default(),
+        })
+    }
+}
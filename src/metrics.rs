@@ -0,0 +1,613 @@
+use crate::models::backtest_result::{
+    BacktestBenchmarkMetrics, BacktestEquityCurve, BacktestResultMetrics, BacktestTrade, NewBacktestBundle, NewBacktestDrawdownPeriod,
+    NewBacktestEquityCurve, NewBacktestTrade,
+};
+use crate::models::candles::Candle;
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use uuid::Uuid;
+
+const SECONDS_PER_YEAR: f64 = 365.25 * 86400.0;
+
+/// Bounds of the `DECIMAL(10, 6)` benchmark statistic columns
+const MAX_DECIMAL_10_6: f64 = 9999.999999;
+/// Bound of the `DECIMAL(10, 8)` `win_rate` column
+const MAX_DECIMAL_10_8: f64 = 99.99999999;
+/// Bound of the `DECIMAL(10, 2)` `avg_time_in_trade` column
+const MAX_DECIMAL_10_2: f64 = 99999999.99;
+/// Bound of the `DECIMAL(20, 8)` columns
+const MAX_DECIMAL_20_8: f64 = 999999999999.99999999;
+
+/// Profit factor reported when there are winning trades but no losing ones
+pub const PROFIT_FACTOR_CAP: f64 = 1000.0;
+
+pub trait EquityData {
+    fn timestamp(&self) -> DateTime<Utc>;
+    fn portfolio_value(&self) -> &BigDecimal;
+}
+
+impl EquityData for BacktestEquityCurve {
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn portfolio_value(&self) -> &BigDecimal { &self.portfolio_value }
+}
+
+impl EquityData for NewBacktestEquityCurve {
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn portfolio_value(&self) -> &BigDecimal { &self.portfolio_value }
+}
+
+pub trait TradeData {
+    fn timestamp(&self) -> DateTime<Utc>;
+    fn symbol(&self) -> &str;
+    /// `Buy` or `Sell`, compared case-insensitively
+    fn side(&self) -> &str;
+    fn quantity(&self) -> &BigDecimal;
+    fn price(&self) -> &BigDecimal;
+    fn commission(&self) -> &BigDecimal;
+}
+
+impl TradeData for BacktestTrade {
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn symbol(&self) -> &str { &self.symbol }
+    fn side(&self) -> &str { &self.side }
+    fn quantity(&self) -> &BigDecimal { &self.quantity }
+    fn price(&self) -> &BigDecimal { &self.price }
+    fn commission(&self) -> &BigDecimal { &self.commission }
+}
+
+impl TradeData for NewBacktestTrade {
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn symbol(&self) -> &str { &self.symbol }
+    fn side(&self) -> &str { &self.side }
+    fn quantity(&self) -> &BigDecimal { &self.quantity }
+    fn price(&self) -> &BigDecimal { &self.price }
+    fn commission(&self) -> &BigDecimal { &self.commission }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Return periods per year used to annualize. Inferred from the median spacing of the
+    /// equity curve when `None`.
+    pub periods_per_year: Option<f64>,
+    /// Annual risk-free rate as a fraction
+    pub risk_free_rate: f64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig { periods_per_year: None, risk_free_rate: 0.0 }
+    }
+}
+
+/// A position opened and closed again, matched FIFO per symbol from the fills
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundTrip {
+    pub symbol: String,
+    pub is_long: bool,
+    pub quantity: f64,
+    pub entry_time: DateTime<Utc>,
+    pub exit_time: DateTime<Utc>,
+    pub entry_price: f64,
+    pub exit_price: f64,
+    /// Net of the entry and exit commission attributable to `quantity`
+    pub pnl: f64,
+    /// `pnl` over the entry notional
+    pub return_pct: f64,
+}
+
+/// A fall from an equity peak. `magnitude` is the peak-to-trough loss as a positive fraction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrawdownPeriod {
+    pub peak: DateTime<Utc>,
+    pub trough: DateTime<Utc>,
+    /// First point back at or above the peak, `None` while still under water
+    pub recovery: Option<DateTime<Utc>>,
+    pub magnitude: f64,
+    /// Peak to recovery, or to the last equity point when unrecovered; at least one day
+    pub duration_days: i32,
+}
+
+impl DrawdownPeriod {
+    pub fn to_new(&self, backtest_result_id: Uuid) -> NewBacktestDrawdownPeriod {
+        NewBacktestDrawdownPeriod {
+            backtest_result_id,
+            start_date: self.peak,
+            end_date: self.trough,
+            duration_days: self.duration_days,
+            magnitude: to_decimal(self.magnitude, 8, MAX_DECIMAL_20_8),
+            recovery_date: self.recovery,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestMetrics {
+    pub columns: BacktestResultMetrics,
+    pub drawdown_periods: Vec<DrawdownPeriod>,
+    pub round_trips: Vec<RoundTrip>,
+    pub periods_per_year: f64,
+}
+
+impl BacktestMetrics {
+    pub fn drawdown_rows(&self, backtest_result_id: Uuid) -> Vec<NewBacktestDrawdownPeriod> {
+        self.drawdown_periods.iter().map(|d| d.to_new(backtest_result_id)).collect()
+    }
+}
+
+/// Compute every derived `backtest_results` metric from the equity curve, the fills and an
+/// optional benchmark candle series, so results from different engines are comparable.
+///
+/// Returns, ratios and drawdowns are fractions (0.05 = 5%). VaR and expected shortfall are
+/// per-period historical figures at 95%, reported as positive losses. Sharpe, Sortino,
+/// volatility, tracking error, information ratio and Jensen's alpha are annualized with
+/// `periods_per_year`. Benchmark closes are aligned to the equity timestamps by taking the
+/// last candle at or before each point. Equity points must be positive; others are ignored.
+pub fn compute_backtest_metrics<E: EquityData, T: TradeData>(
+    equity: &[E],
+    trades: &[T],
+    benchmark: Option<&[Candle]>,
+    config: &MetricsConfig,
+) -> BacktestMetrics {
+    let curve = equity_points(equity);
+
+    let returns: Vec<f64> = curve.windows(2).map(|w| w[1].1 / w[0].1 - 1.0).collect();
+    let periods_per_year = config.periods_per_year.filter(|p| *p > 0.0).unwrap_or_else(|| infer_periods_per_year(&curve));
+    let annualizer = periods_per_year.sqrt();
+    let rf_period = (1.0 + config.risk_free_rate).powf(1.0 / periods_per_year) - 1.0;
+
+    let total_return = match (curve.first(), curve.last()) {
+        (Some(first), Some(last)) => last.1 / first.1 - 1.0,
+        _ => 0.0,
+    };
+    let years = match (curve.first(), curve.last()) {
+        (Some(first), Some(last)) => (last.0 - first.0).num_seconds() as f64 / SECONDS_PER_YEAR,
+        _ => 0.0,
+    };
+    let annualized_return = if years > 0.0 { (1.0 + total_return).powf(1.0 / years) - 1.0 } else { total_return };
+
+    let excess: Vec<f64> = returns.iter().map(|r| r - rf_period).collect();
+    let volatility = std_dev(&returns).map(|s| s * annualizer).unwrap_or(0.0);
+    let sharpe_ratio = match (mean(&excess), std_dev(&excess)) {
+        (Some(m), Some(s)) if s > 0.0 => Some(m / s * annualizer),
+        _ => None,
+    };
+    let downside = if excess.is_empty() {
+        None
+    } else {
+        Some((excess.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / excess.len() as f64).sqrt())
+    };
+    let sortino_ratio = match (mean(&excess), downside) {
+        (Some(m), Some(d)) if d > 0.0 => Some(m / d * annualizer),
+        _ => None,
+    };
+
+    let drawdown_periods = find_drawdown_periods(&curve);
+    let max_drawdown = drawdown_periods.iter().map(|d| d.magnitude).fold(0.0, f64::max);
+    let calmar_ratio = if max_drawdown > 0.0 { Some(annualized_return / max_drawdown) } else { None };
+    let max_drawdown_duration_days = drawdown_periods.iter().map(|d| d.duration_days).max();
+    let avg_drawdown = mean(&drawdown_periods.iter().map(|d| d.magnitude).collect::<Vec<_>>());
+    let current_drawdown = match curve.last() {
+        Some(last) => {
+            let peak = curve.iter().map(|(_, v)| *v).fold(f64::MIN, f64::max);
+            1.0 - last.1 / peak
+        }
+        None => 0.0,
+    };
+
+    let (value_at_risk_95, expected_shortfall_95) = historical_var_es(&returns, 0.95);
+
+    let round_trips = match_round_trips(trades);
+    let trip_returns: Vec<f64> = round_trips.iter().map(|t| t.return_pct).collect();
+    let wins = round_trips.iter().filter(|t| t.pnl > 0.0).count();
+    let win_rate = if round_trips.is_empty() { 0.0 } else { wins as f64 / round_trips.len() as f64 };
+    let gross_profit: f64 = round_trips.iter().filter(|t| t.pnl > 0.0).map(|t| t.pnl).sum();
+    let gross_loss: f64 = round_trips.iter().filter(|t| t.pnl < 0.0).map(|t| -t.pnl).sum();
+    let profit_factor = if gross_loss > 0.0 {
+        gross_profit / gross_loss
+    } else if gross_profit > 0.0 {
+        PROFIT_FACTOR_CAP
+    } else {
+        0.0
+    };
+    let avg_time_in_trade = mean(
+        &round_trips
+            .iter()
+            .map(|t| (t.exit_time - t.entry_time).num_seconds() as f64 / 86400.0)
+            .collect::<Vec<_>>(),
+    );
+    let total_commission_paid: f64 = trades.iter().filter_map(|t| t.commission().to_f64()).sum();
+
+    let bench = benchmark
+        .and_then(|candles| {
+            let pairs: Vec<(f64, f64)> = paired_returns(&curve, candles).iter().map(|(_, s, b)| (*s, *b)).collect();
+            pair_statistics(&pairs, rf_period, periods_per_year)
+        })
+        .map(|b| b.to_columns())
+        .unwrap_or_else(BacktestBenchmarkMetrics::empty);
+
+    let columns = BacktestResultMetrics {
+        total_return: to_decimal(total_return, 8, MAX_DECIMAL_20_8),
+        annualized_return: to_decimal(annualized_return, 8, MAX_DECIMAL_20_8),
+        volatility: to_decimal(volatility, 8, MAX_DECIMAL_20_8),
+        sharpe_ratio: sharpe_ratio.map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        sortino_ratio: sortino_ratio.map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        max_drawdown: to_decimal(max_drawdown, 8, MAX_DECIMAL_20_8),
+        calmar_ratio: calmar_ratio.map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        win_rate: to_decimal(win_rate, 8, MAX_DECIMAL_10_8),
+        profit_factor: to_decimal(profit_factor, 8, MAX_DECIMAL_20_8),
+        avg_trade_return: to_decimal(mean(&trip_returns).unwrap_or(0.0), 8, MAX_DECIMAL_20_8),
+        total_trades: round_trips.len().min(i32::MAX as usize) as i32,
+        best_trade: trip_returns.iter().copied().reduce(f64::max).map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        worst_trade: trip_returns.iter().copied().reduce(f64::min).map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        avg_time_in_trade: avg_time_in_trade.map(|v| to_decimal(v, 2, MAX_DECIMAL_10_2)),
+        value_at_risk_95: value_at_risk_95.map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        expected_shortfall_95: expected_shortfall_95.map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        beta: bench.beta,
+        correlation_with_benchmark: bench.correlation_with_benchmark,
+        tracking_error: bench.tracking_error,
+        information_ratio: bench.information_ratio,
+        jensen_alpha: bench.jensen_alpha,
+        max_drawdown_duration_days,
+        current_drawdown: to_decimal(current_drawdown, 8, MAX_DECIMAL_20_8),
+        avg_drawdown: avg_drawdown.map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+        benchmark_return: bench.benchmark_return,
+        excess_return: bench.excess_return,
+        outperformance_periods: bench.outperformance_periods,
+        underperformance_periods: bench.underperformance_periods,
+        total_commission_paid: to_decimal(total_commission_paid, 8, MAX_DECIMAL_20_8),
+    };
+
+    BacktestMetrics { columns, drawdown_periods, round_trips, periods_per_year }
+}
+
+/// Fill the bundle's metric columns and replace its drawdown periods with the ones derived
+/// from its equity curve. The drawdown rows carry a nil `backtest_result_id`, which
+/// `save_full_backtest` overwrites.
+pub fn fill_bundle_metrics(bundle: &mut NewBacktestBundle, benchmark: Option<&[Candle]>, config: &MetricsConfig) -> BacktestMetrics {
+    let metrics = compute_backtest_metrics(&bundle.equity_curve, &bundle.trades, benchmark, config);
+    metrics.columns.apply_to(&mut bundle.result);
+    bundle.drawdown_periods = metrics.drawdown_rows(Uuid::nil());
+    metrics
+}
+
+/// Split peaks, troughs and recoveries out of a time-sorted equity curve
+pub fn find_drawdown_periods(curve: &[(DateTime<Utc>, f64)]) -> Vec<DrawdownPeriod> {
+    let mut periods = Vec::new();
+    let Some(&(first_ts, first_value)) = curve.first() else {
+        return periods;
+    };
+    let last_ts = curve[curve.len() - 1].0;
+
+    let (mut peak_ts, mut peak) = (first_ts, first_value);
+    // (trough timestamp, trough value) of the drawdown in progress
+    let mut trough: Option<(DateTime<Utc>, f64)> = None;
+
+    for &(ts, value) in &curve[1..] {
+        if value >= peak {
+            if let Some((trough_ts, trough_value)) = trough.take() {
+                periods.push(drawdown_period(peak_ts, peak, trough_ts, trough_value, Some(ts), ts));
+            }
+            peak_ts = ts;
+            peak = value;
+        } else {
+            match trough {
+                Some((_, trough_value)) if value >= trough_value => {}
+                _ => trough = Some((ts, value)),
+            }
+        }
+    }
+    if let Some((trough_ts, trough_value)) = trough {
+        periods.push(drawdown_period(peak_ts, peak, trough_ts, trough_value, None, last_ts));
+    }
+    periods
+}
+
+fn drawdown_period(
+    peak_ts: DateTime<Utc>,
+    peak: f64,
+    trough_ts: DateTime<Utc>,
+    trough: f64,
+    recovery: Option<DateTime<Utc>>,
+    until: DateTime<Utc>,
+) -> DrawdownPeriod {
+    let seconds = (until - peak_ts).num_seconds().max(0) as f64;
+    DrawdownPeriod {
+        peak: peak_ts,
+        trough: trough_ts,
+        recovery,
+        magnitude: 1.0 - trough / peak,
+        duration_days: ((seconds / 86400.0).ceil() as i32).max(1),
+    }
+}
+
+/// Pair fills into round trips FIFO per symbol. A fill larger than the open position
+/// closes it and opens the remainder in the other direction. Positions still open at the
+/// end are not counted.
+pub fn match_round_trips<T: TradeData>(trades: &[T]) -> Vec<RoundTrip> {
+    struct Lot {
+        time: DateTime<Utc>,
+        quantity: f64,
+        price: f64,
+        commission_per_unit: f64,
+    }
+
+    let mut sorted: Vec<&T> = trades.iter().collect();
+    sorted.sort_by_key(|t| t.timestamp());
+
+    // Open lots per symbol; all lots of a symbol share one direction
+    let mut books: HashMap<&str, (bool, VecDeque<Lot>)> = HashMap::new();
+    let mut trips = Vec::new();
+
+    for fill in sorted {
+        let (Some(mut quantity), Some(price)) = (fill.quantity().to_f64(), fill.price().to_f64()) else {
+            continue;
+        };
+        if quantity <= 0.0 || price <= 0.0 {
+            continue;
+        }
+        let is_buy = fill.side().eq_ignore_ascii_case("buy");
+        let commission_per_unit = fill.commission().to_f64().unwrap_or(0.0) / quantity;
+        let (is_long, lots) = books.entry(fill.symbol()).or_insert((is_buy, VecDeque::new()));
+
+        if lots.is_empty() {
+            *is_long = is_buy;
+        }
+        if *is_long != is_buy {
+            while quantity > 0.0 {
+                let Some(lot) = lots.front_mut() else { break };
+                let closed = lot.quantity.min(quantity);
+                let direction = if *is_long { 1.0 } else { -1.0 };
+                let pnl = (price - lot.price) * closed * direction - (lot.commission_per_unit + commission_per_unit) * closed;
+                trips.push(RoundTrip {
+                    symbol: fill.symbol().to_string(),
+                    is_long: *is_long,
+                    quantity: closed,
+                    entry_time: lot.time,
+                    exit_time: fill.timestamp(),
+                    entry_price: lot.price,
+                    exit_price: price,
+                    pnl,
+                    return_pct: pnl / (lot.price * closed),
+                });
+                lot.quantity -= closed;
+                quantity -= closed;
+                if lot.quantity <= f64::EPSILON {
+                    lots.pop_front();
+                }
+            }
+            if quantity <= f64::EPSILON {
+                continue;
+            }
+            *is_long = is_buy;
+        }
+        lots.push_back(Lot { time: fill.timestamp(), quantity, price, commission_per_unit });
+    }
+    trips
+}
+
+/// Benchmark-relative statistics over the span where both the strategy and the benchmark
+/// are priced. Tracking error, information ratio and Jensen's alpha are annualized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkStatistics {
+    pub strategy_return: f64,
+    pub benchmark_return: f64,
+    /// `strategy_return - benchmark_return`
+    pub excess_return: f64,
+    pub beta: Option<f64>,
+    pub correlation: Option<f64>,
+    pub tracking_error: Option<f64>,
+    pub information_ratio: Option<f64>,
+    pub jensen_alpha: Option<f64>,
+    pub outperformance_periods: i32,
+    pub underperformance_periods: i32,
+}
+
+impl BenchmarkStatistics {
+    pub fn to_columns(&self) -> BacktestBenchmarkMetrics {
+        BacktestBenchmarkMetrics {
+            beta: self.beta.map(|v| to_decimal(v, 6, MAX_DECIMAL_10_6)),
+            correlation_with_benchmark: self.correlation.map(|v| to_decimal(v, 6, MAX_DECIMAL_10_6)),
+            tracking_error: self.tracking_error.map(|v| to_decimal(v, 8, MAX_DECIMAL_20_8)),
+            information_ratio: self.information_ratio.map(|v| to_decimal(v, 6, MAX_DECIMAL_10_6)),
+            jensen_alpha: self.jensen_alpha.map(|v| to_decimal(v, 6, MAX_DECIMAL_10_6)),
+            benchmark_return: Some(to_decimal(self.benchmark_return, 8, MAX_DECIMAL_20_8)),
+            excess_return: Some(to_decimal(self.excess_return, 8, MAX_DECIMAL_20_8)),
+            outperformance_periods: Some(self.outperformance_periods),
+            underperformance_periods: Some(self.underperformance_periods),
+        }
+    }
+}
+
+/// `BenchmarkStatistics` over the `window` return periods ending at `timestamp`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollingBenchmarkPoint {
+    pub timestamp: DateTime<Utc>,
+    pub statistics: BenchmarkStatistics,
+}
+
+/// Benchmark-relative statistics of an equity curve against benchmark candles
+pub fn benchmark_statistics<E: EquityData>(equity: &[E], candles: &[Candle], config: &MetricsConfig) -> Option<BenchmarkStatistics> {
+    let curve = equity_points(equity);
+    let periods_per_year = config.periods_per_year.filter(|p| *p > 0.0).unwrap_or_else(|| infer_periods_per_year(&curve));
+    let rf_period = (1.0 + config.risk_free_rate).powf(1.0 / periods_per_year) - 1.0;
+    let pairs = paired_returns(&curve, candles);
+    pair_statistics(&pairs.iter().map(|(_, s, b)| (*s, *b)).collect::<Vec<_>>(), rf_period, periods_per_year)
+}
+
+/// Benchmark-relative statistics over a sliding window of `window` return periods, one point
+/// per period once the window is full, for charting rolling beta, correlation and excess return
+pub fn rolling_benchmark_statistics<E: EquityData>(
+    equity: &[E],
+    candles: &[Candle],
+    window: usize,
+    config: &MetricsConfig,
+) -> Vec<RollingBenchmarkPoint> {
+    let curve = equity_points(equity);
+    let periods_per_year = config.periods_per_year.filter(|p| *p > 0.0).unwrap_or_else(|| infer_periods_per_year(&curve));
+    let rf_period = (1.0 + config.risk_free_rate).powf(1.0 / periods_per_year) - 1.0;
+    let pairs = paired_returns(&curve, candles);
+    let returns: Vec<(f64, f64)> = pairs.iter().map(|(_, s, b)| (*s, *b)).collect();
+    let window = window.max(2);
+
+    if returns.len() < window {
+        return Vec::new();
+    }
+    (window..=returns.len())
+        .filter_map(|end| {
+            pair_statistics(&returns[end - window..end], rf_period, periods_per_year)
+                .map(|statistics| RollingBenchmarkPoint { timestamp: pairs[end - 1].0, statistics })
+        })
+        .collect()
+}
+
+/// `(period end, strategy return, benchmark return)` for each interval where both are priced
+fn paired_returns(curve: &[(DateTime<Utc>, f64)], candles: &[Candle]) -> Vec<(DateTime<Utc>, f64, f64)> {
+    let aligned = align_benchmark(curve, candles);
+    curve
+        .windows(2)
+        .zip(aligned.windows(2))
+        .filter_map(|(c, a)| match (a[0], a[1]) {
+            ((v0, Some(b0)), (v1, Some(b1))) => Some((c[1].0, v1 / v0 - 1.0, b1 / b0 - 1.0)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn pair_statistics(pairs: &[(f64, f64)], rf_period: f64, periods_per_year: f64) -> Option<BenchmarkStatistics> {
+    if pairs.is_empty() {
+        return None;
+    }
+    let strategy: Vec<f64> = pairs.iter().map(|(s, _)| *s).collect();
+    let bench: Vec<f64> = pairs.iter().map(|(_, b)| *b).collect();
+    let active: Vec<f64> = pairs.iter().map(|(s, b)| s - b).collect();
+
+    let strategy_return = strategy.iter().fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0;
+    let benchmark_return = bench.iter().fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0;
+
+    let covariance = covariance(&strategy, &bench);
+    let beta = match (covariance, covariance_self(&bench)) {
+        (Some(c), Some(v)) if v > 0.0 => Some(c / v),
+        _ => None,
+    };
+    let correlation = match (covariance, std_dev(&strategy), std_dev(&bench)) {
+        (Some(c), Some(s), Some(b)) if s > 0.0 && b > 0.0 => Some(c / (s * b)),
+        _ => None,
+    };
+    let tracking_error = std_dev(&active).map(|s| s * periods_per_year.sqrt());
+    let information_ratio = match (mean(&active), tracking_error) {
+        (Some(m), Some(te)) if te > 0.0 => Some(m * periods_per_year / te),
+        _ => None,
+    };
+    let jensen_alpha = match (mean(&strategy), mean(&bench), beta) {
+        (Some(s), Some(b), Some(beta)) => Some(((s - rf_period) - beta * (b - rf_period)) * periods_per_year),
+        _ => None,
+    };
+
+    Some(BenchmarkStatistics {
+        strategy_return,
+        benchmark_return,
+        excess_return: strategy_return - benchmark_return,
+        beta,
+        correlation,
+        tracking_error,
+        information_ratio,
+        jensen_alpha,
+        outperformance_periods: pairs.iter().filter(|(s, b)| s > b).count() as i32,
+        underperformance_periods: pairs.iter().filter(|(s, b)| s < b).count() as i32,
+    })
+}
+
+/// Pair each equity value with the last benchmark close at or before its timestamp
+pub fn align_benchmark(curve: &[(DateTime<Utc>, f64)], candles: &[Candle]) -> Vec<(f64, Option<f64>)> {
+    let mut closes: Vec<(DateTime<Utc>, f64)> = candles
+        .iter()
+        .filter_map(|c| c.close_price.to_f64().filter(|p| *p > 0.0).map(|p| (c.timestamp, p)))
+        .collect();
+    closes.sort_by_key(|(ts, _)| *ts);
+
+    curve
+        .iter()
+        .map(|(ts, value)| {
+            let idx = closes.partition_point(|(c_ts, _)| c_ts <= ts);
+            (*value, if idx == 0 { None } else { Some(closes[idx - 1].1) })
+        })
+        .collect()
+}
+
+/// Positive equity points sorted by timestamp, one per timestamp
+pub fn equity_points<E: EquityData>(equity: &[E]) -> Vec<(DateTime<Utc>, f64)> {
+    let mut curve: Vec<(DateTime<Utc>, f64)> = equity
+        .iter()
+        .filter_map(|p| p.portfolio_value().to_f64().filter(|v| *v > 0.0).map(|v| (p.timestamp(), v)))
+        .collect();
+    curve.sort_by_key(|(ts, _)| *ts);
+    curve.dedup_by_key(|(ts, _)| *ts);
+    curve
+}
+
+/// Median spacing between consecutive points of the curve in seconds, `None` with fewer
+/// than two points
+pub fn median_spacing_seconds(curve: &[(DateTime<Utc>, f64)]) -> Option<i64> {
+    let mut gaps: Vec<i64> = curve.windows(2).map(|w| (w[1].0 - w[0].0).num_seconds()).filter(|g| *g > 0).collect();
+    if gaps.is_empty() {
+        return None;
+    }
+    gaps.sort_unstable();
+    Some(gaps[gaps.len() / 2])
+}
+
+/// Median spacing of the curve converted to periods per year; 252 when it cannot be told
+fn infer_periods_per_year(curve: &[(DateTime<Utc>, f64)]) -> f64 {
+    median_spacing_seconds(curve).map(|gap| SECONDS_PER_YEAR / gap as f64).unwrap_or(252.0)
+}
+
+/// Historical VaR and expected shortfall at `confidence`, as positive per-period losses
+fn historical_var_es(returns: &[f64], confidence: f64) -> (Option<f64>, Option<f64>) {
+    if returns.is_empty() {
+        return (None, None);
+    }
+    let mut sorted = returns.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let cutoff = (((1.0 - confidence) * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    let var = -sorted[cutoff - 1];
+    let es = -(sorted[..cutoff].iter().sum::<f64>() / cutoff as f64);
+    (Some(var), Some(es))
+}
+
+pub(crate) fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+/// Sample standard deviation
+pub(crate) fn std_dev(values: &[f64]) -> Option<f64> {
+    covariance_self(values).map(f64::sqrt)
+}
+
+fn covariance_self(values: &[f64]) -> Option<f64> {
+    covariance(values, values)
+}
+
+/// Sample covariance of two equally long series
+pub(crate) fn covariance(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < 2 || a.len() != b.len() {
+        return None;
+    }
+    let (mean_a, mean_b) = (mean(a)?, mean(b)?);
+    Some(a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum::<f64>() / (a.len() - 1) as f64)
+}
+
+/// Round to the column's scale and clamp to its range; non-finite values become zero
+fn to_decimal(value: f64, scale: i64, max_abs: f64) -> BigDecimal {
+    if !value.is_finite() {
+        return BigDecimal::from(0);
+    }
+    BigDecimal::from_f64(value.clamp(-max_abs, max_abs))
+        .map(|d| d.round(scale))
+        .unwrap_or_else(|| BigDecimal::from(0))
+}
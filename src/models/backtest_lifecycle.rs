@@ -0,0 +1,122 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Timestamptz, Uuid as SqlUuid};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::backtest_tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct BacktestTag {
+    pub backtest_id: Uuid,
+    pub tag: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::backtest_tags)]
+pub struct NewBacktestTag {
+    pub backtest_id: Uuid,
+    pub tag: String,
+}
+
+/// Rows removed by `purge_backtest`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BacktestPurgeReport {
+    pub backtest_id: Uuid,
+    pub sim_buy_orders: usize,
+    pub sim_sell_orders: usize,
+    pub sim_trades: usize,
+    /// Reports whose files were deleted from storage before their rows. Reports kept in
+    /// another storage backend are not counted.
+    pub reports: usize,
+    /// Whether the `backtest_results` row (and with it trades, equity curve, positions,
+    /// drawdown periods and reports) was deleted
+    pub results_deleted: bool,
+    pub tags: usize,
+}
+
+/// Which backtests `collect_backtest_garbage` purges. At least one of `older_than` and
+/// `tags` must be set so an empty policy cannot wipe every backtest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestGcPolicy {
+    /// Purge backtests with no sim rows or result updates newer than this
+    pub older_than: Option<Duration>,
+    /// Purge only backtests carrying at least one of these tags
+    pub tags: Vec<String>,
+    /// Never purge backtests carrying any of these tags
+    pub protected_tags: Vec<String>,
+    /// Keep `backtest_results` and their child rows, removing only sim scratch rows. Backtests
+    /// with nothing but results left are then no longer candidates.
+    pub keep_results: bool,
+    /// Report the candidates without deleting anything
+    pub dry_run: bool,
+    /// Upper bound on backtests purged per call, oldest first
+    pub max_backtests: i64,
+}
+
+impl Default for BacktestGcPolicy {
+    fn default() -> Self {
+        BacktestGcPolicy {
+            older_than: None,
+            tags: Vec::new(),
+            protected_tags: vec!["pinned".to_string()],
+            keep_results: true,
+            dry_run: false,
+            max_backtests: 100,
+        }
+    }
+}
+
+impl BacktestGcPolicy {
+    pub fn older_than(age: Duration) -> BacktestGcPolicy {
+        BacktestGcPolicy {
+            older_than: Some(age),
+            ..BacktestGcPolicy::default()
+        }
+    }
+
+    pub fn tagged(tags: &[&str]) -> BacktestGcPolicy {
+        BacktestGcPolicy {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..BacktestGcPolicy::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, QueryableByName, Serialize, Deserialize)]
+pub struct BacktestGcCandidate {
+    #[diesel(sql_type = SqlUuid)]
+    pub backtest_id: Uuid,
+    #[diesel(sql_type = Timestamptz)]
+    pub last_activity: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BacktestGcReport {
+    pub candidates: Vec<BacktestGcCandidate>,
+    /// Empty on a dry run
+    pub purged: Vec<BacktestPurgeReport>,
+}
+
+/// Row counts and on-disk row sizes per backtest. Byte figures are `pg_column_size` of the
+/// rows as read, i.e. before TimescaleDB compression.
+#[derive(Debug, Clone, QueryableByName, Serialize, Deserialize)]
+pub struct BacktestStorageReport {
+    #[diesel(sql_type = SqlUuid)]
+    pub backtest_id: Uuid,
+    #[diesel(sql_type = BigInt)]
+    pub sim_buy_orders: i64,
+    #[diesel(sql_type = BigInt)]
+    pub sim_sell_orders: i64,
+    #[diesel(sql_type = BigInt)]
+    pub sim_trades: i64,
+    #[diesel(sql_type = BigInt)]
+    pub sim_bytes: i64,
+    #[diesel(sql_type = BigInt)]
+    pub result_rows: i64,
+    #[diesel(sql_type = BigInt)]
+    pub result_bytes: i64,
+    #[diesel(sql_type = BigInt)]
+    pub total_bytes: i64,
+}
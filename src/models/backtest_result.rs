@@ -66,8 +66,9 @@ pub struct BacktestResult {
     pub strategy_instance_id: Option<Uuid>,
 }
 
-#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Insertable, AsChangeset, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::backtest_results)]
+#[diesel(treat_none_as_null = true)]
 pub struct NewBacktestResult {
     pub backtest_id: Uuid,
     pub strategy_name: String,
@@ -127,7 +128,7 @@ pub struct NewBacktestResult {
     pub strategy_instance_id: Option<Uuid>,
 }
 
-#[derive(Debug, Clone, Queryable, Identifiable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::backtest_trades)]
 pub struct BacktestTrade {
     pub id: Uuid,
@@ -159,7 +160,7 @@ pub struct NewBacktestTrade {
     pub metadata: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Queryable, Identifiable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::backtest_equity_curve)]
 pub struct BacktestEquityCurve {
     pub id: Uuid,
@@ -177,7 +178,7 @@ pub struct NewBacktestEquityCurve {
     pub portfolio_value: BigDecimal,
 }
 
-#[derive(Debug, Clone, Queryable, Identifiable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::backtest_position_history)]
 pub struct BacktestPositionHistory {
     pub id: Uuid,
@@ -205,7 +206,7 @@ pub struct NewBacktestPositionHistory {
     pub direction: String,
 }
 
-#[derive(Debug, Clone, Queryable, Identifiable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::backtest_drawdown_periods)]
 pub struct BacktestDrawdownPeriod {
     pub id: Uuid,
@@ -229,8 +230,169 @@ pub struct NewBacktestDrawdownPeriod {
     pub recovery_date: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone, Queryable, Identifiable, Serialize, Deserialize)]
+/// The `backtest_results` columns derived by `metrics::compute_backtest_metrics` from the
+/// equity curve, trades and benchmark. Configuration and order statistics are left alone.
+#[derive(Debug, Clone, AsChangeset, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::backtest_results)]
+#[diesel(treat_none_as_null = true)]
+pub struct BacktestResultMetrics {
+    pub total_return: BigDecimal,
+    pub annualized_return: BigDecimal,
+    pub volatility: BigDecimal,
+    pub sharpe_ratio: Option<BigDecimal>,
+    pub sortino_ratio: Option<BigDecimal>,
+    pub max_drawdown: BigDecimal,
+    pub calmar_ratio: Option<BigDecimal>,
+    pub win_rate: BigDecimal,
+    pub profit_factor: BigDecimal,
+    pub avg_trade_return: BigDecimal,
+    pub total_trades: i32,
+    pub best_trade: Option<BigDecimal>,
+    pub worst_trade: Option<BigDecimal>,
+    pub avg_time_in_trade: Option<BigDecimal>,
+    pub value_at_risk_95: Option<BigDecimal>,
+    pub expected_shortfall_95: Option<BigDecimal>,
+    pub beta: Option<BigDecimal>,
+    pub correlation_with_benchmark: Option<BigDecimal>,
+    pub tracking_error: Option<BigDecimal>,
+    pub information_ratio: Option<BigDecimal>,
+    pub jensen_alpha: Option<BigDecimal>,
+    pub max_drawdown_duration_days: Option<i32>,
+    pub current_drawdown: BigDecimal,
+    pub avg_drawdown: Option<BigDecimal>,
+    pub benchmark_return: Option<BigDecimal>,
+    pub excess_return: Option<BigDecimal>,
+    pub outperformance_periods: Option<i32>,
+    pub underperformance_periods: Option<i32>,
+    pub total_commission_paid: BigDecimal,
+}
+
+impl BacktestResultMetrics {
+    /// Copy the metrics into a result that has not been stored yet
+    pub fn apply_to(&self, result: &mut NewBacktestResult) {
+        result.total_return = self.total_return.clone();
+        result.annualized_return = self.annualized_return.clone();
+        result.volatility = self.volatility.clone();
+        result.sharpe_ratio = self.sharpe_ratio.clone();
+        result.sortino_ratio = self.sortino_ratio.clone();
+        result.max_drawdown = self.max_drawdown.clone();
+        result.calmar_ratio = self.calmar_ratio.clone();
+        result.win_rate = self.win_rate.clone();
+        result.profit_factor = self.profit_factor.clone();
+        result.avg_trade_return = self.avg_trade_return.clone();
+        result.total_trades = self.total_trades;
+        result.best_trade = self.best_trade.clone();
+        result.worst_trade = self.worst_trade.clone();
+        result.avg_time_in_trade = self.avg_time_in_trade.clone();
+        result.value_at_risk_95 = self.value_at_risk_95.clone();
+        result.expected_shortfall_95 = self.expected_shortfall_95.clone();
+        result.beta = self.beta.clone();
+        result.correlation_with_benchmark = self.correlation_with_benchmark.clone();
+        result.tracking_error = self.tracking_error.clone();
+        result.information_ratio = self.information_ratio.clone();
+        result.jensen_alpha = self.jensen_alpha.clone();
+        result.max_drawdown_duration_days = self.max_drawdown_duration_days;
+        result.current_drawdown = self.current_drawdown.clone();
+        result.avg_drawdown = self.avg_drawdown.clone();
+        result.benchmark_return = self.benchmark_return.clone();
+        result.excess_return = self.excess_return.clone();
+        result.outperformance_periods = self.outperformance_periods;
+        result.underperformance_periods = self.underperformance_periods;
+        result.total_commission_paid = self.total_commission_paid.clone();
+    }
+}
+
+/// The benchmark-relative `backtest_results` columns, written by
+/// `benchmark_ops::compute_benchmark_analytics`
+#[derive(Debug, Clone, AsChangeset, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::backtest_results)]
+#[diesel(treat_none_as_null = true)]
+pub struct BacktestBenchmarkMetrics {
+    pub beta: Option<BigDecimal>,
+    pub correlation_with_benchmark: Option<BigDecimal>,
+    pub tracking_error: Option<BigDecimal>,
+    pub information_ratio: Option<BigDecimal>,
+    pub jensen_alpha: Option<BigDecimal>,
+    pub benchmark_return: Option<BigDecimal>,
+    pub excess_return: Option<BigDecimal>,
+    pub outperformance_periods: Option<i32>,
+    pub underperformance_periods: Option<i32>,
+}
+
+impl BacktestBenchmarkMetrics {
+    /// All NULL, for results without a benchmark
+    pub fn empty() -> BacktestBenchmarkMetrics {
+        BacktestBenchmarkMetrics {
+            beta: None,
+            correlation_with_benchmark: None,
+            tracking_error: None,
+            information_ratio: None,
+            jensen_alpha: None,
+            benchmark_return: None,
+            excess_return: None,
+            outperformance_periods: None,
+            underperformance_periods: None,
+        }
+    }
+}
+
+/// What to do when a result with the same `backtest_id` is already stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BacktestWriteMode {
+    /// Overwrite every metric column and replace the child trade, equity curve, position
+    /// and drawdown rows. `created_at` of the stored row is kept.
+    #[default]
+    Replace,
+    /// Return `DatabaseError::DuplicateEntry` and leave the stored result untouched
+    FailIfExists,
+    /// Return the stored result unchanged and write nothing
+    KeepExisting,
+}
+
+/// Which write actually happened for a given `BacktestWriteMode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BacktestWriteOutcome {
+    /// No result with that `backtest_id` existed
+    Inserted,
+    /// An existing result and its child rows were overwritten
+    Replaced,
+    /// An existing result was returned as is
+    KeptExisting,
+}
+
+/// Result of `create_backtest_result`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredBacktestResult {
+    pub result: BacktestResult,
+    pub outcome: BacktestWriteOutcome,
+}
+
+/// A backtest result together with all of its child rows, for `save_full_backtest`.
+/// The children's `backtest_result_id` is overwritten with the id of the stored result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewBacktestBundle {
+    pub result: NewBacktestResult,
+    pub trades: Vec<NewBacktestTrade>,
+    pub equity_curve: Vec<NewBacktestEquityCurve>,
+    pub positions: Vec<NewBacktestPositionHistory>,
+    pub drawdown_periods: Vec<NewBacktestDrawdownPeriod>,
+}
+
+/// Row counts written by `save_full_backtest`. All counts are zero when the outcome is
+/// `KeptExisting`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedBacktest {
+    pub result: BacktestResult,
+    pub outcome: BacktestWriteOutcome,
+    pub trades: usize,
+    pub equity_points: usize,
+    pub positions: usize,
+    pub drawdown_periods: usize,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::backtest_reports)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct BacktestReport {
     pub id: Uuid,
     pub backtest_result_id: Uuid,
@@ -249,7 +411,7 @@ pub struct BacktestReport {
     pub data_points: Option<i32>,
     pub include_trades: bool,
     pub include_charts: bool,
-    pub export_formats: Option<Vec<Option<String>>>,
+    pub export_formats: Vec<Option<String>>,
     pub custom_css: Option<String>,
     pub template_version: Option<String>,
     pub file_paths: serde_json::Value,
@@ -280,6 +442,7 @@ pub struct NewBacktestReport {
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
     pub initial_capital: BigDecimal,
+    pub generated_at: DateTime<Utc>,
     pub generated_by: Option<String>,
     pub generation_source: String,
     pub backtest_duration_seconds: Option<BigDecimal>,
@@ -330,3 +493,315 @@ pub struct NewBacktestReportAccessLog {
     pub success: bool,
     pub error_message: Option<String>,
 }
+
+/// File formats `generate_backtest_report` can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ReportFormat {
+    /// Self-contained page with summary tables and, optionally, an SVG equity chart
+    Html,
+    /// The stored result, summaries and child rows as one document
+    Json,
+    /// Summary, equity curve and drawdown sections, plus trades when included
+    Csv,
+}
+
+impl ReportFormat {
+    /// Value stored in `export_formats` and used as the `file_paths` key
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.name()
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "text/html; charset=utf-8",
+            ReportFormat::Json => "application/json",
+            ReportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+/// What `generate_backtest_report` renders and how the report row is labelled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportOptions {
+    /// Defaults to `<strategy> <symbol> backtest`
+    pub report_name: Option<String>,
+    /// Defaults to the candle timeframe closest to the equity curve spacing
+    pub timeframe: Option<String>,
+    pub formats: Vec<ReportFormat>,
+    pub include_trades: bool,
+    pub include_charts: bool,
+    /// Appended to the HTML report's stylesheet
+    pub custom_css: Option<String>,
+    pub generated_by: Option<String>,
+    /// `CLI`, `API`, `Scheduled` or `Manual`
+    pub generation_source: String,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+}
+
+impl Default for ReportOptions {
+    fn default() -> ReportOptions {
+        ReportOptions {
+            report_name: None,
+            timeframe: None,
+            formats: vec![ReportFormat::Html, ReportFormat::Json, ReportFormat::Csv],
+            include_trades: false,
+            include_charts: true,
+            custom_css: None,
+            generated_by: None,
+            generation_source: "API".to_string(),
+            tags: Vec::new(),
+            notes: None,
+        }
+    }
+}
+
+impl ReportOptions {
+    pub fn new() -> ReportOptions {
+        ReportOptions::default()
+    }
+
+    pub fn name(mut self, report_name: &str) -> ReportOptions {
+        self.report_name = Some(report_name.to_string());
+        self
+    }
+
+    pub fn timeframe(mut self, timeframe: &str) -> ReportOptions {
+        self.timeframe = Some(timeframe.to_string());
+        self
+    }
+
+    pub fn formats(mut self, formats: &[ReportFormat]) -> ReportOptions {
+        self.formats = formats.to_vec();
+        self
+    }
+
+    pub fn with_trades(mut self) -> ReportOptions {
+        self.include_trades = true;
+        self
+    }
+
+    pub fn without_charts(mut self) -> ReportOptions {
+        self.include_charts = false;
+        self
+    }
+
+    pub fn css(mut self, custom_css: &str) -> ReportOptions {
+        self.custom_css = Some(custom_css.to_string());
+        self
+    }
+
+    pub fn generated_by(mut self, generated_by: &str, generation_source: &str) -> ReportOptions {
+        self.generated_by = Some(generated_by.to_string());
+        self.generation_source = generation_source.to_string();
+        self
+    }
+
+    pub fn tagged(mut self, tag: &str) -> ReportOptions {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn notes(mut self, notes: &str) -> ReportOptions {
+        self.notes = Some(notes.to_string());
+        self
+    }
+}
+
+/// Numeric `backtest_results` columns that can be filtered and sorted on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BacktestMetric {
+    TotalReturn,
+    AnnualizedReturn,
+    Volatility,
+    SharpeRatio,
+    SortinoRatio,
+    MaxDrawdown,
+    CalmarRatio,
+    WinRate,
+    ProfitFactor,
+    AvgTradeReturn,
+    TotalTrades,
+    BestTrade,
+    WorstTrade,
+    AvgTimeInTrade,
+    ValueAtRisk95,
+    ExpectedShortfall95,
+    Beta,
+    CorrelationWithBenchmark,
+    TrackingError,
+    InformationRatio,
+    JensenAlpha,
+    MaxDrawdownDurationDays,
+    CurrentDrawdown,
+    AvgDrawdown,
+    BenchmarkReturn,
+    ExcessReturn,
+    TotalCommissionPaid,
+}
+
+impl BacktestMetric {
+    pub fn column(&self) -> &'static str {
+        match self {
+            BacktestMetric::TotalReturn => "total_return",
+            BacktestMetric::AnnualizedReturn => "annualized_return",
+            BacktestMetric::Volatility => "volatility",
+            BacktestMetric::SharpeRatio => "sharpe_ratio",
+            BacktestMetric::SortinoRatio => "sortino_ratio",
+            BacktestMetric::MaxDrawdown => "max_drawdown",
+            BacktestMetric::CalmarRatio => "calmar_ratio",
+            BacktestMetric::WinRate => "win_rate",
+            BacktestMetric::ProfitFactor => "profit_factor",
+            BacktestMetric::AvgTradeReturn => "avg_trade_return",
+            BacktestMetric::TotalTrades => "total_trades",
+            BacktestMetric::BestTrade => "best_trade",
+            BacktestMetric::WorstTrade => "worst_trade",
+            BacktestMetric::AvgTimeInTrade => "avg_time_in_trade",
+            BacktestMetric::ValueAtRisk95 => "value_at_risk_95",
+            BacktestMetric::ExpectedShortfall95 => "expected_shortfall_95",
+            BacktestMetric::Beta => "beta",
+            BacktestMetric::CorrelationWithBenchmark => "correlation_with_benchmark",
+            BacktestMetric::TrackingError => "tracking_error",
+            BacktestMetric::InformationRatio => "information_ratio",
+            BacktestMetric::JensenAlpha => "jensen_alpha",
+            BacktestMetric::MaxDrawdownDurationDays => "max_drawdown_duration_days",
+            BacktestMetric::CurrentDrawdown => "current_drawdown",
+            BacktestMetric::AvgDrawdown => "avg_drawdown",
+            BacktestMetric::BenchmarkReturn => "benchmark_return",
+            BacktestMetric::ExcessReturn => "excess_return",
+            BacktestMetric::TotalCommissionPaid => "total_commission_paid",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetricComparison {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+}
+
+impl MetricComparison {
+    pub fn operator(&self) -> &'static str {
+        match self {
+            MetricComparison::Gt => ">",
+            MetricComparison::Ge => ">=",
+            MetricComparison::Lt => "<",
+            MetricComparison::Le => "<=",
+            MetricComparison::Eq => "=",
+        }
+    }
+}
+
+/// `metric <comparison> value`. Results where the metric is NULL never match.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetricThreshold {
+    pub metric: BacktestMetric,
+    pub comparison: MetricComparison,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SortDirection {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+/// Filters, metric thresholds, sort order and page for `search_backtest_results`.
+/// Without a sort the newest results come first; NULL metrics always sort last.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BacktestResultQuery {
+    pub strategy_name: Option<String>,
+    pub symbol: Option<String>,
+    pub strategy_instance_id: Option<Uuid>,
+    /// Results carrying this tag in `backtest_tags`
+    pub tag: Option<String>,
+    /// Backtests whose `start_date` is at or after this
+    pub window_start: Option<DateTime<Utc>>,
+    /// Backtests whose `end_date` is at or before this
+    pub window_end: Option<DateTime<Utc>>,
+    pub thresholds: Vec<MetricThreshold>,
+    pub sort: Option<(BacktestMetric, SortDirection)>,
+    /// Page size, 100 when unset and capped at 1000
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+impl BacktestResultQuery {
+    pub fn new() -> BacktestResultQuery {
+        BacktestResultQuery::default()
+    }
+
+    pub fn strategy(mut self, strategy_name: &str) -> BacktestResultQuery {
+        self.strategy_name = Some(strategy_name.to_string());
+        self
+    }
+
+    pub fn symbol(mut self, symbol: &str) -> BacktestResultQuery {
+        self.symbol = Some(symbol.to_string());
+        self
+    }
+
+    pub fn strategy_instance(mut self, strategy_instance_id: Uuid) -> BacktestResultQuery {
+        self.strategy_instance_id = Some(strategy_instance_id);
+        self
+    }
+
+    pub fn tagged(mut self, tag: &str) -> BacktestResultQuery {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Backtests that ran entirely within `[start, end]`
+    pub fn within(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> BacktestResultQuery {
+        self.window_start = Some(start);
+        self.window_end = Some(end);
+        self
+    }
+
+    /// Add a threshold, e.g. `.metric(BacktestMetric::SharpeRatio, MetricComparison::Gt, 1.5)`
+    pub fn metric(mut self, metric: BacktestMetric, comparison: MetricComparison, value: f64) -> BacktestResultQuery {
+        self.thresholds.push(MetricThreshold { metric, comparison, value });
+        self
+    }
+
+    pub fn sort_by(mut self, metric: BacktestMetric, direction: SortDirection) -> BacktestResultQuery {
+        self.sort = Some((metric, direction));
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> BacktestResultQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> BacktestResultQuery {
+        self.offset = offset;
+        self
+    }
+
+    /// Page `page` (zero-based) of `page_size` results
+    pub fn page(self, page: i64, page_size: i64) -> BacktestResultQuery {
+        self.limit(page_size).offset(page.saturating_mul(page_size))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestResultPage {
+    pub results: Vec<BacktestResult>,
+    /// Results matching the filters across all pages
+    pub total_count: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
@@ -0,0 +1,238 @@
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::metrics::BacktestMetrics;
+use crate::models::backtest_result::{BacktestEquityCurve, NewBacktestEquityCurve};
+
+pub const IN_SAMPLE: &str = "in_sample";
+pub const OUT_OF_SAMPLE: &str = "out_of_sample";
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::backtest_segments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct BacktestSegment {
+    pub id: Uuid,
+    pub walk_forward_id: Uuid,
+    pub strategy_instance_id: Option<Uuid>,
+    pub window_type: String,
+    pub fold_index: i32,
+    pub segment_type: String,
+    pub backtest_result_id: Uuid,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub parameters: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl BacktestSegment {
+    pub fn is_out_of_sample(&self) -> bool {
+        self.segment_type == OUT_OF_SAMPLE
+    }
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::backtest_segments)]
+pub struct NewBacktestSegment {
+    pub walk_forward_id: Uuid,
+    pub strategy_instance_id: Option<Uuid>,
+    /// `anchored` or `rolling`
+    pub window_type: String,
+    pub fold_index: i32,
+    /// `in_sample` or `out_of_sample`
+    pub segment_type: String,
+    pub backtest_result_id: Uuid,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub parameters: Option<serde_json::Value>,
+}
+
+impl NewBacktestSegment {
+    /// Training segment of `window`'s fold
+    pub fn in_sample(
+        walk_forward_id: Uuid,
+        window: &WalkForwardWindow,
+        backtest_result_id: Uuid,
+        strategy_instance_id: Option<Uuid>,
+        parameters: Option<serde_json::Value>,
+    ) -> NewBacktestSegment {
+        NewBacktestSegment {
+            walk_forward_id,
+            strategy_instance_id,
+            window_type: window.window_type.name().to_string(),
+            fold_index: window.fold_index,
+            segment_type: IN_SAMPLE.to_string(),
+            backtest_result_id,
+            window_start: window.train_start,
+            window_end: window.train_end,
+            parameters,
+        }
+    }
+
+    /// Test segment of `window`'s fold, run with the parameters chosen in-sample
+    pub fn out_of_sample(
+        walk_forward_id: Uuid,
+        window: &WalkForwardWindow,
+        backtest_result_id: Uuid,
+        strategy_instance_id: Option<Uuid>,
+        parameters: Option<serde_json::Value>,
+    ) -> NewBacktestSegment {
+        NewBacktestSegment {
+            walk_forward_id,
+            strategy_instance_id,
+            window_type: window.window_type.name().to_string(),
+            fold_index: window.fold_index,
+            segment_type: OUT_OF_SAMPLE.to_string(),
+            backtest_result_id,
+            window_start: window.test_start,
+            window_end: window.test_end,
+            parameters,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalkForwardWindowType {
+    /// Training always starts at the beginning of the range and grows each fold
+    Anchored,
+    /// Training has a fixed length and slides forward with the test window
+    Rolling,
+}
+
+impl WalkForwardWindowType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WalkForwardWindowType::Anchored => "anchored",
+            WalkForwardWindowType::Rolling => "rolling",
+        }
+    }
+}
+
+/// Train/test boundaries of one walk-forward fold. Test windows follow their training
+/// window directly and do not overlap each other.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WalkForwardWindow {
+    pub fold_index: i32,
+    pub window_type: WalkForwardWindowType,
+    pub train_start: DateTime<Utc>,
+    pub train_end: DateTime<Utc>,
+    pub test_start: DateTime<Utc>,
+    pub test_end: DateTime<Utc>,
+}
+
+/// Split `[start, end]` into walk-forward folds: an initial `train` window followed by
+/// consecutive `test` windows. A final test window shorter than `test` is dropped.
+pub fn walk_forward_windows(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    train: Duration,
+    test: Duration,
+    window_type: WalkForwardWindowType,
+) -> Vec<WalkForwardWindow> {
+    let mut windows = Vec::new();
+    if train <= Duration::zero() || test <= Duration::zero() {
+        return windows;
+    }
+
+    let mut test_start = start + train;
+    while test_start + test <= end {
+        let train_start = match window_type {
+            WalkForwardWindowType::Anchored => start,
+            WalkForwardWindowType::Rolling => test_start - train,
+        };
+        windows.push(WalkForwardWindow {
+            fold_index: windows.len() as i32,
+            window_type,
+            train_start,
+            train_end: test_start,
+            test_start,
+            test_end: test_start + test,
+        });
+        test_start += test;
+    }
+    windows
+}
+
+/// The in-sample and out-of-sample segments sharing a fold index. Either may be missing
+/// while the sequence is still being run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkForwardFold {
+    pub fold_index: i32,
+    pub in_sample: Option<BacktestSegment>,
+    pub out_of_sample: Option<BacktestSegment>,
+}
+
+/// How much of a fold's in-sample performance survived out of sample
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoldDegradation {
+    pub fold_index: i32,
+    pub in_sample_result_id: Uuid,
+    pub out_of_sample_result_id: Uuid,
+    pub in_sample_sharpe: Option<f64>,
+    pub out_of_sample_sharpe: Option<f64>,
+    pub in_sample_annualized_return: f64,
+    pub out_of_sample_annualized_return: f64,
+    /// Out-of-sample over in-sample annualized return, `None` when the in-sample return is
+    /// not positive
+    pub efficiency: Option<f64>,
+    /// In-sample minus out-of-sample Sharpe ratio
+    pub sharpe_decay: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkForwardReport {
+    pub walk_forward_id: Uuid,
+    pub folds: Vec<FoldDegradation>,
+    /// Out-of-sample curves chained in fold order. `backtest_result_id` is nil; set it
+    /// before storing the curve under a result of its own.
+    pub stitched_equity: Vec<NewBacktestEquityCurve>,
+    /// Metrics of the stitched curve and the out-of-sample trades
+    pub stitched_metrics: Option<BacktestMetrics>,
+    pub mean_in_sample_sharpe: Option<f64>,
+    pub mean_out_of_sample_sharpe: Option<f64>,
+    /// Mean out-of-sample over mean in-sample annualized return across complete folds
+    pub walk_forward_efficiency: Option<f64>,
+}
+
+/// Chain equity curves, each clipped to its `[start, end]` window, into one curve. Every
+/// curve after the first is rescaled so it starts where the previous one ended, so the
+/// stitched curve compounds the segments' returns from the first segment's capital.
+pub fn stitch_equity_curves(
+    segments: &[(DateTime<Utc>, DateTime<Utc>, Vec<BacktestEquityCurve>)],
+) -> Vec<NewBacktestEquityCurve> {
+    let mut stitched: Vec<NewBacktestEquityCurve> = Vec::new();
+    let mut last_value: Option<f64> = None;
+
+    for (start, end, curve) in segments {
+        let clipped: Vec<(DateTime<Utc>, f64)> = curve
+            .iter()
+            .filter(|p| p.timestamp >= *start && p.timestamp <= *end)
+            .filter_map(|p| p.portfolio_value.to_f64().map(|v| (p.timestamp, v)))
+            .filter(|(_, v)| *v > 0.0)
+            .collect();
+        let Some(&(_, first_value)) = clipped.as_slice().first() else {
+            continue;
+        };
+        let scale = last_value.map(|lv| lv / first_value).unwrap_or(1.0);
+        let previous_ts = stitched.last().map(|p| p.timestamp);
+
+        for (ts, value) in clipped {
+            // Adjacent windows share their boundary; keep the earlier segment's point
+            if previous_ts.is_some_and(|prev| ts <= prev) {
+                continue;
+            }
+            let Some(portfolio_value) = BigDecimal::from_f64(value * scale) else {
+                continue;
+            };
+            stitched.push(NewBacktestEquityCurve {
+                backtest_result_id: Uuid::nil(),
+                timestamp: ts,
+                portfolio_value: portfolio_value.with_scale(8),
+            });
+            last_value = Some(value * scale);
+        }
+    }
+    stitched
+}
@@ -209,3 +209,68 @@ impl CandleData for Candle {
     fn volume(&self) -> &BigDecimal { &self.volume }
     fn trade_count(&self) -> i32 { self.trade_count }
 }
+
+
+/// How candle history is back-adjusted for corporate actions
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum CandleAdjustment {
+    /// Raw prices and volumes as stored
+    None,
+    /// Prices divided and volumes multiplied by the split ratios that went effective later
+    Splits,
+    /// Split adjustment plus the multiplicative dividend factor `1 - dividend / prior close`
+    SplitsAndDividends,
+}
+
+/// Filters for `get_adjusted_candles`. The range may span at most one year.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandleQuery {
+    pub symbol: String,
+    pub exchange: String,
+    pub timeframe: String,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+    pub adjustment: CandleAdjustment,
+}
+
+impl CandleQuery {
+    pub fn new(symbol: &str, exchange: &str, timeframe: &str) -> CandleQuery {
+        CandleQuery {
+            symbol: symbol.to_string(),
+            exchange: exchange.to_string(),
+            timeframe: timeframe.to_string(),
+            start_time: None,
+            end_time: None,
+            limit: None,
+            adjustment: CandleAdjustment::None,
+        }
+    }
+
+    /// Candles with `start <= timestamp <= end`
+    pub fn between(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> CandleQuery {
+        self.start_time = Some(start);
+        self.end_time = Some(end);
+        self
+    }
+
+    pub fn since(mut self, start: DateTime<Utc>) -> CandleQuery {
+        self.start_time = Some(start);
+        self
+    }
+
+    pub fn until(mut self, end: DateTime<Utc>) -> CandleQuery {
+        self.end_time = Some(end);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> CandleQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn adjustment(mut self, adjustment: CandleAdjustment) -> CandleQuery {
+        self.adjustment = adjustment;
+        self
+    }
+}
@@ -0,0 +1,115 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::corporate_actions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CorporateAction {
+    pub id: Uuid,
+    pub security_id: Uuid,
+    pub symbol: String,
+    pub action_type: String,
+    pub effective_date: DateTime<Utc>,
+    pub split_ratio: Option<BigDecimal>,
+    pub dividend_amount: Option<BigDecimal>,
+    pub new_symbol: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::corporate_actions)]
+pub struct NewCorporateAction {
+    pub security_id: Uuid,
+    pub symbol: String,
+    pub action_type: String,
+    pub effective_date: DateTime<Utc>,
+    pub split_ratio: Option<BigDecimal>,
+    pub dividend_amount: Option<BigDecimal>,
+    pub new_symbol: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl NewCorporateAction {
+    /// A split of `ratio` new shares per old share, e.g. 4 for a 4:1 split or 0.1 for a 1:10 reverse split
+    pub fn split(security_id: Uuid, symbol: &str, effective_date: DateTime<Utc>, ratio: &BigDecimal) -> NewCorporateAction {
+        NewCorporateAction {
+            security_id,
+            symbol: symbol.to_string(),
+            action_type: "split".to_string(),
+            effective_date,
+            split_ratio: Some(ratio.clone()),
+            dividend_amount: None,
+            new_symbol: None,
+            notes: None,
+        }
+    }
+
+    /// A cash dividend of `amount` per share going ex on `effective_date`
+    pub fn dividend(security_id: Uuid, symbol: &str, effective_date: DateTime<Utc>, amount: &BigDecimal) -> NewCorporateAction {
+        NewCorporateAction {
+            security_id,
+            symbol: symbol.to_string(),
+            action_type: "dividend".to_string(),
+            effective_date,
+            split_ratio: None,
+            dividend_amount: Some(amount.clone()),
+            new_symbol: None,
+            notes: None,
+        }
+    }
+
+    /// A ticker change from `symbol` to `new_symbol`
+    pub fn rename(security_id: Uuid, symbol: &str, effective_date: DateTime<Utc>, new_symbol: &str) -> NewCorporateAction {
+        NewCorporateAction {
+            security_id,
+            symbol: symbol.to_string(),
+            action_type: "rename".to_string(),
+            effective_date,
+            split_ratio: None,
+            dividend_amount: None,
+            new_symbol: Some(new_symbol.to_string()),
+            notes: None,
+        }
+    }
+
+    /// The security stops trading on `effective_date`
+    pub fn delist(security_id: Uuid, symbol: &str, effective_date: DateTime<Utc>) -> NewCorporateAction {
+        NewCorporateAction {
+            security_id,
+            symbol: symbol.to_string(),
+            action_type: "delist".to_string(),
+            effective_date,
+            split_ratio: None,
+            dividend_amount: None,
+            new_symbol: None,
+            notes: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::symbol_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SymbolHistory {
+    pub id: Uuid,
+    pub security_id: Uuid,
+    pub symbol: String,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::symbol_history)]
+pub struct NewSymbolHistory {
+    pub security_id: Uuid,
+    pub symbol: String,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+    pub status: String,
+}
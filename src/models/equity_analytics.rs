@@ -0,0 +1,137 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Datelike, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Nullable, Numeric, Timestamptz, Uuid as SqlUuid};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// `time_bucket` width used to resample equity curves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EquityBucket {
+    Daily,
+    /// Monday-aligned weeks
+    Weekly,
+    /// Calendar months
+    Monthly,
+}
+
+impl EquityBucket {
+    /// PostgreSQL interval literal passed to `time_bucket`
+    pub fn interval(&self) -> &'static str {
+        match self {
+            EquityBucket::Daily => "1 day",
+            EquityBucket::Weekly => "1 week",
+            EquityBucket::Monthly => "1 month",
+        }
+    }
+
+    /// Buckets per year, for annualizing bucketed returns
+    pub fn periods_per_year(&self) -> f64 {
+        match self {
+            EquityBucket::Daily => 365.0,
+            EquityBucket::Weekly => 52.0,
+            EquityBucket::Monthly => 12.0,
+        }
+    }
+}
+
+/// First, highest, lowest and last portfolio value within one bucket
+#[derive(Debug, Clone, QueryableByName, Serialize, Deserialize)]
+pub struct ResampledEquityPoint {
+    #[diesel(sql_type = SqlUuid)]
+    pub backtest_result_id: Uuid,
+    #[diesel(sql_type = Timestamptz)]
+    pub bucket: DateTime<Utc>,
+    #[diesel(sql_type = Numeric)]
+    pub open_value: BigDecimal,
+    #[diesel(sql_type = Numeric)]
+    pub high_value: BigDecimal,
+    #[diesel(sql_type = Numeric)]
+    pub low_value: BigDecimal,
+    #[diesel(sql_type = Numeric)]
+    pub close_value: BigDecimal,
+    #[diesel(sql_type = BigInt)]
+    pub points: i64,
+}
+
+/// Return over one bucket, from the previous bucket's close (or this bucket's first value
+/// for the first bucket) to this bucket's close. `None` when the starting value is zero.
+#[derive(Debug, Clone, QueryableByName, Serialize, Deserialize)]
+pub struct PeriodReturn {
+    #[diesel(sql_type = SqlUuid)]
+    pub backtest_result_id: Uuid,
+    #[diesel(sql_type = Timestamptz)]
+    pub period_start: DateTime<Utc>,
+    #[diesel(sql_type = Numeric)]
+    pub start_value: BigDecimal,
+    #[diesel(sql_type = Numeric)]
+    pub end_value: BigDecimal,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub period_return: Option<f64>,
+}
+
+/// One year of a monthly return table. `months[0]` is January.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonthlyReturnRow {
+    pub year: i32,
+    pub months: [Option<f64>; 12],
+    /// Compounded over the months present
+    pub year_return: f64,
+}
+
+impl MonthlyReturnRow {
+    /// Arrange monthly `PeriodReturn`s of one backtest into calendar years
+    pub fn table(returns: &[PeriodReturn]) -> Vec<MonthlyReturnRow> {
+        let mut years: BTreeMap<i32, [Option<f64>; 12]> = BTreeMap::new();
+        for r in returns {
+            let months = years.entry(r.period_start.year()).or_insert([None; 12]);
+            months[r.period_start.month0() as usize] = r.period_return;
+        }
+        years
+            .into_iter()
+            .map(|(year, months)| MonthlyReturnRow {
+                year,
+                months,
+                year_return: months.iter().flatten().fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0,
+            })
+            .collect()
+    }
+}
+
+/// Daily-return statistics over the trailing calendar window ending at `timestamp`
+#[derive(Debug, Clone, QueryableByName, Serialize, Deserialize)]
+pub struct RollingEquityMetric {
+    #[diesel(sql_type = SqlUuid)]
+    pub backtest_result_id: Uuid,
+    #[diesel(sql_type = Timestamptz)]
+    pub timestamp: DateTime<Utc>,
+    /// Daily returns inside the window
+    #[diesel(sql_type = BigInt)]
+    pub observations: i64,
+    /// Compounded over the window
+    #[diesel(sql_type = Nullable<Double>)]
+    pub rolling_return: Option<f64>,
+    /// Annualized
+    #[diesel(sql_type = Nullable<Double>)]
+    pub volatility: Option<f64>,
+    /// Annualized, zero risk-free rate
+    #[diesel(sql_type = Nullable<Double>)]
+    pub sharpe_ratio: Option<f64>,
+}
+
+/// Point of an underwater curve. `drawdown` is zero at new highs and negative below them,
+/// and `None` while the running peak is zero.
+#[derive(Debug, Clone, QueryableByName, Serialize, Deserialize)]
+pub struct DrawdownPoint {
+    #[diesel(sql_type = SqlUuid)]
+    pub backtest_result_id: Uuid,
+    #[diesel(sql_type = Timestamptz)]
+    pub timestamp: DateTime<Utc>,
+    #[diesel(sql_type = Numeric)]
+    pub portfolio_value: BigDecimal,
+    #[diesel(sql_type = Numeric)]
+    pub peak_value: BigDecimal,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub drawdown: Option<f64>,
+}
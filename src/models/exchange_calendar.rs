@@ -0,0 +1,111 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use diesel::pg::sql_types::Timestamptz;
+use diesel::prelude::*;
+use diesel::sql_types::{Date, VarChar};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::exchange_calendars)]
+#[diesel(primary_key(exchange_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ExchangeCalendar {
+    pub exchange_id: Uuid,
+    pub timezone: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable, AsChangeset, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::exchange_calendars)]
+pub struct NewExchangeCalendar {
+    pub exchange_id: Uuid,
+    pub timezone: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::exchange_sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ExchangeSession {
+    pub id: Uuid,
+    pub exchange_id: Uuid,
+    pub day_of_week: i16,
+    pub session_type: String,
+    pub open_time: NaiveTime,
+    pub close_time: NaiveTime,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::exchange_sessions)]
+pub struct NewExchangeSession {
+    pub exchange_id: Uuid,
+    pub day_of_week: i16,
+    pub session_type: String,
+    pub open_time: NaiveTime,
+    pub close_time: NaiveTime,
+}
+
+impl NewExchangeSession {
+    /// `day_of_week` is ISO numbered (1 = Monday, 7 = Sunday). Times are venue-local;
+    /// a close at or before the open ends on the following day.
+    pub fn new(exchange_id: Uuid, day_of_week: i16, session_type: &str, open_time: NaiveTime, close_time: NaiveTime) -> NewExchangeSession {
+        NewExchangeSession {
+            exchange_id,
+            day_of_week,
+            session_type: session_type.to_string(),
+            open_time,
+            close_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::exchange_holidays)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ExchangeHoliday {
+    pub id: Uuid,
+    pub exchange_id: Uuid,
+    pub holiday_date: NaiveDate,
+    pub name: String,
+    pub early_close: Option<NaiveTime>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable, AsChangeset, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::exchange_holidays)]
+pub struct NewExchangeHoliday {
+    pub exchange_id: Uuid,
+    pub holiday_date: NaiveDate,
+    pub name: String,
+    pub early_close: Option<NaiveTime>,
+}
+
+/// A concrete session instance resolved from the weekly template, holidays and timezone
+#[derive(Debug, Clone, PartialEq, QueryableByName, Serialize, Deserialize)]
+pub struct TradingSession {
+    #[diesel(sql_type = Date)]
+    pub session_date: NaiveDate,
+    #[diesel(sql_type = VarChar)]
+    pub session_type: String,
+    #[diesel(sql_type = Timestamptz)]
+    pub open_at: DateTime<Utc>,
+    #[diesel(sql_type = Timestamptz)]
+    pub close_at: DateTime<Utc>,
+}
+
+impl TradingSession {
+    pub fn contains(&self, ts: DateTime<Utc>) -> bool {
+        self.open_at <= ts && ts < self.close_at
+    }
+}
+
+// Composite struct for the full calendar of one venue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExchangeCalendarDetail {
+    pub calendar: ExchangeCalendar,
+    pub sessions: Vec<ExchangeSession>,
+    pub holidays: Vec<ExchangeHoliday>,
+}
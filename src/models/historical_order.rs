@@ -8,22 +8,22 @@ use uuid::Uuid;
 
 use crate::schema::historical_orders;
 
-#[derive(Debug, Insertable, AsChangeset)]
+#[derive(Debug, Clone, Insertable, AsChangeset)]
 #[diesel(table_name = historical_orders)]
 pub struct NewHistoricalOrder {
-    timestamp: DateTime<Utc>,
-    order_id: String,
-    event_type: String,
-    side: String,
-    price_level: BigDecimal,
-    quantity: BigDecimal,
-    prev_price: Option<BigDecimal>,
-    prev_quantity: Option<BigDecimal>,
-    status: String,
-    exchange: String,
-    symbol: String,
-    exchange_id: Uuid,
-    security_id: Uuid,
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) order_id: String,
+    pub(crate) event_type: String,
+    pub(crate) side: String,
+    pub(crate) price_level: BigDecimal,
+    pub(crate) quantity: BigDecimal,
+    pub(crate) prev_price: Option<BigDecimal>,
+    pub(crate) prev_quantity: Option<BigDecimal>,
+    pub(crate) status: String,
+    pub(crate) exchange: String,
+    pub(crate) symbol: String,
+    pub(crate) exchange_id: Uuid,
+    pub(crate) security_id: Uuid,
 }
 
 impl NewHistoricalOrder {
@@ -11,15 +11,15 @@ use crate::schema::historical_snapshot;
 #[derive(Debug, Insertable, AsChangeset)]
 #[diesel(table_name = historical_snapshot)]
 pub struct NewHistoricalSnapshot {
-    timestamp: DateTime<Utc>,
+    pub(crate) timestamp: DateTime<Utc>,
     pub order_id: String,
-    event_type: String,
-    side: String,
-    price_level: BigDecimal,
-    quantity: BigDecimal,
-    status: String,
-    exchange: String,
-    symbol: String
+    pub(crate) event_type: String,
+    pub(crate) side: String,
+    pub(crate) price_level: BigDecimal,
+    pub(crate) quantity: BigDecimal,
+    pub(crate) status: String,
+    pub(crate) exchange: String,
+    pub(crate) symbol: String
 }
 
 impl NewHistoricalSnapshot {
@@ -1,8 +1,14 @@
+pub mod backtest_lifecycle;
 pub mod backtest_result;
+pub mod backtest_segment;
 pub mod candles;
+pub mod corporate_action;
+pub mod equity_analytics;
 pub mod exchange;
+pub mod exchange_calendar;
 pub mod historical_order;
 pub mod historical_snapshot;
+pub mod monte_carlo;
 pub mod open_buy_order;
 pub mod open_sell_order;
 pub mod order_book;
@@ -0,0 +1,190 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Nullable, Text};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::resampling::ResamplingScheme;
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::monte_carlo_runs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MonteCarloRun {
+    pub id: Uuid,
+    pub strategy_name: String,
+    pub symbol: String,
+    pub exchange: String,
+    pub data_source: String,
+    pub resampling_scheme: String,
+    pub scheme_parameters: serde_json::Value,
+    pub base_seed: Option<i64>,
+    pub num_paths: i32,
+    pub status: String,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl MonteCarloRun {
+    pub fn get_base_seed(&self) -> Option<u64> {
+        self.base_seed.map(|s| s as u64)
+    }
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::monte_carlo_runs)]
+pub struct NewMonteCarloRun {
+    pub strategy_name: String,
+    pub symbol: String,
+    pub exchange: String,
+    /// `orders`, `snapshots`, `candles` or `trades`
+    pub data_source: String,
+    pub resampling_scheme: String,
+    pub scheme_parameters: serde_json::Value,
+    pub base_seed: Option<i64>,
+    pub num_paths: i32,
+    pub notes: Option<String>,
+}
+
+impl NewMonteCarloRun {
+    pub fn new(
+        strategy_name: &str,
+        symbol: &str,
+        exchange: &str,
+        data_source: &str,
+        scheme: ResamplingScheme,
+        base_seed: Option<u64>,
+        num_paths: i32,
+    ) -> NewMonteCarloRun {
+        NewMonteCarloRun {
+            strategy_name: strategy_name.to_string(),
+            symbol: symbol.to_string(),
+            exchange: exchange.to_string(),
+            data_source: data_source.to_string(),
+            resampling_scheme: scheme.name().to_string(),
+            scheme_parameters: scheme.parameters(),
+            base_seed: base_seed.map(|s| s as i64),
+            num_paths,
+            notes: None,
+        }
+    }
+
+    /// A run produced by `get_randomized_*`, which shuffles within fixed time windows
+    pub fn window_shuffle(
+        strategy_name: &str,
+        symbol: &str,
+        exchange: &str,
+        data_source: &str,
+        window_minutes: i32,
+        base_seed: Option<u64>,
+        num_paths: i32,
+    ) -> NewMonteCarloRun {
+        NewMonteCarloRun {
+            resampling_scheme: "window_shuffle".to_string(),
+            scheme_parameters: serde_json::json!({ "window_minutes": window_minutes }),
+            ..NewMonteCarloRun::new(strategy_name, symbol, exchange, data_source, ResamplingScheme::Simple, base_seed, num_paths)
+        }
+    }
+
+    pub fn with_notes(mut self, notes: &str) -> NewMonteCarloRun {
+        self.notes = Some(notes.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::monte_carlo_paths)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MonteCarloPath {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub path_index: i32,
+    pub seed: i64,
+    pub resampling_scheme: String,
+    pub scheme_parameters: serde_json::Value,
+    pub backtest_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MonteCarloPath {
+    /// The seed exactly as it was passed to the resampling function
+    pub fn get_seed(&self) -> u64 {
+        self.seed as u64
+    }
+}
+
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::monte_carlo_paths)]
+pub struct NewMonteCarloPath {
+    pub run_id: Uuid,
+    pub path_index: i32,
+    pub seed: i64,
+    pub resampling_scheme: String,
+    pub scheme_parameters: serde_json::Value,
+    pub backtest_id: Option<Uuid>,
+}
+
+impl NewMonteCarloPath {
+    /// A path using the run's scheme and parameters
+    pub fn for_run(run: &MonteCarloRun, path_index: i32, seed: u64, backtest_id: Option<Uuid>) -> NewMonteCarloPath {
+        NewMonteCarloPath {
+            run_id: run.id,
+            path_index,
+            seed: seed as i64,
+            resampling_scheme: run.resampling_scheme.clone(),
+            scheme_parameters: run.scheme_parameters.clone(),
+            backtest_id,
+        }
+    }
+
+    /// A path whose scheme differs from the run's, e.g. when a sweep varies the block size
+    pub fn with_scheme(run_id: Uuid, path_index: i32, seed: u64, scheme: ResamplingScheme, backtest_id: Option<Uuid>) -> NewMonteCarloPath {
+        NewMonteCarloPath {
+            run_id,
+            path_index,
+            seed: seed as i64,
+            resampling_scheme: scheme.name().to_string(),
+            scheme_parameters: scheme.parameters(),
+            backtest_id,
+        }
+    }
+}
+
+/// Distribution of one backtest metric across the paths of a run
+#[derive(Debug, Clone, QueryableByName, Serialize, Deserialize)]
+pub struct MetricDistribution {
+    #[diesel(sql_type = Text)]
+    pub metric: String,
+    /// Paths with a non-null value for the metric
+    #[diesel(sql_type = BigInt)]
+    pub observations: i64,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub mean: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub std_dev: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub min: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub p5: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub p25: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub p50: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub p75: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub p95: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonteCarloDistribution {
+    pub run_id: Uuid,
+    pub paths: i64,
+    /// Paths linked to a stored backtest result
+    pub paths_with_results: i64,
+    pub total_return: Option<MetricDistribution>,
+    pub max_drawdown: Option<MetricDistribution>,
+    pub sharpe_ratio: Option<MetricDistribution>,
+}
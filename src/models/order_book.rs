@@ -27,7 +27,7 @@ impl NewOrderBook {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Queryable, Clone, Selectable, AsChangeset)]
+#[derive(Serialize, Deserialize, Debug, Queryable, Clone, Selectable, QueryableByName, AsChangeset)]
 #[diesel(table_name = order_books)]
 #[diesel(check_for_backend(Pg))]
 pub struct OrderBook {
@@ -4,6 +4,8 @@ use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::comparison::{ComparisonResults, ComparisonSummary};
+
 #[derive(Debug, Clone, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::strategies)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -203,6 +205,18 @@ pub struct StrategyComparison {
     pub created_at: DateTime<Utc>,
 }
 
+impl StrategyComparison {
+    /// `results` in the layout written by `compare_backtests`; `None` for free-form comparisons
+    pub fn comparison_results(&self) -> Option<ComparisonResults> {
+        self.results.clone().and_then(|v| serde_json::from_value(v).ok())
+    }
+
+    /// `summary` in the layout written by `compare_backtests`; `None` for free-form comparisons
+    pub fn comparison_summary(&self) -> Option<ComparisonSummary> {
+        self.summary.clone().and_then(|v| serde_json::from_value(v).ok())
+    }
+}
+
 #[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::strategy_comparisons)]
 pub struct NewStrategyComparison {
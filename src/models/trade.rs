@@ -19,6 +19,10 @@ pub struct NewTrade {
     pub side: String,
     pub price: BigDecimal,
     pub quantity: BigDecimal,
+    /// Exchange event time. When unset the database falls back to the ingest time.
+    #[diesel(column_name = created_at)]
+    #[diesel(sql_type = diesel::sql_types::Nullable<Timestamptz>)]
+    pub event_time: Option<DateTime<Utc>>,
 }
 
 impl NewTrade {
@@ -41,8 +45,14 @@ impl NewTrade {
             side: side.to_string(),
             price: price.clone(),
             quantity: quantity.clone(),
+            event_time: None,
         }
     }
+
+    pub fn with_event_time(mut self, event_time: DateTime<Utc>) -> NewTrade {
+        self.event_time = Some(event_time);
+        self
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, Queryable, Selectable, QueryableByName, AsChangeset)]
@@ -75,6 +85,9 @@ pub struct Trade {
 
     #[diesel(sql_type = Numeric)]
     pub quantity: BigDecimal,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Timestamptz>)]
+    pub ingested_at: Option<DateTime<Utc>>,
 }
 
 impl Trade {
@@ -105,4 +118,146 @@ impl Trade {
     pub fn get_quantity(&self) -> &BigDecimal {
         &self.quantity
     }
-}
\ No newline at end of file
+
+    pub fn get_ingested_at(&self) -> Option<DateTime<Utc>> {
+        self.ingested_at
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TradeOrdering {
+    #[default]
+    OldestFirst,
+    NewestFirst,
+}
+
+/// Filters for reading trades. Always bound the time range on large symbols:
+/// the hypertable uses 1-day chunks and an unbounded query scans every one of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeQuery {
+    pub symbol: String,
+    pub exchange: String,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub side: Option<String>,
+    pub min_quantity: Option<BigDecimal>,
+    pub max_quantity: Option<BigDecimal>,
+    pub limit: Option<i64>,
+    pub ordering: TradeOrdering,
+}
+
+impl TradeQuery {
+    pub fn new(symbol: &str, exchange: &str) -> TradeQuery {
+        TradeQuery {
+            symbol: symbol.to_string(),
+            exchange: exchange.to_string(),
+            start_time: None,
+            end_time: None,
+            side: None,
+            min_quantity: None,
+            max_quantity: None,
+            limit: None,
+            ordering: TradeOrdering::default(),
+        }
+    }
+
+    /// Trades with `start <= created_at < end`
+    pub fn between(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> TradeQuery {
+        self.start_time = Some(start);
+        self.end_time = Some(end);
+        self
+    }
+
+    pub fn since(mut self, start: DateTime<Utc>) -> TradeQuery {
+        self.start_time = Some(start);
+        self
+    }
+
+    pub fn until(mut self, end: DateTime<Utc>) -> TradeQuery {
+        self.end_time = Some(end);
+        self
+    }
+
+    pub fn side(mut self, side: &str) -> TradeQuery {
+        self.side = Some(side.to_string());
+        self
+    }
+
+    pub fn min_quantity(mut self, quantity: &BigDecimal) -> TradeQuery {
+        self.min_quantity = Some(quantity.clone());
+        self
+    }
+
+    pub fn max_quantity(mut self, quantity: &BigDecimal) -> TradeQuery {
+        self.max_quantity = Some(quantity.clone());
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> TradeQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn ordering(mut self, ordering: TradeOrdering) -> TradeQuery {
+        self.ordering = ordering;
+        self
+    }
+}
+
+/// Server-side aggregate over the trades matched by a `TradeQuery`
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+pub struct TradeSummary {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub trade_count: i64,
+
+    #[diesel(sql_type = Numeric)]
+    pub total_volume: BigDecimal,
+
+    #[diesel(sql_type = Numeric)]
+    pub buy_volume: BigDecimal,
+
+    #[diesel(sql_type = Numeric)]
+    pub sell_volume: BigDecimal,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Numeric>)]
+    pub vwap: Option<BigDecimal>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Timestamptz>)]
+    pub first_trade_at: Option<DateTime<Utc>>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Timestamptz>)]
+    pub last_trade_at: Option<DateTime<Utc>>,
+}
+
+/// Buy/sell flow for one time bucket. `imbalance` is `(buy - sell) / (buy + sell)`, in [-1, 1].
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+pub struct TradeImbalanceBucket {
+    #[diesel(sql_type = Timestamptz)]
+    pub bucket: DateTime<Utc>,
+
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub trade_count: i64,
+
+    #[diesel(sql_type = Numeric)]
+    pub buy_volume: BigDecimal,
+
+    #[diesel(sql_type = Numeric)]
+    pub sell_volume: BigDecimal,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Numeric>)]
+    pub vwap: Option<BigDecimal>,
+
+    #[diesel(sql_type = diesel::sql_types::Nullable<Numeric>)]
+    pub imbalance: Option<BigDecimal>,
+}
+
+/// Outcome of `ingest_trades`. `duplicates` counts trades whose `(exchange_id, trade_id)`
+/// was already stored or repeated within the batch; `late` counts trades routed into
+/// chunks that had already been compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeIngestReport {
+    pub received: usize,
+    pub inserted: usize,
+    pub duplicates: usize,
+    pub late: usize,
+    pub decompressed_chunks: usize,
+}
@@ -0,0 +1,477 @@
+use crate::{
+    get_timescale_connection,
+    models::backtest_lifecycle::{
+        BacktestGcCandidate, BacktestGcPolicy, BacktestGcReport, BacktestPurgeReport,
+        BacktestStorageReport, BacktestTag, NewBacktestTag
+    },
+    ops::backtest_report_ops,
+    report_storage::ReportStorage,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Bool, Nullable, Text, Timestamptz, Uuid as SqlUuid};
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, error, debug, warn};
+use uuid::Uuid;
+
+/// Scratch tables written while a backtest runs, all keyed by `backtest_id`
+const SIM_TABLES: [&str; 3] = ["sim_open_buy_orders", "sim_open_sell_orders", "sim_trades"];
+
+/// Tables hanging off `backtest_results` through `backtest_result_id`
+const RESULT_CHILD_TABLES: [&str; 5] = [
+    "backtest_trades",
+    "backtest_equity_curve",
+    "backtest_position_history",
+    "backtest_drawdown_periods",
+    "backtest_reports",
+];
+
+fn validate_tag(tag: &str) -> Result<(), Error> {
+    if tag.is_empty() || tag.len() > 100 {
+        error!("Invalid tag length: {}", tag.len());
+        return Err(anyhow::anyhow!("Tag must be between 1 and 100 characters"));
+    }
+    Ok(())
+}
+
+/// Add tags to a backtest; tags it already has are ignored
+pub async fn tag_backtest(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    test_id: Uuid,
+    tags: &[&str],
+) -> Result<usize, Error> {
+    for t in tags {
+        validate_tag(t)?;
+    }
+    let rows: Vec<NewBacktestTag> = tags
+        .iter()
+        .map(|t| NewBacktestTag { backtest_id: test_id, tag: t.to_string() })
+        .collect();
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_tags::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        diesel::insert_into(backtest_tags)
+            .values(&rows)
+            .on_conflict_do_nothing()
+            .execute(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error tagging backtest {}: {}", test_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+pub async fn untag_backtest(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    test_id: Uuid,
+    tags: &[&str],
+) -> Result<usize, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_tags::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        diesel::delete(backtest_tags.filter(backtest_id.eq(test_id)).filter(tag.eq_any(tags)))
+            .execute(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+pub async fn get_backtest_tags(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    test_id: Uuid,
+) -> Result<Vec<BacktestTag>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_tags::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        backtest_tags
+            .filter(backtest_id.eq(test_id))
+            .order(tag.asc())
+            .select(BacktestTag::as_select())
+            .load::<BacktestTag>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+async fn purge_backtest_in(
+    conn: &mut AsyncPgConnection,
+    test_id: Uuid,
+    keep_results: bool,
+) -> Result<BacktestPurgeReport, diesel::result::Error> {
+    let mut report = BacktestPurgeReport {
+        backtest_id: test_id,
+        ..BacktestPurgeReport::default()
+    };
+
+    {
+        use crate::schema::sim_open_buy_orders::dsl::*;
+        report.sim_buy_orders = diesel::delete(sim_open_buy_orders.filter(backtest_id.eq(test_id)))
+            .execute(conn)
+            .await?;
+    }
+    {
+        use crate::schema::sim_open_sell_orders::dsl::*;
+        report.sim_sell_orders = diesel::delete(sim_open_sell_orders.filter(backtest_id.eq(test_id)))
+            .execute(conn)
+            .await?;
+    }
+    {
+        use crate::schema::sim_trades::dsl::*;
+        report.sim_trades = diesel::delete(sim_trades.filter(backtest_id.eq(test_id)))
+            .execute(conn)
+            .await?;
+    }
+
+    if !keep_results {
+        {
+            // Child tables and reports go with the result through ON DELETE CASCADE
+            use crate::schema::backtest_results::dsl::*;
+            report.results_deleted = diesel::delete(backtest_results.filter(backtest_id.eq(test_id)))
+                .execute(conn)
+                .await? > 0;
+        }
+        {
+            use crate::schema::backtest_tags::dsl::*;
+            report.tags = diesel::delete(backtest_tags.filter(backtest_id.eq(test_id)))
+                .execute(conn)
+                .await?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Id and storage location of each report generated for a backtest's result
+async fn get_backtest_report_locations(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    test_id: Uuid,
+) -> Result<Vec<(Uuid, String)>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::{backtest_reports, backtest_results};
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        backtest_reports::table
+            .inner_join(backtest_results::table)
+            .filter(backtest_results::backtest_id.eq(test_id))
+            .select((backtest_reports::id, backtest_reports::storage_location))
+            .load::<(Uuid, String)>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Remove a backtest's simulation orders and trades in one transaction. Unless
+/// `keep_results` is set the `backtest_results` row, its child tables and the
+/// backtest's tags are removed as well. Report files are deleted from `storage` first and the
+/// purge stops if one of them cannot be; reports kept in another backend are logged and
+/// their files left in place.
+pub async fn purge_backtest(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    storage: &dyn ReportStorage,
+    test_id: Uuid,
+    keep_results: bool,
+) -> Result<BacktestPurgeReport, Error> {
+    let query_start = Instant::now();
+    info!("Purging backtest {} (keep_results: {})", test_id, keep_results);
+
+    // The cascade only removes report rows, so their files go through the report ops first
+    let mut reports = 0;
+    if !keep_results {
+        for (report_id, location) in get_backtest_report_locations(pool.clone(), test_id).await? {
+            if location != storage.location() {
+                warn!("Leaving files of report {} in {} storage while purging backtest {} through {}",
+                    report_id, location, test_id, storage.location());
+                continue;
+            }
+            if backtest_report_ops::delete_backtest_report(pool.clone(), storage, report_id).await? {
+                reports += 1;
+            }
+        }
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let mut report = connection
+            .transaction::<_, diesel::result::Error, _>(|conn| Box::pin(purge_backtest_in(conn, test_id, keep_results)))
+            .await
+            .map_err(|e| {
+                error!("Error purging backtest {}: {}", test_id, e);
+                anyhow::Error::from(e)
+            })?;
+        report.reports = reports;
+
+        info!("Purged backtest {}: {} buy orders, {} sell orders, {} trades, {} reports, results deleted: {} in {}ms",
+            test_id, report.sim_buy_orders, report.sim_sell_orders, report.sim_trades, report.reports,
+            report.results_deleted, query_start.elapsed().as_millis());
+        Ok(report)
+    }).await
+}
+
+/// Backtests matching `policy`, least recently active first. A backtest's last activity is
+/// the newest sim row or result update it has. With `keep_results` only backtests that still
+/// have sim rows are returned; ones already reduced to their results would otherwise fill
+/// every batch and keep GC from reaching newer backtests.
+pub async fn find_backtest_gc_candidates(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    policy: &BacktestGcPolicy,
+) -> Result<Vec<BacktestGcCandidate>, Error> {
+    if policy.older_than.is_none() && policy.tags.is_empty() {
+        error!("Refusing to collect backtests without an age or tag filter");
+        return Err(anyhow::anyhow!("A GC policy needs older_than or tags"));
+    }
+    for t in policy.tags.iter().chain(&policy.protected_tags) {
+        validate_tag(t)?;
+    }
+    let cutoff = policy.older_than.map(|age| Utc::now() - age);
+    let limit = policy.max_backtests.clamp(1, 10000);
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let activity = SIM_TABLES
+            .iter()
+            .map(|t| format!("SELECT backtest_id, MAX(created_at) AS last_activity, TRUE AS has_sim_rows FROM {} GROUP BY backtest_id", t))
+            .chain(std::iter::once("SELECT backtest_id, updated_at AS last_activity, FALSE AS has_sim_rows FROM backtest_results".to_string()))
+            .collect::<Vec<_>>()
+            .join("\n                UNION ALL\n                ");
+
+        diesel::sql_query(format!(
+            r#"
+            WITH activity AS (
+                {}
+            ),
+            backtests AS (
+                SELECT backtest_id, MAX(last_activity) AS last_activity, BOOL_OR(has_sim_rows) AS has_sim_rows
+                FROM activity
+                GROUP BY backtest_id
+            )
+            SELECT b.backtest_id, b.last_activity
+            FROM backtests b
+            WHERE ($1::timestamptz IS NULL OR b.last_activity < $1)
+              AND (NOT $5 OR b.has_sim_rows)
+              AND (cardinality($2::text[]) = 0 OR EXISTS (
+                  SELECT 1 FROM backtest_tags t WHERE t.backtest_id = b.backtest_id AND t.tag = ANY($2)))
+              AND NOT EXISTS (
+                  SELECT 1 FROM backtest_tags t WHERE t.backtest_id = b.backtest_id AND t.tag = ANY($3))
+            ORDER BY b.last_activity ASC
+            LIMIT $4
+            "#,
+            activity
+        ))
+            .bind::<Nullable<Timestamptz>, _>(cutoff)
+            .bind::<Array<Text>, _>(&policy.tags)
+            .bind::<Array<Text>, _>(&policy.protected_tags)
+            .bind::<BigInt, _>(limit)
+            .bind::<Bool, _>(policy.keep_results)
+            .load::<BacktestGcCandidate>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error finding backtest GC candidates: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Purge every backtest matching `policy`, each in its own transaction so one failure does
+/// not roll back the others. Report files are deleted from `storage`. Failed purges are
+/// logged and left out of the report.
+pub async fn collect_backtest_garbage(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    storage: &dyn ReportStorage,
+    policy: &BacktestGcPolicy,
+) -> Result<BacktestGcReport, Error> {
+    let query_start = Instant::now();
+    let candidates = find_backtest_gc_candidates(pool.clone(), policy).await?;
+
+    if policy.dry_run {
+        info!("Backtest GC dry run: {} candidates", candidates.len());
+        return Ok(BacktestGcReport { candidates, purged: Vec::new() });
+    }
+
+    let mut purged = Vec::with_capacity(candidates.len());
+    for candidate in &candidates {
+        match purge_backtest(pool.clone(), storage, candidate.backtest_id, policy.keep_results).await {
+            Ok(report) => purged.push(report),
+            Err(e) => warn!("Skipping backtest {} during GC: {}", candidate.backtest_id, e),
+        }
+    }
+
+    info!("Backtest GC purged {} of {} candidates in {}ms", purged.len(), candidates.len(), query_start.elapsed().as_millis());
+    Ok(BacktestGcReport { candidates, purged })
+}
+
+/// Storage query over the backtest ids produced by `ids_sql` (a query with a `backtest_id` column)
+fn storage_report_query(ids_sql: &str, tail: &str) -> String {
+    let sim_counts = SIM_TABLES
+        .iter()
+        .zip(["sim_buy_orders", "sim_sell_orders", "sim_trades"])
+        .map(|(t, alias)| format!("(SELECT COUNT(*) FROM {} x WHERE x.backtest_id = ids.backtest_id) AS {}", t, alias))
+        .collect::<Vec<_>>()
+        .join(",\n                   ");
+    let sim_bytes = SIM_TABLES
+        .iter()
+        .map(|t| format!("COALESCE((SELECT SUM(pg_column_size(x.*)) FROM {} x WHERE x.backtest_id = ids.backtest_id), 0)", t))
+        .collect::<Vec<_>>()
+        .join("\n                     + ");
+    let child_rows = RESULT_CHILD_TABLES
+        .iter()
+        .map(|t| format!("(SELECT COUNT(*) FROM {} x WHERE x.backtest_result_id = r.id)", t))
+        .collect::<Vec<_>>()
+        .join("\n                     + ");
+    let child_bytes = RESULT_CHILD_TABLES
+        .iter()
+        .map(|t| format!("COALESCE((SELECT SUM(pg_column_size(x.*)) FROM {} x WHERE x.backtest_result_id = r.id), 0)", t))
+        .collect::<Vec<_>>()
+        .join("\n                     + ");
+
+    format!(
+        r#"
+        WITH ids AS (
+            {}
+        ),
+        sizes AS (
+            SELECT ids.backtest_id,
+                   {},
+                   ({})::BIGINT AS sim_bytes,
+                   (CASE WHEN r.id IS NULL THEN 0 ELSE 1
+                     + {} END)::BIGINT AS result_rows,
+                   (CASE WHEN r.id IS NULL THEN 0 ELSE pg_column_size(r.*)
+                     + {} END)::BIGINT AS result_bytes
+            FROM ids
+            LEFT JOIN backtest_results r ON r.backtest_id = ids.backtest_id
+        )
+        SELECT backtest_id, sim_buy_orders, sim_sell_orders, sim_trades, sim_bytes,
+               result_rows, result_bytes, sim_bytes + result_bytes AS total_bytes
+        FROM sizes
+        {}
+        "#,
+        ids_sql, sim_counts, sim_bytes, child_rows, child_bytes, tail
+    )
+}
+
+/// Rows and bytes held by one backtest across sim tables and result tables
+pub async fn get_backtest_storage_report(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    test_id: Uuid,
+) -> Result<BacktestStorageReport, Error> {
+    let query_start = Instant::now();
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let report = diesel::sql_query(storage_report_query("SELECT $1::uuid AS backtest_id", ""))
+            .bind::<SqlUuid, _>(test_id)
+            .get_result::<BacktestStorageReport>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error building storage report for backtest {}: {}", test_id, e);
+                anyhow::Error::from(e)
+            })?;
+
+        debug!("Built storage report for backtest {} in {}ms", test_id, query_start.elapsed().as_millis());
+        Ok(report)
+    }).await
+}
+
+/// Storage reports for every known backtest, largest first
+pub async fn list_backtest_storage(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    limit: i64,
+) -> Result<Vec<BacktestStorageReport>, Error> {
+    let query_start = Instant::now();
+    let limit = limit.clamp(1, 10000);
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let ids_sql = SIM_TABLES
+        .iter()
+        .map(|t| format!("SELECT DISTINCT backtest_id FROM {}", t))
+        .chain(std::iter::once("SELECT backtest_id FROM backtest_results".to_string()))
+        .collect::<Vec<_>>()
+        .join("\n            UNION\n            ");
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let reports = diesel::sql_query(storage_report_query(&ids_sql, "ORDER BY total_bytes DESC LIMIT $1"))
+            .bind::<BigInt, _>(limit)
+            .load::<BacktestStorageReport>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error listing backtest storage: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        info!("Listed storage for {} backtests in {}ms", reports.len(), query_start.elapsed().as_millis());
+        Ok(reports)
+    }).await
+}
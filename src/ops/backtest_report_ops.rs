@@ -0,0 +1,423 @@
+use crate::{
+    get_timescale_connection,
+    metrics,
+    models::backtest_result::{BacktestReport, NewBacktestReport, ReportFormat, ReportOptions},
+    ops::{backtest_result_ops, benchmark_ops},
+    report::{self, ReportData, REPORT_TEMPLATE_VERSION},
+    report_storage::ReportStorage,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, error, debug, warn};
+use uuid::Uuid;
+
+const REPORT_STATUSES: [&str; 4] = ["generating", "generated", "failed", "archived"];
+
+fn validate_report_options(options: &ReportOptions) -> Result<(), Error> {
+    if options.formats.is_empty() {
+        return Err(anyhow::anyhow!("A report needs at least one export format"));
+    }
+    if let Some(name) = &options.report_name {
+        if name.is_empty() || name.len() > 255 {
+            error!("Invalid report name length: {}", name.len());
+            return Err(anyhow::anyhow!("Invalid report name length: {}", name.len()));
+        }
+    }
+    if let Some(tf) = &options.timeframe {
+        if tf.is_empty() || tf.len() > 20 {
+            return Err(anyhow::anyhow!("Invalid timeframe length: {}", tf.len()));
+        }
+    }
+    if options.generated_by.as_ref().is_some_and(|g| g.len() > 255) {
+        return Err(anyhow::anyhow!("generated_by exceeds 255 characters"));
+    }
+    if options.generation_source.is_empty() || options.generation_source.len() > 50 {
+        return Err(anyhow::anyhow!("Invalid generation source length: {}", options.generation_source.len()));
+    }
+    Ok(())
+}
+
+/// Build HTML, JSON and/or CSV reports for a stored backtest result, write them to
+/// `storage` and record them in `backtest_reports` under its `storage_location`.
+///
+/// The report row is inserted as `generating` with its summaries before any file is
+/// written, then set to `generated` with file paths and sizes. If rendering or storage
+/// fails the row is set to `failed` with the error, files already written are removed and
+/// the error is returned.
+pub async fn generate_backtest_report(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    storage: &dyn ReportStorage,
+    result_id: Uuid,
+    options: ReportOptions,
+) -> Result<BacktestReport, Error> {
+    let query_start = Instant::now();
+    validate_report_options(&options)?;
+    let mut formats: Vec<ReportFormat> = Vec::new();
+    for f in &options.formats {
+        if !formats.contains(f) {
+            formats.push(*f);
+        }
+    }
+
+    let result = backtest_result_ops::get_backtest_result(pool.clone(), result_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Backtest result {} not found", result_id))?;
+    let equity_curve = backtest_result_ops::get_full_backtest_equity_curve(pool.clone(), result_id, None, None).await?;
+    let drawdown_periods = backtest_result_ops::get_backtest_drawdown_periods(pool.clone(), result_id, None, None).await?;
+    let (trades, positions) = if options.include_trades {
+        (
+            backtest_result_ops::get_full_backtest_trades(pool.clone(), result_id, None, None).await?,
+            backtest_result_ops::get_full_backtest_position_history(pool.clone(), result_id, None, None).await?,
+        )
+    } else {
+        (vec![], vec![])
+    };
+
+    let timeframe = options.timeframe.clone().unwrap_or_else(|| {
+        let spacing = metrics::median_spacing_seconds(&metrics::equity_points(&equity_curve));
+        benchmark_ops::timeframe_for_spacing(spacing.unwrap_or(86400)).to_string()
+    });
+    let report_name = options
+        .report_name
+        .clone()
+        .unwrap_or_else(|| format!("{} {} backtest", result.strategy_name, result.symbol));
+    let summaries = report::summarize(&result, &equity_curve, &drawdown_periods);
+    let generated_at = Utc::now();
+    let report_key = format!("bt-{}-{}", generated_at.format("%Y%m%d%H%M%S"), &Uuid::new_v4().simple().to_string()[..8]);
+
+    let new_report = NewBacktestReport {
+        backtest_result_id: result_id,
+        report_id: report_key.clone(),
+        report_name: report_name.chars().take(255).collect(),
+        strategy_name: result.strategy_name.clone(),
+        symbol: result.symbol.chars().take(50).collect(),
+        timeframe: timeframe.clone(),
+        start_date: result.start_date.date_naive(),
+        end_date: result.end_date.date_naive(),
+        initial_capital: result.initial_capital.clone(),
+        generated_at,
+        generated_by: options.generated_by.clone(),
+        generation_source: options.generation_source.clone(),
+        backtest_duration_seconds: None,
+        data_points: Some(equity_curve.len() as i32),
+        include_trades: options.include_trades,
+        include_charts: options.include_charts,
+        export_formats: Some(formats.iter().map(|f| Some(f.name().to_string())).collect()),
+        custom_css: options.custom_css.clone(),
+        template_version: Some(REPORT_TEMPLATE_VERSION.to_string()),
+        file_paths: serde_json::json!({}),
+        file_sizes: None,
+        storage_location: storage.location().to_string(),
+        performance_summary: summaries.performance.clone(),
+        risk_summary: summaries.risk.clone(),
+        trade_summary: summaries.trade.clone(),
+        status: "generating".to_string(),
+        error_message: None,
+        tags: (!options.tags.is_empty()).then(|| options.tags.iter().map(|t| Some(t.clone())).collect()),
+        notes: options.notes.clone(),
+    };
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let report_row = Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_reports::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::insert_into(backtest_reports)
+            .values(&new_report)
+            .returning(BacktestReport::as_returning())
+            .get_result::<BacktestReport>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error recording report {} for backtest result {}: {}", report_key, result_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+    debug!("Recorded report {} for backtest result {} as generating", report_key, result_id);
+
+    let data = ReportData {
+        report_id: report_key.clone(),
+        report_name,
+        timeframe,
+        generated_at,
+        result,
+        equity_curve,
+        drawdown_periods,
+        trades,
+        positions,
+        include_trades: options.include_trades,
+        include_charts: options.include_charts,
+        custom_css: options.custom_css.clone(),
+    };
+
+    let mut paths = serde_json::Map::new();
+    let mut sizes = serde_json::Map::new();
+    let mut written: Vec<String> = Vec::new();
+    for format in &formats {
+        let key = format!("{}/report.{}", report_key, format.extension());
+        let stored = match report::render(&data, &summaries, *format) {
+            Ok(bytes) => match storage.put(&key, &bytes, format.content_type()).await {
+                // Stored locators must map back to their key for later reads
+                Ok(path) if storage.key_for_path(&path).as_deref() != Some(key.as_str()) => {
+                    let _ = storage.delete(&key).await;
+                    Err(anyhow::anyhow!("Stored path {} does not resolve to {}", path, key))
+                }
+                stored => stored.map(|path| (path, bytes.len())),
+            },
+            Err(e) => Err(e),
+        };
+        match stored {
+            Ok((path, size)) => {
+                written.push(key);
+                paths.insert(format.name().to_string(), serde_json::Value::from(path));
+                sizes.insert(format.name().to_string(), serde_json::Value::from(size));
+            }
+            Err(e) => {
+                error!("Error writing {} report {}: {}", format.name(), report_key, e);
+                for key in &written {
+                    if let Err(cleanup) = storage.delete(key).await {
+                        warn!("Could not remove {} after failed report: {}", key, cleanup);
+                    }
+                }
+                let message = format!("Writing {} report failed: {}", format.name(), e);
+                if let Err(status_error) = mark_report_failed(pool.clone(), report_row.id, &message).await {
+                    error!("Error marking report {} failed: {}", report_key, status_error);
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    let file_paths_value = serde_json::Value::Object(paths);
+    let file_sizes_value = serde_json::Value::Object(sizes);
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let generated = Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_reports::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::update(backtest_reports.find(report_row.id))
+            .set((
+                file_paths.eq(&file_paths_value),
+                file_sizes.eq(Some(&file_sizes_value)),
+                status.eq("generated"),
+                updated_at.eq(Utc::now()),
+            ))
+            .returning(BacktestReport::as_returning())
+            .get_result::<BacktestReport>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error marking report {} generated: {}", report_key, e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+
+    info!("Generated report {} ({}) for backtest result {} in {}ms",
+        report_key, formats.iter().map(|f| f.name()).collect::<Vec<_>>().join(", "), result_id, query_start.elapsed().as_millis());
+    Ok(generated)
+}
+
+async fn mark_report_failed(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    row_id: Uuid,
+    message: &str,
+) -> Result<usize, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_reports::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::update(backtest_reports.find(row_id))
+            .set((status.eq("failed"), error_message.eq(Some(message)), updated_at.eq(Utc::now())))
+            .execute(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Get a report row by its primary key
+pub async fn get_backtest_report(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    id_value: Uuid,
+) -> Result<Option<BacktestReport>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_reports::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        backtest_reports
+            .find(id_value)
+            .select(BacktestReport::as_select())
+            .first::<BacktestReport>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| {
+                error!("Error getting report {}: {}", id_value, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Get a report row by its `report_id` string
+pub async fn get_backtest_report_by_report_id(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    report_key: &str,
+) -> Result<Option<BacktestReport>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_reports::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        backtest_reports
+            .filter(report_id.eq(report_key))
+            .select(BacktestReport::as_select())
+            .first::<BacktestReport>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| {
+                error!("Error getting report {}: {}", report_key, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Reports of a backtest result, newest first, optionally only those with `status_filter`
+pub async fn get_reports_for_backtest_result(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    status_filter: Option<&str>,
+) -> Result<Vec<BacktestReport>, Error> {
+    if let Some(s) = status_filter {
+        if !REPORT_STATUSES.contains(&s) {
+            error!("Invalid report status: {}", s);
+            return Err(anyhow::anyhow!("Invalid report status: {}", s));
+        }
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_reports::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        let mut query = backtest_reports
+            .filter(backtest_result_id.eq(result_id))
+            .into_boxed();
+        if let Some(s) = status_filter {
+            query = query.filter(status.eq(s));
+        }
+        query
+            .order(generated_at.desc())
+            .select(BacktestReport::as_select())
+            .load::<BacktestReport>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error getting reports for backtest result {}: {}", result_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Storage key of a report's file in `format`, checking that the report lives in `storage`
+fn report_file_key(storage: &dyn ReportStorage, report: &BacktestReport, format: ReportFormat) -> Result<String, Error> {
+    if report.storage_location != storage.location() {
+        return Err(anyhow::anyhow!("Report {} is stored in {}, not {}", report.report_id, report.storage_location, storage.location()));
+    }
+    let path = report
+        .file_paths
+        .get(format.name())
+        .and_then(|p| p.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Report {} has no {} file", report.report_id, format.name()))?;
+    storage
+        .key_for_path(path)
+        .ok_or_else(|| anyhow::anyhow!("File path {} of report {} does not resolve in {} storage", path, report.report_id, storage.location()))
+}
+
+async fn load_generated_report(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    id_value: Uuid,
+) -> Result<BacktestReport, Error> {
+    let report = get_backtest_report(pool, id_value)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Report {} not found", id_value))?;
+    if report.status != "generated" {
+        return Err(anyhow::anyhow!("Report {} is {}", report.report_id, report.status));
+    }
+    Ok(report)
+}
+
+/// Contents of a generated report's file in `format`
+pub async fn get_report_file(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    storage: &dyn ReportStorage,
+    id_value: Uuid,
+    format: ReportFormat,
+) -> Result<Vec<u8>, Error> {
+    let report = load_generated_report(pool, id_value).await?;
+    let key = report_file_key(storage, &report, format)?;
+    debug!("Reading {} file of report {} from {}", format.name(), report.report_id, key);
+    storage.get(&key).await
+}
+
+/// Time-limited URL of a generated report's file in `format`, for handing to clients
+pub async fn get_report_url(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    storage: &dyn ReportStorage,
+    id_value: Uuid,
+    format: ReportFormat,
+    expires_in: Duration,
+) -> Result<String, Error> {
+    let report = load_generated_report(pool, id_value).await?;
+    let key = report_file_key(storage, &report, format)?;
+    storage.presigned_url(&key, expires_in).await
+}
+
+/// Delete a report's files from `storage` and then its row. Returns false when there is
+/// no such report.
+pub async fn delete_backtest_report(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    storage: &dyn ReportStorage,
+    id_value: Uuid,
+) -> Result<bool, Error> {
+    let Some(report) = get_backtest_report(pool.clone(), id_value).await? else {
+        return Ok(false);
+    };
+    if report.storage_location != storage.location() {
+        return Err(anyhow::anyhow!("Report {} is stored in {}, not {}", report.report_id, report.storage_location, storage.location()));
+    }
+    if let Some(paths) = report.file_paths.as_object() {
+        for path in paths.values().filter_map(|p| p.as_str()) {
+            match storage.key_for_path(path) {
+                Some(key) => storage.delete(&key).await?,
+                None => warn!("File path {} of report {} does not resolve; leaving it in place", path, report.report_id),
+            }
+        }
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let deleted = Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_reports::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::delete(backtest_reports.find(id_value))
+            .execute(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error deleting report {}: {}", id_value, e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+
+    info!("Deleted report {} and its files from {} storage", report.report_id, storage.location());
+    Ok(deleted > 0)
+}
@@ -1,64 +1,222 @@
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::sync::Arc;
 use std::time::Instant;
 use anyhow::Error;
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use diesel::upsert::excluded;
-use diesel_async::RunQueryDsl;
-use tokio_retry::{strategy::ExponentialBackoff, Retry, strategy::jitter};
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio_retry::{strategy::ExponentialBackoff, Retry, RetryIf, strategy::jitter};
 use uuid::Uuid;
 use ultra_logger::UltraLogger;
 
 use crate::models::backtest_result::{
-    BacktestResult, NewBacktestResult
+    BacktestDrawdownPeriod, BacktestEquityCurve, BacktestPositionHistory, BacktestResult, BacktestTrade,
+    BacktestWriteMode, BacktestWriteOutcome, StoredBacktestResult, NewBacktestBundle,
+    BacktestResultPage, BacktestResultQuery, SortDirection, NewBacktestDrawdownPeriod, NewBacktestEquityCurve, NewBacktestPositionHistory,
+    NewBacktestResult, NewBacktestTrade, SavedBacktest
 };
+use crate::errors::DatabaseError;
 use crate::get_timescale_connection;
+use crate::metrics::{self, BacktestMetrics, MetricsConfig};
+use crate::ops::benchmark_ops;
 use diesel_async::pooled_connection::deadpool;
 use diesel_async::AsyncPgConnection;
 
 
-/// Create a new backtest result
+/// Default and maximum page sizes for `search_backtest_results`
+const DEFAULT_SEARCH_LIMIT: i64 = 100;
+const MAX_SEARCH_LIMIT: i64 = 1000;
+
+fn validate_backtest_result_query(query: &BacktestResultQuery) -> Result<(), Error> {
+    if query.strategy_name.as_ref().is_some_and(|s| s.is_empty() || s.len() > 100) {
+        return Err(anyhow::anyhow!("Strategy name must be 1-100 characters"));
+    }
+    if query.symbol.as_ref().is_some_and(|s| s.is_empty() || s.len() > 20) {
+        return Err(anyhow::anyhow!("Symbol must be 1-20 characters"));
+    }
+    if let (Some(start), Some(end)) = (query.window_start, query.window_end) {
+        if start >= end {
+            return Err(anyhow::anyhow!("Window start must be before window end"));
+        }
+    }
+    if query.thresholds.iter().any(|t| !t.value.is_finite()) {
+        return Err(anyhow::anyhow!("Metric thresholds must be finite"));
+    }
+    if query.limit.is_some_and(|l| l <= 0) || query.offset < 0 {
+        return Err(anyhow::anyhow!("Limit must be positive and offset non-negative"));
+    }
+    Ok(())
+}
+
+/// `backtest_results` narrowed by every filter of `query`, without ordering or paging.
+/// Metric columns come from the `BacktestMetric` whitelist, so the SQL fragments are safe.
+fn filtered_backtest_results(query: &BacktestResultQuery) -> crate::schema::backtest_results::BoxedQuery<'static, diesel::pg::Pg> {
+    use crate::schema::backtest_results::dsl::*;
+    use crate::schema::backtest_tags;
+    use diesel::sql_types::{Bool, Double};
+
+    let mut boxed = backtest_results.into_boxed();
+    if let Some(name) = &query.strategy_name {
+        boxed = boxed.filter(strategy_name.eq(name.clone()));
+    }
+    if let Some(sym) = &query.symbol {
+        boxed = boxed.filter(symbol.eq(sym.clone()));
+    }
+    if let Some(instance) = query.strategy_instance_id {
+        boxed = boxed.filter(strategy_instance_id.eq(instance));
+    }
+    if let Some(t) = &query.tag {
+        boxed = boxed.filter(backtest_id.eq_any(
+            backtest_tags::table
+                .filter(backtest_tags::tag.eq(t.clone()))
+                .select(backtest_tags::backtest_id),
+        ));
+    }
+    if let Some(start) = query.window_start {
+        boxed = boxed.filter(start_date.ge(start));
+    }
+    if let Some(end) = query.window_end {
+        boxed = boxed.filter(end_date.le(end));
+    }
+    for threshold in &query.thresholds {
+        boxed = boxed.filter(
+            diesel::dsl::sql::<Bool>(&format!("{} {} ", threshold.metric.column(), threshold.comparison.operator()))
+                .bind::<Double, _>(threshold.value),
+        );
+    }
+    boxed
+}
+
+/// Whether `error` is the `FailIfExists` rejection, which retrying cannot fix
+fn is_duplicate_entry(error: &Error) -> bool {
+    matches!(error.downcast_ref::<DatabaseError>(), Some(DatabaseError::DuplicateEntry(_)))
+}
+
+/// Write `new_result` according to `mode` without touching child rows, except that
+/// `Replace` deletes the children of the overwritten result. `FailIfExists` on a stored
+/// `backtest_id` fails with `DatabaseError::DuplicateEntry`.
+async fn write_backtest_result(
+    conn: &mut AsyncPgConnection,
+    new_result: &NewBacktestResult,
+    mode: BacktestWriteMode,
+) -> Result<(BacktestResult, BacktestWriteOutcome), Error> {
+    use crate::schema::backtest_results::dsl::*;
+
+    match mode {
+        BacktestWriteMode::FailIfExists => {
+            let inserted = diesel::insert_into(backtest_results)
+                .values(new_result)
+                .on_conflict(backtest_id)
+                .do_nothing()
+                .get_result::<BacktestResult>(conn)
+                .await
+                .optional()?;
+            match inserted {
+                Some(result) => Ok((result, BacktestWriteOutcome::Inserted)),
+                None => Err(DatabaseError::DuplicateEntry(format!(
+                    "Backtest result {} already exists", new_result.backtest_id
+                )).into()),
+            }
+        }
+        BacktestWriteMode::KeepExisting => {
+            let inserted = diesel::insert_into(backtest_results)
+                .values(new_result)
+                .on_conflict(backtest_id)
+                .do_nothing()
+                .get_result::<BacktestResult>(conn)
+                .await
+                .optional()?;
+            match inserted {
+                Some(result) => Ok((result, BacktestWriteOutcome::Inserted)),
+                None => {
+                    let result = backtest_results
+                        .filter(backtest_id.eq(new_result.backtest_id))
+                        .first::<BacktestResult>(conn)
+                        .await?;
+                    Ok((result, BacktestWriteOutcome::KeptExisting))
+                }
+            }
+        }
+        BacktestWriteMode::Replace => {
+            let existing = backtest_results
+                .filter(backtest_id.eq(new_result.backtest_id))
+                .for_update()
+                .first::<BacktestResult>(conn)
+                .await
+                .optional()?;
+            match existing {
+                None => {
+                    let result = diesel::insert_into(backtest_results)
+                        .values(new_result)
+                        .get_result::<BacktestResult>(conn)
+                        .await?;
+                    Ok((result, BacktestWriteOutcome::Inserted))
+                }
+                Some(existing) => {
+                    let mut changes = new_result.clone();
+                    changes.created_at = existing.created_at;
+                    changes.updated_at = Utc::now();
+                    let result = diesel::update(backtest_results.find(existing.id))
+                        .set(&changes)
+                        .get_result::<BacktestResult>(conn)
+                        .await?;
+                    delete_backtest_children(conn, existing.id).await?;
+                    Ok((result, BacktestWriteOutcome::Replaced))
+                }
+            }
+        }
+    }
+}
+
+async fn delete_backtest_children(conn: &mut AsyncPgConnection, result_id: Uuid) -> Result<usize, diesel::result::Error> {
+    use crate::schema::{backtest_drawdown_periods, backtest_equity_curve, backtest_position_history, backtest_trades};
+
+    let mut deleted = diesel::delete(backtest_trades::table.filter(backtest_trades::backtest_result_id.eq(result_id)))
+        .execute(conn)
+        .await?;
+    deleted += diesel::delete(backtest_equity_curve::table.filter(backtest_equity_curve::backtest_result_id.eq(result_id)))
+        .execute(conn)
+        .await?;
+    deleted += diesel::delete(backtest_position_history::table.filter(backtest_position_history::backtest_result_id.eq(result_id)))
+        .execute(conn)
+        .await?;
+    deleted += diesel::delete(backtest_drawdown_periods::table.filter(backtest_drawdown_periods::backtest_result_id.eq(result_id)))
+        .execute(conn)
+        .await?;
+    Ok(deleted)
+}
+
+/// Create a backtest result, resolving an existing result with the same `backtest_id`
+/// according to `mode`. `Replace` overwrites every metric and deletes the old result's
+/// trades, equity curve, positions and drawdown periods; use `save_full_backtest` to
+/// write the new children in the same transaction.
 pub async fn create_backtest_result(
     pool: Arc<deadpool::Pool<AsyncPgConnection>>,
     new_result: NewBacktestResult,
-) -> Result<BacktestResult, Error> {
+    mode: BacktestWriteMode,
+) -> Result<StoredBacktestResult, Error> {
     let start_time = Instant::now();
     let logger = UltraLogger::new("databaseschema".to_string());
-    let _ = logger.info(format!("Creating backtest result for strategy: {}", new_result.strategy_name)).await;
-    use crate::schema::backtest_results::dsl::*;
+    let _ = logger.info(format!("Creating backtest result for strategy: {} ({:?})", new_result.strategy_name, mode)).await;
 
     let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
 
-    Retry::spawn(retry_strategy, || async {
-        let mut connection = get_timescale_connection(pool.clone())
-            .await
-            .map_err(|e| {
-                let logger = UltraLogger::new("databaseschema".to_string());
-                let _ = logger.error(format!("Failed to get database connection: {}", e));
-                anyhow::Error::from(e)
-            })?;
+    let result = RetryIf::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
 
-        let result = diesel::insert_into(backtest_results)
-            .values(&new_result)
-            .on_conflict(backtest_id)
-            .do_update()
-            .set((
-                strategy_name.eq(excluded(strategy_name)),
-                symbol.eq(excluded(symbol)),
-                total_return.eq(excluded(total_return)),
-                updated_at.eq(excluded(updated_at)),
-            ))
-            .get_result::<BacktestResult>(&mut connection)
+        connection
+            .transaction::<_, Error, _>(|conn| Box::pin(write_backtest_result(conn, &new_result, mode)))
             .await
-            .map_err(|e| {
-                let logger = UltraLogger::new("databaseschema".to_string());
-                let _ = logger.error(format!("Error creating backtest result: {}", e));
-                anyhow::Error::from(e)
-            })?;
-            
-        let logger = UltraLogger::new("databaseschema".to_string());
-        let _ = logger.debug(format!("Created backtest result in {}ms", start_time.elapsed().as_millis())).await;
-        Ok(result)
-    }).await
+            .map(|(result, outcome)| StoredBacktestResult { result, outcome })
+    }, |e: &Error| !is_duplicate_entry(e)).await;
+
+    match &result {
+        Ok(stored) => { let _ = logger.debug(format!("Backtest result {} {:?} in {}ms", stored.result.backtest_id, stored.outcome, start_time.elapsed().as_millis())).await; },
+        Err(e) => { let _ = logger.error(format!("Error creating backtest result {}: {}", new_result.backtest_id, e)).await; },
+    }
+    result
 }
 
 /// Get backtest result by ID
@@ -151,7 +309,7 @@ pub async fn get_backtest_results_by_strategy(
     // Input validation
     if strategy.is_empty() || strategy.len() > 100 {
         let logger = UltraLogger::new("databaseschema".to_string());
-        let _ = logger.error(format!("Invalid strategy name length")).await;
+        let _ = logger.error("Invalid strategy name length".to_string()).await;
         return Err(anyhow::anyhow!("Strategy name must be 1-100 characters"));
     }
     
@@ -186,3 +344,626 @@ pub async fn get_backtest_results_by_strategy(
         Ok(results)
     }).await
 }
+
+/// Rows per INSERT statement for child tables, well under PostgreSQL's bind parameter limit
+const CHILD_INSERT_CHUNK: usize = 1000;
+
+/// Upper bound on rows returned by the ranged child table reads
+const MAX_CHILD_ROWS: i64 = 100000;
+
+async fn insert_backtest_trades(conn: &mut AsyncPgConnection, rows: &[NewBacktestTrade]) -> Result<usize, diesel::result::Error> {
+    use crate::schema::backtest_trades::dsl::*;
+    let mut inserted = 0;
+    for chunk in rows.chunks(CHILD_INSERT_CHUNK) {
+        inserted += diesel::insert_into(backtest_trades).values(chunk).execute(conn).await?;
+    }
+    Ok(inserted)
+}
+
+/// Keep the last row for each key, in input order. One upsert statement cannot update the
+/// same row twice, so duplicate keys must be collapsed before the rows are chunked.
+fn last_per_key<T, K: Eq + Hash>(rows: &[T], key: impl Fn(&T) -> K) -> Vec<&T> {
+    let mut last = HashMap::with_capacity(rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        last.insert(key(row), i);
+    }
+    rows.iter()
+        .enumerate()
+        .filter(|(i, row)| last.get(&key(row)) == Some(i))
+        .map(|(_, row)| row)
+        .collect()
+}
+
+async fn insert_backtest_equity_curve(conn: &mut AsyncPgConnection, rows: &[NewBacktestEquityCurve]) -> Result<usize, diesel::result::Error> {
+    use crate::schema::backtest_equity_curve::dsl::*;
+    let rows = last_per_key(rows, |row| (row.backtest_result_id, row.timestamp));
+    let mut inserted = 0;
+    for chunk in rows.chunks(CHILD_INSERT_CHUNK) {
+        inserted += diesel::insert_into(backtest_equity_curve)
+            .values(chunk.to_vec())
+            .on_conflict((backtest_result_id, timestamp))
+            .do_update()
+            .set(portfolio_value.eq(excluded(portfolio_value)))
+            .execute(conn)
+            .await?;
+    }
+    Ok(inserted)
+}
+
+async fn insert_backtest_position_history(conn: &mut AsyncPgConnection, rows: &[NewBacktestPositionHistory]) -> Result<usize, diesel::result::Error> {
+    use crate::schema::backtest_position_history::dsl::*;
+    let rows = last_per_key(rows, |row| (row.backtest_result_id, row.timestamp, row.symbol.clone()));
+    let mut inserted = 0;
+    for chunk in rows.chunks(CHILD_INSERT_CHUNK) {
+        inserted += diesel::insert_into(backtest_position_history)
+            .values(chunk.to_vec())
+            .on_conflict((backtest_result_id, timestamp, symbol))
+            .do_update()
+            .set((
+                quantity.eq(excluded(quantity)),
+                average_price.eq(excluded(average_price)),
+                current_price.eq(excluded(current_price)),
+                unrealized_pnl.eq(excluded(unrealized_pnl)),
+                direction.eq(excluded(direction)),
+            ))
+            .execute(conn)
+            .await?;
+    }
+    Ok(inserted)
+}
+
+async fn insert_backtest_drawdown_periods(conn: &mut AsyncPgConnection, rows: &[NewBacktestDrawdownPeriod]) -> Result<usize, diesel::result::Error> {
+    use crate::schema::backtest_drawdown_periods::dsl::*;
+    let mut inserted = 0;
+    for chunk in rows.chunks(CHILD_INSERT_CHUNK) {
+        inserted += diesel::insert_into(backtest_drawdown_periods).values(chunk).execute(conn).await?;
+    }
+    Ok(inserted)
+}
+
+/// Insert backtest trades in one transaction
+pub async fn create_backtest_trades(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    rows: Vec<NewBacktestTrade>,
+) -> Result<usize, Error> {
+    let start_time = Instant::now();
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+        connection
+            .transaction::<_, diesel::result::Error, _>(|conn| Box::pin(insert_backtest_trades(conn, &rows)))
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    match &result {
+        Ok(n) => { let _ = logger.debug(format!("Inserted {} backtest trades in {}ms", n, start_time.elapsed().as_millis())).await; },
+        Err(e) => { let _ = logger.error(format!("Error inserting backtest trades: {}", e)).await; },
+    }
+    result
+}
+
+/// Insert equity curve points in one transaction; a point at an existing timestamp replaces it
+pub async fn create_backtest_equity_curve(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    rows: Vec<NewBacktestEquityCurve>,
+) -> Result<usize, Error> {
+    let start_time = Instant::now();
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+        connection
+            .transaction::<_, diesel::result::Error, _>(|conn| Box::pin(insert_backtest_equity_curve(conn, &rows)))
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    match &result {
+        Ok(n) => { let _ = logger.debug(format!("Inserted {} equity curve points in {}ms", n, start_time.elapsed().as_millis())).await; },
+        Err(e) => { let _ = logger.error(format!("Error inserting equity curve: {}", e)).await; },
+    }
+    result
+}
+
+/// Insert position snapshots in one transaction; a snapshot for an existing
+/// `(timestamp, symbol)` replaces it
+pub async fn create_backtest_position_history(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    rows: Vec<NewBacktestPositionHistory>,
+) -> Result<usize, Error> {
+    let start_time = Instant::now();
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+        connection
+            .transaction::<_, diesel::result::Error, _>(|conn| Box::pin(insert_backtest_position_history(conn, &rows)))
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    match &result {
+        Ok(n) => { let _ = logger.debug(format!("Inserted {} position snapshots in {}ms", n, start_time.elapsed().as_millis())).await; },
+        Err(e) => { let _ = logger.error(format!("Error inserting position history: {}", e)).await; },
+    }
+    result
+}
+
+/// Insert drawdown periods in one transaction
+pub async fn create_backtest_drawdown_periods(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    rows: Vec<NewBacktestDrawdownPeriod>,
+) -> Result<usize, Error> {
+    let start_time = Instant::now();
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+        connection
+            .transaction::<_, diesel::result::Error, _>(|conn| Box::pin(insert_backtest_drawdown_periods(conn, &rows)))
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    match &result {
+        Ok(n) => { let _ = logger.debug(format!("Inserted {} drawdown periods in {}ms", n, start_time.elapsed().as_millis())).await; },
+        Err(e) => { let _ = logger.error(format!("Error inserting drawdown periods: {}", e)).await; },
+    }
+    result
+}
+
+/// Write a backtest result and all of its child rows in one transaction, so a failure
+/// leaves nothing behind. An existing result with the same `backtest_id` is handled
+/// according to `mode`; with `Replace` its child rows are swapped for the bundle's.
+pub async fn save_full_backtest(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    bundle: NewBacktestBundle,
+    mode: BacktestWriteMode,
+) -> Result<SavedBacktest, Error> {
+    let start_time = Instant::now();
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.info(format!(
+        "Saving backtest {} with {} trades, {} equity points, {} positions, {} drawdowns",
+        bundle.result.backtest_id, bundle.trades.len(), bundle.equity_curve.len(),
+        bundle.positions.len(), bundle.drawdown_periods.len()
+    )).await;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = RetryIf::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        connection.transaction::<_, Error, _>(|conn| Box::pin(async {
+            let (result, outcome) = write_backtest_result(conn, &bundle.result, mode).await?;
+            if outcome == BacktestWriteOutcome::KeptExisting {
+                return Ok(SavedBacktest { result, outcome, trades: 0, equity_points: 0, positions: 0, drawdown_periods: 0 });
+            }
+
+            let mut trades = bundle.trades.clone();
+            trades.iter_mut().for_each(|r| r.backtest_result_id = result.id);
+            let mut equity_curve = bundle.equity_curve.clone();
+            equity_curve.iter_mut().for_each(|r| r.backtest_result_id = result.id);
+            let mut positions = bundle.positions.clone();
+            positions.iter_mut().for_each(|r| r.backtest_result_id = result.id);
+            let mut drawdown_periods = bundle.drawdown_periods.clone();
+            drawdown_periods.iter_mut().for_each(|r| r.backtest_result_id = result.id);
+
+            Ok(SavedBacktest {
+                trades: insert_backtest_trades(conn, &trades).await?,
+                equity_points: insert_backtest_equity_curve(conn, &equity_curve).await?,
+                positions: insert_backtest_position_history(conn, &positions).await?,
+                drawdown_periods: insert_backtest_drawdown_periods(conn, &drawdown_periods).await?,
+                result,
+                outcome,
+            })
+        }))
+        .await
+    }, |e: &Error| !is_duplicate_entry(e)).await;
+
+    match &result {
+        Ok(saved) => { let _ = logger.info(format!("Saved backtest {} ({:?}) in {}ms", saved.result.backtest_id, saved.outcome, start_time.elapsed().as_millis())).await; },
+        Err(e) => { let _ = logger.error(format!("Error saving backtest {}: {}", bundle.result.backtest_id, e)).await; },
+    }
+    result
+}
+
+/// Trades of a backtest result within `[start, end]`, oldest first. Returns at most
+/// `MAX_CHILD_ROWS` rows; use `get_full_backtest_trades` to read them all.
+pub async fn get_backtest_trades(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> Result<Vec<BacktestTrade>, Error> {
+    let limit = limit.unwrap_or(MAX_CHILD_ROWS).clamp(1, MAX_CHILD_ROWS);
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_trades::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        let mut query = backtest_trades
+            .filter(backtest_result_id.eq(result_id))
+            .order((timestamp.asc(), id.asc()))
+            .limit(limit)
+            .select(BacktestTrade::as_select())
+            .into_boxed();
+        if let Some(s) = start {
+            query = query.filter(timestamp.ge(s));
+        }
+        if let Some(e) = end {
+            query = query.filter(timestamp.le(e));
+        }
+
+        query.load::<BacktestTrade>(&mut connection).await.map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Equity curve of a backtest result within `[start, end]`, oldest first. Returns at most
+/// `MAX_CHILD_ROWS` points; use `get_full_backtest_equity_curve` to read them all.
+pub async fn get_backtest_equity_curve(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> Result<Vec<BacktestEquityCurve>, Error> {
+    let limit = limit.unwrap_or(MAX_CHILD_ROWS).clamp(1, MAX_CHILD_ROWS);
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_equity_curve::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        let mut query = backtest_equity_curve
+            .filter(backtest_result_id.eq(result_id))
+            .order(timestamp.asc())
+            .limit(limit)
+            .select(BacktestEquityCurve::as_select())
+            .into_boxed();
+        if let Some(s) = start {
+            query = query.filter(timestamp.ge(s));
+        }
+        if let Some(e) = end {
+            query = query.filter(timestamp.le(e));
+        }
+
+        query.load::<BacktestEquityCurve>(&mut connection).await.map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Position snapshots of a backtest result within `[start, end]`, optionally for one symbol.
+/// Returns at most `MAX_CHILD_ROWS` rows; use `get_full_backtest_position_history` to read
+/// them all.
+pub async fn get_backtest_position_history(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    sym: Option<&str>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> Result<Vec<BacktestPositionHistory>, Error> {
+    let limit = limit.unwrap_or(MAX_CHILD_ROWS).clamp(1, MAX_CHILD_ROWS);
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_position_history::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        let mut query = backtest_position_history
+            .filter(backtest_result_id.eq(result_id))
+            .order((timestamp.asc(), symbol.asc()))
+            .limit(limit)
+            .select(BacktestPositionHistory::as_select())
+            .into_boxed();
+        if let Some(s) = sym {
+            query = query.filter(symbol.eq(s));
+        }
+        if let Some(s) = start {
+            query = query.filter(timestamp.ge(s));
+        }
+        if let Some(e) = end {
+            query = query.filter(timestamp.le(e));
+        }
+
+        query.load::<BacktestPositionHistory>(&mut connection).await.map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Drawdown periods of a backtest result that started within `[start, end]`, oldest first
+pub async fn get_backtest_drawdown_periods(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<BacktestDrawdownPeriod>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_drawdown_periods::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        let mut query = backtest_drawdown_periods
+            .filter(backtest_result_id.eq(result_id))
+            .order(start_date.asc())
+            .limit(MAX_CHILD_ROWS)
+            .select(BacktestDrawdownPeriod::as_select())
+            .into_boxed();
+        if let Some(s) = start {
+            query = query.filter(start_date.ge(s));
+        }
+        if let Some(e) = end {
+            query = query.filter(start_date.le(e));
+        }
+
+        query.load::<BacktestDrawdownPeriod>(&mut connection).await.map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Every trade of a backtest result within `[start, end]`, oldest first. Reads in pages of
+/// `MAX_CHILD_ROWS` keyed on `(timestamp, id)`, so unlike `get_backtest_trades` the result
+/// is never truncated.
+pub async fn get_full_backtest_trades(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<BacktestTrade>, Error> {
+    let mut rows: Vec<BacktestTrade> = Vec::new();
+    loop {
+        let after = rows.last().map(|t| (t.timestamp, t.id));
+        let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+        let page = Retry::spawn(retry_strategy, || async {
+            use crate::schema::backtest_trades::dsl::*;
+            let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+            let mut query = backtest_trades
+                .filter(backtest_result_id.eq(result_id))
+                .order((timestamp.asc(), id.asc()))
+                .limit(MAX_CHILD_ROWS)
+                .select(BacktestTrade::as_select())
+                .into_boxed();
+            if let Some((ts, last_id)) = after {
+                query = query.filter(timestamp.gt(ts).or(timestamp.eq(ts).and(id.gt(last_id))));
+            }
+            if let Some(s) = start {
+                query = query.filter(timestamp.ge(s));
+            }
+            if let Some(e) = end {
+                query = query.filter(timestamp.le(e));
+            }
+
+            query.load::<BacktestTrade>(&mut connection).await.map_err(|e| anyhow::Error::from(e))
+        }).await?;
+
+        let last_page = (page.len() as i64) < MAX_CHILD_ROWS;
+        rows.extend(page);
+        if last_page {
+            return Ok(rows);
+        }
+    }
+}
+
+/// Every equity point of a backtest result within `[start, end]`, oldest first. Reads in
+/// pages of `MAX_CHILD_ROWS`, so unlike `get_backtest_equity_curve` the curve is never
+/// truncated.
+pub async fn get_full_backtest_equity_curve(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<BacktestEquityCurve>, Error> {
+    let mut rows: Vec<BacktestEquityCurve> = Vec::new();
+    loop {
+        let after = rows.last().map(|p| p.timestamp);
+        let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+        let page = Retry::spawn(retry_strategy, || async {
+            use crate::schema::backtest_equity_curve::dsl::*;
+            let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+            let mut query = backtest_equity_curve
+                .filter(backtest_result_id.eq(result_id))
+                .order(timestamp.asc())
+                .limit(MAX_CHILD_ROWS)
+                .select(BacktestEquityCurve::as_select())
+                .into_boxed();
+            if let Some(ts) = after {
+                query = query.filter(timestamp.gt(ts));
+            }
+            if let Some(s) = start {
+                query = query.filter(timestamp.ge(s));
+            }
+            if let Some(e) = end {
+                query = query.filter(timestamp.le(e));
+            }
+
+            query.load::<BacktestEquityCurve>(&mut connection).await.map_err(|e| anyhow::Error::from(e))
+        }).await?;
+
+        let last_page = (page.len() as i64) < MAX_CHILD_ROWS;
+        rows.extend(page);
+        if last_page {
+            return Ok(rows);
+        }
+    }
+}
+
+/// Every position snapshot of a backtest result within `[start, end]`, oldest first. Reads
+/// in pages of `MAX_CHILD_ROWS` keyed on `(timestamp, symbol)`, so unlike
+/// `get_backtest_position_history` the result is never truncated.
+pub async fn get_full_backtest_position_history(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<BacktestPositionHistory>, Error> {
+    let mut rows: Vec<BacktestPositionHistory> = Vec::new();
+    loop {
+        let after = rows.last().map(|p| (p.timestamp, p.symbol.clone()));
+        let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+        let page = Retry::spawn(retry_strategy, || async {
+            use crate::schema::backtest_position_history::dsl::*;
+            let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+            let mut query = backtest_position_history
+                .filter(backtest_result_id.eq(result_id))
+                .order((timestamp.asc(), symbol.asc()))
+                .limit(MAX_CHILD_ROWS)
+                .select(BacktestPositionHistory::as_select())
+                .into_boxed();
+            if let Some((ts, sym)) = after.clone() {
+                query = query.filter(timestamp.gt(ts).or(timestamp.eq(ts).and(symbol.gt(sym))));
+            }
+            if let Some(s) = start {
+                query = query.filter(timestamp.ge(s));
+            }
+            if let Some(e) = end {
+                query = query.filter(timestamp.le(e));
+            }
+
+            query.load::<BacktestPositionHistory>(&mut connection).await.map_err(|e| anyhow::Error::from(e))
+        }).await?;
+
+        let last_page = (page.len() as i64) < MAX_CHILD_ROWS;
+        rows.extend(page);
+        if last_page {
+            return Ok(rows);
+        }
+    }
+}
+
+/// Recompute the metric columns and drawdown periods of a stored result from its equity
+/// curve and trades. When the result names a `benchmark`, it is resolved into candles at
+/// the equity curve's frequency for the benchmark statistics.
+pub async fn recompute_backtest_metrics(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    config: MetricsConfig,
+) -> Result<(BacktestResult, BacktestMetrics), Error> {
+    let start_time = Instant::now();
+    let logger = UltraLogger::new("databaseschema".to_string());
+
+    let stored = get_backtest_result(pool.clone(), result_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Backtest result {} not found", result_id))?;
+    let equity = get_full_backtest_equity_curve(pool.clone(), result_id, None, None).await?;
+    let trades = get_full_backtest_trades(pool.clone(), result_id, None, None).await?;
+    let benchmark = benchmark_ops::get_backtest_benchmark_candles(pool.clone(), &stored, &equity).await?;
+
+    let computed = metrics::compute_backtest_metrics(&equity, &trades, benchmark.as_deref(), &config);
+    let drawdown_rows = computed.drawdown_rows(result_id);
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        connection.transaction::<_, diesel::result::Error, _>(|conn| Box::pin(async {
+            use crate::schema::{backtest_drawdown_periods, backtest_results};
+
+            let updated = diesel::update(backtest_results::table.find(result_id))
+                .set((&computed.columns, backtest_results::updated_at.eq(Utc::now())))
+                .get_result::<BacktestResult>(conn)
+                .await?;
+            diesel::delete(backtest_drawdown_periods::table.filter(backtest_drawdown_periods::backtest_result_id.eq(result_id)))
+                .execute(conn)
+                .await?;
+            insert_backtest_drawdown_periods(conn, &drawdown_rows).await?;
+            Ok(updated)
+        }))
+        .await
+        .map_err(|e| anyhow::Error::from(e))
+    }).await;
+
+    match &result {
+        Ok(_) => { let _ = logger.info(format!(
+            "Recomputed metrics for backtest result {} from {} equity points and {} trades in {}ms",
+            result_id, equity.len(), trades.len(), start_time.elapsed().as_millis()
+        )).await; },
+        Err(e) => { let _ = logger.error(format!("Error storing recomputed metrics for {}: {}", result_id, e)).await; },
+    }
+    result.map(|updated| (updated, computed))
+}
+
+/// Find backtest results by strategy, symbol, instance, tag, date window and metric
+/// thresholds, sorted by any metric and paginated, e.g. the top Sharpe ratios with at
+/// least 100 trades and a drawdown under 20%.
+pub async fn search_backtest_results(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    query: &BacktestResultQuery,
+) -> Result<BacktestResultPage, Error> {
+    let start_time = Instant::now();
+    let logger = UltraLogger::new("databaseschema".to_string());
+
+    if let Err(e) = validate_backtest_result_query(query) {
+        let _ = logger.error(format!("Invalid backtest result query: {}", e)).await;
+        return Err(e);
+    }
+    let page_limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_results::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        let total_count = filtered_backtest_results(query)
+            .count()
+            .get_result::<i64>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        let mut page = filtered_backtest_results(query);
+        if let Some((metric, direction)) = query.sort {
+            let dir = match direction {
+                SortDirection::Ascending => "ASC",
+                SortDirection::Descending => "DESC",
+            };
+            page = page.order(diesel::dsl::sql::<diesel::sql_types::Untyped>(&format!("{} {} NULLS LAST", metric.column(), dir)));
+        }
+        let results = page
+            .then_order_by((created_at.desc(), id.asc()))
+            .limit(page_limit)
+            .offset(query.offset)
+            .select(BacktestResult::as_select())
+            .load::<BacktestResult>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        Ok(BacktestResultPage { results, total_count, limit: page_limit, offset: query.offset })
+    }).await;
+
+    match &result {
+        Ok(page) => { let _ = logger.debug(format!(
+            "Found {} of {} backtest results in {}ms", page.results.len(), page.total_count, start_time.elapsed().as_millis()
+        )).await; },
+        Err(e) => { let _ = logger.error(format!("Error searching backtest results: {}", e)).await; },
+    }
+    result
+}
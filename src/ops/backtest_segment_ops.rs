@@ -0,0 +1,294 @@
+use crate::{
+    get_timescale_connection,
+    metrics::{self, MetricsConfig},
+    models::backtest_result::BacktestResult,
+    models::backtest_segment::{
+        stitch_equity_curves, BacktestSegment, FoldDegradation, NewBacktestSegment, WalkForwardFold,
+        WalkForwardReport, IN_SAMPLE, OUT_OF_SAMPLE
+    },
+    ops::backtest_result_ops,
+};
+use bigdecimal::ToPrimitive;
+use diesel::prelude::*;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, error, debug, warn};
+use uuid::Uuid;
+
+const SEGMENT_TYPES: [&str; 2] = [IN_SAMPLE, OUT_OF_SAMPLE];
+const WINDOW_TYPES: [&str; 2] = ["anchored", "rolling"];
+
+fn validate_segment(segment: &NewBacktestSegment) -> Result<(), Error> {
+    if !SEGMENT_TYPES.contains(&segment.segment_type.as_str()) {
+        error!("Invalid segment type: {}", segment.segment_type);
+        return Err(anyhow::anyhow!("Invalid segment type: {}", segment.segment_type));
+    }
+    if !WINDOW_TYPES.contains(&segment.window_type.as_str()) {
+        error!("Invalid window type: {}", segment.window_type);
+        return Err(anyhow::anyhow!("Invalid window type: {}", segment.window_type));
+    }
+    if segment.fold_index < 0 {
+        return Err(anyhow::anyhow!("Invalid fold index: {}", segment.fold_index));
+    }
+    if segment.window_end <= segment.window_start {
+        return Err(anyhow::anyhow!("Segment window end must be after its start"));
+    }
+    Ok(())
+}
+
+/// Record the segments of a walk-forward sequence in one statement. All segments must
+/// share a `walk_forward_id` and window type.
+pub async fn create_walk_forward(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    segments: Vec<NewBacktestSegment>,
+) -> Result<Vec<BacktestSegment>, Error> {
+    let query_start = Instant::now();
+    let Some(head) = segments.as_slice().first() else {
+        return Ok(vec![]);
+    };
+    if segments.len() > 10000 {
+        error!("Too many backtest segments in one batch: {}", segments.len());
+        return Err(anyhow::anyhow!("Batch size {} exceeds the maximum of 10000 segments", segments.len()));
+    }
+    for segment in &segments {
+        validate_segment(segment)?;
+        if segment.walk_forward_id != head.walk_forward_id || segment.window_type != head.window_type {
+            return Err(anyhow::anyhow!("All segments of a walk-forward sequence must share its id and window type"));
+        }
+    }
+    let wf_id = head.walk_forward_id;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_segments::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::insert_into(backtest_segments)
+            .values(&segments)
+            .returning(BacktestSegment::as_returning())
+            .get_results::<BacktestSegment>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error creating walk-forward {}: {}", wf_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+
+    info!("Created walk-forward {} with {} segments in {}ms", wf_id, result.len(), query_start.elapsed().as_millis());
+    Ok(result)
+}
+
+/// Link one more result into a walk-forward sequence, e.g. as each fold finishes
+pub async fn add_backtest_segment(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    segment: NewBacktestSegment,
+) -> Result<BacktestSegment, Error> {
+    validate_segment(&segment)?;
+    debug!("Adding {} segment {} to walk-forward {}", segment.segment_type, segment.fold_index, segment.walk_forward_id);
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_segments::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::insert_into(backtest_segments)
+            .values(&segment)
+            .returning(BacktestSegment::as_returning())
+            .get_result::<BacktestSegment>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error adding segment to walk-forward {}: {}", segment.walk_forward_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Segments of a walk-forward sequence, by fold with in-sample first
+pub async fn get_walk_forward_segments(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    wf_id: Uuid,
+) -> Result<Vec<BacktestSegment>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_segments::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        backtest_segments
+            .filter(walk_forward_id.eq(wf_id))
+            .order((fold_index.asc(), segment_type.asc()))
+            .select(BacktestSegment::as_select())
+            .load::<BacktestSegment>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error getting segments of walk-forward {}: {}", wf_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Segments of a walk-forward sequence paired by fold
+pub async fn get_walk_forward_folds(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    wf_id: Uuid,
+) -> Result<Vec<WalkForwardFold>, Error> {
+    let segments = get_walk_forward_segments(pool, wf_id).await?;
+
+    let mut folds: BTreeMap<i32, WalkForwardFold> = BTreeMap::new();
+    for segment in segments {
+        let fold = folds.entry(segment.fold_index).or_insert(WalkForwardFold {
+            fold_index: segment.fold_index,
+            in_sample: None,
+            out_of_sample: None,
+        });
+        if segment.is_out_of_sample() {
+            fold.out_of_sample = Some(segment);
+        } else {
+            fold.in_sample = Some(segment);
+        }
+    }
+    Ok(folds.into_values().collect())
+}
+
+/// Segments run for a strategy instance, newest sequence first
+pub async fn get_segments_for_strategy_instance(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    instance_id: Uuid,
+) -> Result<Vec<BacktestSegment>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_segments::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        backtest_segments
+            .filter(strategy_instance_id.eq(instance_id))
+            .order((created_at.desc(), walk_forward_id.asc(), fold_index.asc(), segment_type.asc()))
+            .select(BacktestSegment::as_select())
+            .load::<BacktestSegment>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error getting segments for strategy instance {}: {}", instance_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Walk-forward segments a backtest result takes part in
+pub async fn get_segments_for_backtest_result(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+) -> Result<Vec<BacktestSegment>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_segments::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        backtest_segments
+            .filter(backtest_result_id.eq(result_id))
+            .order((walk_forward_id.asc(), fold_index.asc()))
+            .select(BacktestSegment::as_select())
+            .load::<BacktestSegment>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error getting segments for backtest result {}: {}", result_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+async fn load_segment_result(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    segment: &BacktestSegment,
+) -> Result<BacktestResult, Error> {
+    backtest_result_ops::get_backtest_result(pool, segment.backtest_result_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Backtest result {} not found", segment.backtest_result_id))
+}
+
+/// Stitch the out-of-sample equity curves of a walk-forward sequence into one curve,
+/// compute its metrics from the out-of-sample trades, and compare each fold's stored
+/// in-sample and out-of-sample Sharpe ratio and annualized return. Folds missing either
+/// segment contribute only what they have.
+pub async fn analyze_walk_forward(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    wf_id: Uuid,
+    config: MetricsConfig,
+) -> Result<WalkForwardReport, Error> {
+    let query_start = Instant::now();
+    let folds = get_walk_forward_folds(pool.clone(), wf_id).await?;
+    if folds.is_empty() {
+        return Err(anyhow::anyhow!("Walk-forward {} has no segments", wf_id));
+    }
+
+    let mut curves = Vec::new();
+    let mut trades = Vec::new();
+    let mut degradation = Vec::new();
+    for fold in &folds {
+        let Some(oos) = &fold.out_of_sample else {
+            warn!("Fold {} of walk-forward {} has no out-of-sample segment", fold.fold_index, wf_id);
+            continue;
+        };
+        let equity = backtest_result_ops::get_full_backtest_equity_curve(
+            pool.clone(), oos.backtest_result_id, Some(oos.window_start), Some(oos.window_end),
+        ).await?;
+        trades.extend(backtest_result_ops::get_full_backtest_trades(
+            pool.clone(), oos.backtest_result_id, Some(oos.window_start), Some(oos.window_end),
+        ).await?);
+        curves.push((oos.window_start, oos.window_end, equity));
+
+        let Some(is) = &fold.in_sample else {
+            continue;
+        };
+        let is_result = load_segment_result(pool.clone(), is).await?;
+        let oos_result = load_segment_result(pool.clone(), oos).await?;
+        let is_sharpe = is_result.sharpe_ratio.as_ref().and_then(|s| s.to_f64());
+        let oos_sharpe = oos_result.sharpe_ratio.as_ref().and_then(|s| s.to_f64());
+        let is_return = is_result.annualized_return.to_f64().unwrap_or(0.0);
+        let oos_return = oos_result.annualized_return.to_f64().unwrap_or(0.0);
+        degradation.push(FoldDegradation {
+            fold_index: fold.fold_index,
+            in_sample_result_id: is.backtest_result_id,
+            out_of_sample_result_id: oos.backtest_result_id,
+            in_sample_sharpe: is_sharpe,
+            out_of_sample_sharpe: oos_sharpe,
+            in_sample_annualized_return: is_return,
+            out_of_sample_annualized_return: oos_return,
+            efficiency: (is_return > 0.0).then(|| oos_return / is_return),
+            sharpe_decay: is_sharpe.zip(oos_sharpe).map(|(i, o)| i - o),
+        });
+    }
+
+    let stitched_equity = stitch_equity_curves(&curves);
+    let stitched_metrics = (stitched_equity.len() >= 2)
+        .then(|| metrics::compute_backtest_metrics(&stitched_equity, &trades, None, &config));
+
+    let is_sharpes: Vec<f64> = degradation.iter().filter_map(|d| d.in_sample_sharpe).collect();
+    let oos_sharpes: Vec<f64> = degradation.iter().filter_map(|d| d.out_of_sample_sharpe).collect();
+    let is_returns: Vec<f64> = degradation.iter().map(|d| d.in_sample_annualized_return).collect();
+    let oos_returns: Vec<f64> = degradation.iter().map(|d| d.out_of_sample_annualized_return).collect();
+    let walk_forward_efficiency = metrics::mean(&is_returns)
+        .zip(metrics::mean(&oos_returns))
+        .filter(|(is, _)| *is > 0.0)
+        .map(|(is, oos)| oos / is);
+
+    info!("Analyzed walk-forward {}: {} folds, {} stitched points, {} trades in {}ms",
+        wf_id, folds.len(), stitched_equity.len(), trades.len(), query_start.elapsed().as_millis());
+    Ok(WalkForwardReport {
+        walk_forward_id: wf_id,
+        folds: degradation,
+        stitched_equity,
+        stitched_metrics,
+        mean_in_sample_sharpe: metrics::mean(&is_sharpes),
+        mean_out_of_sample_sharpe: metrics::mean(&oos_sharpes),
+        walk_forward_efficiency,
+    })
+}
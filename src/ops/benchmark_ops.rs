@@ -0,0 +1,210 @@
+use crate::{
+    get_timescale_connection,
+    metrics::{self, BenchmarkStatistics, MetricsConfig, RollingBenchmarkPoint},
+    models::backtest_result::{BacktestEquityCurve, BacktestResult},
+    models::candles::{Candle, CandleAdjustment, CandleQuery},
+    ops::{backtest_result_ops, candles_ops},
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, error, debug, warn};
+use uuid::Uuid;
+
+/// Candle timeframes and their length in seconds, shortest first
+const TIMEFRAMES: [(&str, i64); 5] = [("1m", 60), ("5m", 300), ("15m", 900), ("1h", 3600), ("1d", 86400)];
+
+/// Rows `get_adjusted_candles` returns at most per call
+const CANDLES_PER_REQUEST: i64 = 100000;
+
+/// Split a `backtest_results.benchmark` value into `(exchange, symbol)`. Both `SYMBOL` and
+/// `EXCHANGE:SYMBOL` are accepted; without an exchange it is looked up from `candles`.
+pub fn parse_benchmark(benchmark: &str) -> (Option<&str>, &str) {
+    match benchmark.trim().split_once(':') {
+        Some((xchange, sym)) if !xchange.is_empty() && !sym.is_empty() => (Some(xchange.trim()), sym.trim()),
+        _ => (None, benchmark.trim()),
+    }
+}
+
+/// The longest candle timeframe no longer than the equity curve spacing, so every equity
+/// point has a benchmark close at or before it
+pub fn timeframe_for_spacing(spacing_seconds: i64) -> &'static str {
+    TIMEFRAMES
+        .iter()
+        .rev()
+        .find(|(_, seconds)| *seconds <= spacing_seconds)
+        .map(|(tf, _)| *tf)
+        .unwrap_or("1m")
+}
+
+/// Load adjusted benchmark candles over `[start_time, end_time]`, splitting the range into
+/// requests that stay within `get_adjusted_candles`' one-year and row limits
+pub async fn load_benchmark_candles(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    benchmark: &str,
+    tf: &str,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<Candle>, Error> {
+    let query_start = Instant::now();
+    let (xchange, sym) = parse_benchmark(benchmark);
+    let xchange = match xchange {
+        Some(x) => x.to_string(),
+        None => match resolve_benchmark_exchange(pool.clone(), sym, tf).await? {
+            Some(x) => x,
+            None => {
+                warn!("No {} candles found for benchmark {}", tf, sym);
+                return Ok(vec![]);
+            }
+        },
+    };
+
+    let tf_seconds = TIMEFRAMES.iter().find(|(t, _)| *t == tf).map(|(_, s)| *s).unwrap_or(86400);
+    let step = Duration::days(365).min(Duration::seconds(tf_seconds * (CANDLES_PER_REQUEST - 1)));
+
+    let mut loaded: Vec<Candle> = Vec::new();
+    let mut from = start_time;
+    while from <= end_time {
+        let to = (from + step).min(end_time);
+        let chunk_query = CandleQuery::new(sym, &xchange, tf)
+            .between(from, to)
+            .limit(CANDLES_PER_REQUEST as usize)
+            .adjustment(CandleAdjustment::SplitsAndDividends);
+        let chunk = candles_ops::get_adjusted_candles(pool.clone(), &chunk_query).await?;
+        // Chunk boundaries are inclusive on both ends
+        let last_loaded = loaded.last().map(|c| c.timestamp);
+        loaded.extend(chunk.into_iter().filter(|c| last_loaded.is_none_or(|ts| c.timestamp > ts)));
+        if to >= end_time {
+            break;
+        }
+        from = to;
+    }
+
+    info!("Loaded {} {} benchmark candles for {}/{} in {}ms", loaded.len(), tf, sym, xchange, query_start.elapsed().as_millis());
+    Ok(loaded)
+}
+
+/// Exchange of the most recent `tf` candle stored for `sym`
+async fn resolve_benchmark_exchange(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    tf: &str,
+) -> Result<Option<String>, Error> {
+    if sym.is_empty() || sym.len() > 20 {
+        error!("Invalid benchmark symbol length: {}", sym.len());
+        return Err(anyhow::anyhow!("Invalid benchmark symbol length: {}", sym.len()));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::candles::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        candles
+            .filter(symbol.eq(sym))
+            .filter(timeframe.eq(tf))
+            .order(timestamp.desc())
+            .select(exchange)
+            .first::<String>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| {
+                error!("Error resolving exchange for benchmark {}: {}", sym, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Benchmark candles for a stored result at the frequency of its equity curve, or `None`
+/// when the result names no benchmark
+pub async fn get_backtest_benchmark_candles(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result: &BacktestResult,
+    equity: &[BacktestEquityCurve],
+) -> Result<Option<Vec<Candle>>, Error> {
+    let Some(benchmark) = result.benchmark.as_deref().filter(|b| !b.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let curve = metrics::equity_points(equity);
+    let tf = timeframe_for_spacing(metrics::median_spacing_seconds(&curve).unwrap_or(86400));
+    // A week of lead so the first equity point has a close even after a weekend or holiday
+    let start = curve.as_slice().first().map(|(ts, _)| *ts).unwrap_or(result.start_date) - Duration::days(7);
+    let end = curve.last().map(|(ts, _)| *ts).unwrap_or(result.end_date);
+
+    debug!("Resolving benchmark {} for backtest result {} at {}", benchmark, result.id, tf);
+    load_benchmark_candles(pool, benchmark, tf, start, end).await.map(Some)
+}
+
+/// Resolve a stored result's benchmark into candles, compute beta, correlation, tracking
+/// error, information ratio, Jensen's alpha, benchmark and excess return and the
+/// out/underperformance counts against its equity curve, and write them to the result.
+/// Returns `None`, leaving the row untouched, when there is no benchmark or no overlap.
+pub async fn compute_benchmark_analytics(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    config: MetricsConfig,
+) -> Result<Option<BenchmarkStatistics>, Error> {
+    let query_start = Instant::now();
+    let stored = backtest_result_ops::get_backtest_result(pool.clone(), result_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Backtest result {} not found", result_id))?;
+    let equity = backtest_result_ops::get_full_backtest_equity_curve(pool.clone(), result_id, None, None).await?;
+    let Some(benchmark) = get_backtest_benchmark_candles(pool.clone(), &stored, &equity).await? else {
+        warn!("Backtest result {} has no benchmark", result_id);
+        return Ok(None);
+    };
+
+    let Some(statistics) = metrics::benchmark_statistics(&equity, &benchmark, &config) else {
+        warn!("Benchmark {:?} does not overlap the equity curve of {}", stored.benchmark, result_id);
+        return Ok(None);
+    };
+    let columns = statistics.to_columns();
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::backtest_results::dsl::*;
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::update(backtest_results.find(result_id))
+            .set((&columns, updated_at.eq(Utc::now())))
+            .execute(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error storing benchmark analytics for {}: {}", result_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+
+    info!("Computed benchmark analytics for {} from {} equity points and {} candles in {}ms",
+        result_id, equity.len(), benchmark.len(), query_start.elapsed().as_millis());
+    Ok(Some(statistics))
+}
+
+/// Rolling benchmark-relative statistics over `window` return periods of a stored result,
+/// for charting. Nothing is written.
+pub async fn get_rolling_benchmark_analytics(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_id: Uuid,
+    window: usize,
+    config: MetricsConfig,
+) -> Result<Vec<RollingBenchmarkPoint>, Error> {
+    let query_start = Instant::now();
+    let stored = backtest_result_ops::get_backtest_result(pool.clone(), result_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Backtest result {} not found", result_id))?;
+    let equity = backtest_result_ops::get_full_backtest_equity_curve(pool.clone(), result_id, None, None).await?;
+    let Some(benchmark) = get_backtest_benchmark_candles(pool.clone(), &stored, &equity).await? else {
+        return Ok(vec![]);
+    };
+
+    let points = metrics::rolling_benchmark_statistics(&equity, &benchmark, window, &config);
+    debug!("Computed {} rolling benchmark points for {} in {}ms", points.len(), result_id, query_start.elapsed().as_millis());
+    Ok(points)
+}
@@ -1,7 +1,9 @@
 use crate::{
     get_timescale_connection, 
-    models::candles::Candle
+    models::{candles::{Candle, CandleAdjustment, CandleQuery}, corporate_action::CorporateAction},
+    resampling::{self, CandlePathOptions, VolatilityRegime}
 };
+use bigdecimal::BigDecimal;
 use diesel_async::pooled_connection::deadpool;
 use diesel_async::AsyncPgConnection;
 
@@ -13,6 +15,8 @@ use std::sync::Arc;
 use chrono::{DateTime, Utc, Duration};
 use tracing::{info, error, warn};
 use std::time::Instant;
+use std::collections::HashMap;
+use uuid::Uuid;
 
 /// Get 1-minute candles for a symbol and exchange
 pub async fn get_candles_1m(
@@ -403,3 +407,217 @@ pub async fn get_candles_1d(
         Ok(result)
     }).await
 }
+
+
+/// Get candles of any timeframe, back-adjusted for the splits and dividends in `corporate_actions`.
+/// Prices are expressed in the share basis in effect after the last action, so returns computed
+/// across a split or ex-dividend date are continuous.
+pub async fn get_adjusted_candles(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    candle_query: &CandleQuery,
+) -> Result<Vec<Candle>, Error> {
+    let query_start = Instant::now();
+    use crate::schema::candles::dsl::*;
+
+    let (sym, xchange, tf) = (candle_query.symbol.as_str(), candle_query.exchange.as_str(), candle_query.timeframe.as_str());
+    let (start_time, end_time) = (candle_query.start_time, candle_query.end_time);
+    let (limit, adjustment) = (candle_query.limit, candle_query.adjustment);
+
+    // Security: Input validation
+    if sym.is_empty() || sym.len() > 20 {
+        error!("Invalid symbol length: {}", sym.len());
+        return Err(anyhow::anyhow!("Invalid symbol length: {}", sym.len()));
+    }
+    
+    if xchange.is_empty() || xchange.len() > 50 {
+        error!("Invalid exchange length: {}", xchange.len());
+        return Err(anyhow::anyhow!("Invalid exchange length: {}", xchange.len()));
+    }
+
+    if !["1m", "5m", "15m", "1h", "1d"].contains(&tf) {
+        error!("Invalid timeframe: {}", tf);
+        return Err(anyhow::anyhow!("Invalid timeframe: {}", tf));
+    }
+
+    // Security: Validate date range to prevent excessive queries
+    if let (Some(start), Some(end)) = (start_time, end_time) {
+        let duration = end - start;
+        if duration > Duration::days(365) {
+            error!("Date range too large: {} days", duration.num_days());
+            return Err(anyhow::anyhow!("Date range too large: {} days", duration.num_days()));
+        }
+    }
+
+    // Security: Validate limit to prevent memory exhaustion
+    const MAX_LIMIT: usize = 100000;
+    let safe_limit = match limit {
+        Some(l) if l > MAX_LIMIT => {
+            warn!("Limit {} exceeds maximum {}, using maximum", l, MAX_LIMIT);
+            MAX_LIMIT
+        },
+        Some(l) => l,
+        None => 10000, // Default reasonable limit
+    };
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let mut query = candles
+            .filter(symbol.eq(sym).and(exchange.eq(xchange)))
+            .filter(timeframe.eq(tf))
+            .order(timestamp.asc())
+            .limit(safe_limit as i64)
+            .into_boxed();
+
+        if let Some(start) = start_time {
+            query = query.filter(timestamp.ge(start));
+        }
+        if let Some(end) = end_time {
+            query = query.filter(timestamp.le(end));
+        }
+
+        let mut result = query
+            .select(Candle::as_select())
+            .load::<Candle>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        let (first, last) = match (result.as_slice().first(), result.last()) {
+            (Some(first), Some(last)) if adjustment != CandleAdjustment::None => (first.clone(), last.clone()),
+            _ => return Ok(result),
+        };
+
+        let action_types: Vec<&str> = match adjustment {
+            CandleAdjustment::SplitsAndDividends => vec!["split", "dividend"],
+            _ => vec!["split"],
+        };
+
+        let actions = {
+            use crate::schema::corporate_actions;
+            corporate_actions::table
+                .filter(corporate_actions::security_id.eq(first.security_id))
+                .filter(corporate_actions::effective_date.gt(first.timestamp))
+                .filter(corporate_actions::action_type.eq_any(&action_types))
+                .order(corporate_actions::effective_date.desc())
+                .select(CorporateAction::as_select())
+                .load::<CorporateAction>(&mut connection)
+                .await
+                .map_err(|e| anyhow::Error::from(e))?
+        };
+
+        // Dividends going ex after the range need the close right before their ex-date,
+        // which is not among the loaded candles
+        let mut closes_before = HashMap::new();
+        for action in actions.iter().filter(|a| a.action_type == "dividend" && a.effective_date > last.timestamp) {
+            let prior_close = candles
+                .filter(symbol.eq(sym).and(exchange.eq(xchange)))
+                .filter(timeframe.eq(tf))
+                .filter(timestamp.lt(action.effective_date))
+                .order(timestamp.desc())
+                .select(close_price)
+                .first::<BigDecimal>(&mut connection)
+                .await
+                .optional()
+                .map_err(|e| anyhow::Error::from(e))?;
+            if let Some(prior_close) = prior_close {
+                closes_before.insert(action.id, prior_close);
+            }
+        }
+
+        apply_corporate_actions(&mut result, &actions, &closes_before);
+
+        info!("Fetched {} adjusted {} candles ({} actions) in {}ms", result.len(), tf, actions.len(), query_start.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
+
+/// Back-adjust ascending candles in place. `actions` must be ordered by effective date descending.
+fn apply_corporate_actions(
+    candles: &mut [Candle],
+    actions: &[CorporateAction],
+    closes_before: &HashMap<Uuid, BigDecimal>,
+) {
+    let one = BigDecimal::from(1);
+    let mut price_factor = one.clone();
+    let mut volume_factor = one.clone();
+    let mut next_action = 0;
+
+    for candle in candles.iter_mut().rev() {
+        while let Some(action) = actions.get(next_action).filter(|a| a.effective_date > candle.timestamp) {
+            match (action.action_type.as_str(), &action.split_ratio, &action.dividend_amount) {
+                ("split", Some(ratio), _) => {
+                    price_factor = &price_factor / ratio;
+                    volume_factor = &volume_factor * ratio;
+                },
+                ("dividend", _, Some(amount)) => {
+                    let prior_close = closes_before.get(&action.id).unwrap_or(&candle.close_price);
+                    if prior_close > amount {
+                        price_factor = &price_factor * (&one - amount / prior_close);
+                    } else {
+                        warn!("Skipping dividend {} larger than prior close {}", amount, prior_close);
+                    }
+                },
+                _ => {},
+            }
+            next_action += 1;
+        }
+
+        if price_factor != one {
+            candle.open_price = (&candle.open_price * &price_factor).round(8);
+            candle.high_price = (&candle.high_price * &price_factor).round(8);
+            candle.low_price = (&candle.low_price * &price_factor).round(8);
+            candle.close_price = (&candle.close_price * &price_factor).round(8);
+        }
+        if volume_factor != one {
+            candle.volume = (&candle.volume * &volume_factor).round(8);
+        }
+    }
+}
+
+/// Load candles covering `[start_time, end_time]` and label each with its volatility regime
+pub async fn get_candle_regimes(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    xchange: &str,
+    tf: &str,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    regime_window: usize,
+) -> Result<(Vec<Candle>, Vec<VolatilityRegime>), Error> {
+    let candle_query = CandleQuery::new(sym, xchange, tf).between(start_time, end_time).limit(100000);
+    let loaded = get_adjusted_candles(pool, &candle_query).await?;
+    let regimes = resampling::classify_volatility_regimes(&loaded, regime_window);
+    Ok((loaded, regimes))
+}
+
+/// Build synthetic candle paths by resampling historical bar returns over the range of
+/// `candle_query`. Split-adjusted candles are used so corporate actions do not show up as
+/// price jumps, whatever adjustment the query asks for. With `options.regime` set, only bars
+/// from that volatility regime are drawn.
+pub async fn get_resampled_candle_paths(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    candle_query: &CandleQuery,
+    options: &CandlePathOptions,
+) -> Result<Vec<Vec<Candle>>, Error> {
+    let query_start = Instant::now();
+    let (sym, xchange, tf) = (&candle_query.symbol, &candle_query.exchange, &candle_query.timeframe);
+    let history_query = candle_query.clone().limit(100000).adjustment(CandleAdjustment::Splits);
+    let history = get_adjusted_candles(pool, &history_query).await?;
+
+    if history.len() < 2 {
+        warn!("Not enough {} candles for {}/{} to resample ({})", tf, sym, xchange, history.len());
+        return Ok(vec![]);
+    }
+
+    let paths = resampling::synthesize_candle_paths(&history, options);
+    info!("Built {} synthetic {} candle paths of {} bars from {} candles in {}ms",
+        paths.len(), tf, options.path_len, history.len(), query_start.elapsed().as_millis());
+    Ok(paths)
+}
@@ -0,0 +1,319 @@
+use crate::{
+    get_timescale_connection,
+    models::corporate_action::{CorporateAction, NewCorporateAction, NewSymbolHistory, SymbolHistory}
+};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, error, debug};
+use uuid::Uuid;
+
+/// Record a corporate action. Renames and delistings also close the current
+/// `symbol_history` window (and open the new one for renames) in the same transaction.
+pub async fn record_corporate_action(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    action: NewCorporateAction,
+) -> Result<CorporateAction, Error> {
+    let start_time = Instant::now();
+    info!("Recording {} for {} effective {}", action.action_type, action.symbol, action.effective_date);
+
+    // Input validation
+    if action.symbol.is_empty() || action.symbol.len() > 20 {
+        error!("Invalid symbol length: {}", action.symbol.len());
+        return Err(anyhow::anyhow!("Invalid symbol length: {}", action.symbol.len()));
+    }
+
+    let zero = BigDecimal::from(0);
+    match action.action_type.as_str() {
+        "split" if action.split_ratio.as_ref().is_none_or(|r| r <= &zero) => {
+            return Err(anyhow::anyhow!("Split requires a positive split_ratio"));
+        },
+        "dividend" if action.dividend_amount.as_ref().is_none_or(|a| a <= &zero) => {
+            return Err(anyhow::anyhow!("Dividend requires a positive dividend_amount"));
+        },
+        "rename" if action.new_symbol.as_ref().is_none_or(|s| s.is_empty() || s.len() > 20) => {
+            return Err(anyhow::anyhow!("Rename requires a new_symbol of 1-20 characters"));
+        },
+        "split" | "dividend" | "rename" | "delist" => {},
+        other => return Err(anyhow::anyhow!("Unknown corporate action type: {}", other)),
+    }
+
+    // Renames and delistings close the open symbol window, which must exist and must start
+    // before the action takes effect, so the closed window is never empty. Checked up front
+    // so a bad date is not retried.
+    if matches!(action.action_type.as_str(), "rename" | "delist") {
+        let open_window = get_open_symbol_window(pool.clone(), action.security_id).await?;
+        match open_window {
+            None => {
+                error!("No open symbol window for security {}", action.security_id);
+                return Err(anyhow::anyhow!("Security {} has no open symbol history window", action.security_id));
+            },
+            Some(window) if action.effective_date <= window.valid_from => {
+                error!("{} effective {} does not follow open window starting {}", action.action_type, action.effective_date, window.valid_from);
+                return Err(anyhow::anyhow!(
+                    "Cannot {} {} effective {}: current symbol window starts {}",
+                    action.action_type, window.symbol, action.effective_date, window.valid_from
+                ));
+            },
+            Some(_) => {},
+        }
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let result = connection.transaction::<_, diesel::result::Error, _>(|conn| Box::pin(async {
+            use crate::schema::{corporate_actions, symbol_history};
+
+            let recorded = diesel::insert_into(corporate_actions::table)
+                .values(&action)
+                .returning(CorporateAction::as_returning())
+                .get_result::<CorporateAction>(conn)
+                .await?;
+
+            let closing_status = match action.action_type.as_str() {
+                "rename" => Some("renamed"),
+                "delist" => Some("delisted"),
+                _ => None,
+            };
+
+            if let Some(closing_status) = closing_status {
+                let closed = diesel::update(
+                    symbol_history::table
+                        .filter(symbol_history::security_id.eq(action.security_id))
+                        .filter(symbol_history::valid_to.is_null())
+                        .filter(symbol_history::valid_from.lt(action.effective_date))
+                )
+                    .set((
+                        symbol_history::valid_to.eq(Some(action.effective_date)),
+                        symbol_history::status.eq(closing_status),
+                    ))
+                    .execute(conn)
+                    .await?;
+
+                // The open window moved since the check above
+                if closed == 0 {
+                    return Err(diesel::result::Error::NotFound);
+                }
+            }
+
+            if let Some(renamed_to) = action.new_symbol.as_ref().filter(|_| action.action_type == "rename") {
+                diesel::insert_into(symbol_history::table)
+                    .values(&NewSymbolHistory {
+                        security_id: action.security_id,
+                        symbol: renamed_to.clone(),
+                        valid_from: action.effective_date,
+                        valid_to: None,
+                        status: "active".to_string(),
+                    })
+                    .execute(conn)
+                    .await?;
+            }
+
+            Ok(recorded)
+        })).await
+            .map_err(|e| {
+                error!("Error recording corporate action: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        debug!("Recorded corporate action in {}ms", start_time.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
+
+/// Record a split of `ratio` new shares per old share
+pub async fn record_split(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    security_id: Uuid,
+    sym: &str,
+    effective_date: DateTime<Utc>,
+    ratio: &BigDecimal,
+) -> Result<CorporateAction, Error> {
+    record_corporate_action(pool, NewCorporateAction::split(security_id, sym, effective_date, ratio)).await
+}
+
+/// Record a cash dividend of `amount` per share with the given ex-date
+pub async fn record_dividend(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    security_id: Uuid,
+    sym: &str,
+    ex_date: DateTime<Utc>,
+    amount: &BigDecimal,
+) -> Result<CorporateAction, Error> {
+    record_corporate_action(pool, NewCorporateAction::dividend(security_id, sym, ex_date, amount)).await
+}
+
+/// Record a ticker change and roll the symbol history over to `new_sym`
+pub async fn record_rename(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    security_id: Uuid,
+    sym: &str,
+    effective_date: DateTime<Utc>,
+    new_sym: &str,
+) -> Result<CorporateAction, Error> {
+    record_corporate_action(pool, NewCorporateAction::rename(security_id, sym, effective_date, new_sym)).await
+}
+
+/// Record a delisting and close the security's current symbol window
+pub async fn record_delisting(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    security_id: Uuid,
+    sym: &str,
+    effective_date: DateTime<Utc>,
+) -> Result<CorporateAction, Error> {
+    record_corporate_action(pool, NewCorporateAction::delist(security_id, sym, effective_date)).await
+}
+
+/// Get corporate actions for a security, optionally bounded by effective date
+pub async fn get_corporate_actions(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sec_id: Uuid,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+) -> Result<Vec<CorporateAction>, Error> {
+    let query_start = Instant::now();
+    use crate::schema::corporate_actions::dsl::*;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let mut query = corporate_actions
+            .filter(security_id.eq(sec_id))
+            .order(effective_date.asc())
+            .limit(10000) // Prevent memory exhaustion
+            .into_boxed();
+
+        if let Some(start) = start_time {
+            query = query.filter(effective_date.ge(start));
+        }
+        if let Some(end) = end_time {
+            query = query.filter(effective_date.le(end));
+        }
+
+        let result = query
+            .select(CorporateAction::as_select())
+            .load::<CorporateAction>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        info!("Fetched {} corporate actions in {}ms", result.len(), query_start.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
+
+/// Get every symbol a security has traded under, oldest first
+pub async fn get_symbol_history(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sec_id: Uuid,
+) -> Result<Vec<SymbolHistory>, Error> {
+    let query_start = Instant::now();
+    use crate::schema::symbol_history::dsl::*;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let result = symbol_history
+            .filter(security_id.eq(sec_id))
+            .order(valid_from.asc())
+            .select(SymbolHistory::as_select())
+            .load::<SymbolHistory>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        debug!("Fetched {} symbol history rows in {}ms", result.len(), query_start.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
+
+/// Get the symbol window a security currently trades under, if it is still listed
+pub async fn get_open_symbol_window(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sec_id: Uuid,
+) -> Result<Option<SymbolHistory>, Error> {
+    use crate::schema::symbol_history::dsl::*;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        symbol_history
+            .filter(security_id.eq(sec_id))
+            .filter(valid_to.is_null())
+            .select(SymbolHistory::as_select())
+            .first::<SymbolHistory>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Resolve which security traded as `sym` at time `as_of`
+pub async fn resolve_symbol_as_of(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    as_of: DateTime<Utc>,
+) -> Result<Option<SymbolHistory>, Error> {
+    use crate::schema::symbol_history::dsl::*;
+
+    // Input validation
+    if sym.is_empty() || sym.len() > 20 {
+        error!("Invalid symbol length: {}", sym.len());
+        return Err(anyhow::anyhow!("Invalid symbol length: {}", sym.len()));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        symbol_history
+            .filter(symbol.eq(sym))
+            .filter(valid_from.le(as_of))
+            .filter(valid_to.is_null().or(valid_to.gt(as_of)))
+            .order(valid_from.desc())
+            .select(SymbolHistory::as_select())
+            .first::<SymbolHistory>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
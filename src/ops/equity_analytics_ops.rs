@@ -0,0 +1,329 @@
+use crate::{
+    get_timescale_connection,
+    models::equity_analytics::{
+        DrawdownPoint, EquityBucket, MonthlyReturnRow, PeriodReturn, ResampledEquityPoint, RollingEquityMetric
+    }
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::sql_types::{Array, BigInt, Double, Interval, Nullable, Text, Timestamptz, Uuid as SqlUuid};
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{error, debug};
+use uuid::Uuid;
+
+/// Upper bound on backtest results per call
+const MAX_RESULT_IDS: usize = 100;
+
+/// Upper bound on rows returned per call. A call that would return more fails instead of
+/// cutting off the later results.
+const MAX_POINTS: i64 = 500000;
+
+// Shared filter on `backtest_equity_curve`: $1 result ids, $2/$3 optional time range
+const EQUITY_FILTER: &str = r#"
+    backtest_result_id = ANY($1)
+    AND ($2::TIMESTAMPTZ IS NULL OR "timestamp" >= $2)
+    AND ($3::TIMESTAMPTZ IS NULL OR "timestamp" <= $3)
+"#;
+
+fn validate_request(result_ids: &[Uuid], start_time: Option<DateTime<Utc>>, end_time: Option<DateTime<Utc>>) -> Result<(), Error> {
+    if result_ids.is_empty() || result_ids.len() > MAX_RESULT_IDS {
+        error!("Invalid number of backtest result ids: {}", result_ids.len());
+        return Err(anyhow::anyhow!("Between 1 and {} backtest result ids are required", MAX_RESULT_IDS));
+    }
+    if let (Some(start), Some(end)) = (start_time, end_time) {
+        if start >= end {
+            error!("Invalid time range: {} >= {}", start, end);
+            return Err(anyhow::anyhow!("Start time must be before end time"));
+        }
+    }
+    Ok(())
+}
+
+/// Queries fetch one row past `MAX_POINTS`; seeing it means the series would be truncated
+fn check_row_cap<T>(rows: Vec<T>, what: &str) -> Result<Vec<T>, Error> {
+    if rows.len() as i64 > MAX_POINTS {
+        error!("{} exceed {} rows", what, MAX_POINTS);
+        return Err(anyhow::anyhow!(
+            "{} exceed {} rows; narrow the time range or request fewer backtest results", what, MAX_POINTS
+        ));
+    }
+    Ok(rows)
+}
+
+/// Resample equity curves into `bucket`-wide `time_bucket`s with first/high/low/last values
+pub async fn get_resampled_equity(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_ids: &[Uuid],
+    bucket: EquityBucket,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+) -> Result<Vec<ResampledEquityPoint>, Error> {
+    let query_start = Instant::now();
+    validate_request(result_ids, start_time, end_time)?;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let points = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::sql_query(format!(
+            r#"
+            SELECT backtest_result_id,
+                   time_bucket($4::INTERVAL, "timestamp") AS bucket,
+                   first(portfolio_value, "timestamp") AS open_value,
+                   MAX(portfolio_value) AS high_value,
+                   MIN(portfolio_value) AS low_value,
+                   last(portfolio_value, "timestamp") AS close_value,
+                   COUNT(*) AS points
+            FROM backtest_equity_curve
+            WHERE {}
+            GROUP BY backtest_result_id, bucket
+            ORDER BY backtest_result_id, bucket
+            LIMIT $5
+            "#,
+            EQUITY_FILTER
+        ))
+            .bind::<Array<SqlUuid>, _>(result_ids.to_vec())
+            .bind::<Nullable<Timestamptz>, _>(start_time)
+            .bind::<Nullable<Timestamptz>, _>(end_time)
+            .bind::<Text, _>(bucket.interval())
+            .bind::<BigInt, _>(MAX_POINTS + 1)
+            .load::<ResampledEquityPoint>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error resampling equity curves: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+    let points = check_row_cap(points, "Resampled equity points")?;
+
+    debug!("Resampled {} equity curves into {} {:?} buckets in {}ms",
+        result_ids.len(), points.len(), bucket, query_start.elapsed().as_millis());
+    Ok(points)
+}
+
+/// Return of each `bucket`, close to close, per backtest result
+pub async fn get_period_returns(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_ids: &[Uuid],
+    bucket: EquityBucket,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+) -> Result<Vec<PeriodReturn>, Error> {
+    let query_start = Instant::now();
+    validate_request(result_ids, start_time, end_time)?;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let returns = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::sql_query(format!(
+            r#"
+            WITH buckets AS (
+                SELECT backtest_result_id,
+                       time_bucket($4::INTERVAL, "timestamp") AS period_start,
+                       first(portfolio_value, "timestamp") AS open_value,
+                       last(portfolio_value, "timestamp") AS close_value
+                FROM backtest_equity_curve
+                WHERE {}
+                GROUP BY backtest_result_id, period_start
+            ), chained AS (
+                SELECT backtest_result_id, period_start, close_value,
+                       COALESCE(LAG(close_value) OVER (PARTITION BY backtest_result_id ORDER BY period_start), open_value) AS start_value
+                FROM buckets
+            )
+            SELECT backtest_result_id, period_start, start_value,
+                   close_value AS end_value,
+                   (close_value / NULLIF(start_value, 0) - 1)::FLOAT8 AS period_return
+            FROM chained
+            ORDER BY backtest_result_id, period_start
+            LIMIT $5
+            "#,
+            EQUITY_FILTER
+        ))
+            .bind::<Array<SqlUuid>, _>(result_ids.to_vec())
+            .bind::<Nullable<Timestamptz>, _>(start_time)
+            .bind::<Nullable<Timestamptz>, _>(end_time)
+            .bind::<Text, _>(bucket.interval())
+            .bind::<BigInt, _>(MAX_POINTS + 1)
+            .load::<PeriodReturn>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error computing period returns: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+    let returns = check_row_cap(returns, "Period returns")?;
+
+    debug!("Computed {} {:?} returns for {} backtest results in {}ms",
+        returns.len(), bucket, result_ids.len(), query_start.elapsed().as_millis());
+    Ok(returns)
+}
+
+/// Monthly return tables (years by calendar months) per backtest result
+pub async fn get_monthly_return_tables(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_ids: &[Uuid],
+) -> Result<BTreeMap<Uuid, Vec<MonthlyReturnRow>>, Error> {
+    let returns = get_period_returns(pool, result_ids, EquityBucket::Monthly, None, None).await?;
+
+    let mut by_result: BTreeMap<Uuid, Vec<PeriodReturn>> = BTreeMap::new();
+    for r in returns {
+        by_result.entry(r.backtest_result_id).or_default().push(r);
+    }
+    Ok(by_result
+        .into_iter()
+        .map(|(id, returns)| (id, MonthlyReturnRow::table(&returns)))
+        .collect())
+}
+
+/// Rolling return, volatility and Sharpe ratio over a trailing `window_days` calendar
+/// window of daily returns, e.g. 30 or 90 days. Days with fewer than `min_observations`
+/// returns in their window are left out.
+pub async fn get_rolling_equity_metrics(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_ids: &[Uuid],
+    window_days: i64,
+    min_observations: i64,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+) -> Result<Vec<RollingEquityMetric>, Error> {
+    let query_start = Instant::now();
+    validate_request(result_ids, start_time, end_time)?;
+    if !(2..=3650).contains(&window_days) {
+        error!("Invalid rolling window: {} days", window_days);
+        return Err(anyhow::anyhow!("Rolling window must be 2-3650 days"));
+    }
+    // The window ending on a day covers that day and the `window_days - 1` before it
+    let lookback = Duration::days(window_days - 1);
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let metrics = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::sql_query(format!(
+            r#"
+            WITH daily AS (
+                SELECT backtest_result_id,
+                       time_bucket('1 day'::INTERVAL, "timestamp") AS day,
+                       last(portfolio_value, "timestamp")::FLOAT8 AS close_value
+                FROM backtest_equity_curve
+                WHERE {}
+                GROUP BY backtest_result_id, day
+            ), returns AS (
+                SELECT backtest_result_id, day,
+                       close_value / NULLIF(LAG(close_value) OVER (PARTITION BY backtest_result_id ORDER BY day), 0) - 1 AS r
+                FROM daily
+            ), rolling AS (
+                SELECT backtest_result_id, day,
+                       COUNT(r) OVER w AS observations,
+                       -- A window holding a total loss compounds to -100%; LN only sees r > -1
+                       CASE WHEN MIN(r) OVER w <= -1 THEN -1
+                            ELSE EXP(SUM(LN(1 + CASE WHEN r > -1 THEN r END)) OVER w) - 1
+                       END AS rolling_return,
+                       STDDEV_SAMP(r) OVER w * SQRT($5) AS volatility,
+                       AVG(r) OVER w / NULLIF(STDDEV_SAMP(r) OVER w, 0) * SQRT($5) AS sharpe_ratio
+                FROM returns
+                WINDOW w AS (PARTITION BY backtest_result_id ORDER BY day RANGE BETWEEN $4 PRECEDING AND CURRENT ROW)
+            )
+            SELECT backtest_result_id, day AS "timestamp", observations, rolling_return, volatility, sharpe_ratio
+            FROM rolling
+            WHERE observations >= $6
+            ORDER BY backtest_result_id, day
+            LIMIT $7
+            "#,
+            EQUITY_FILTER
+        ))
+            .bind::<Array<SqlUuid>, _>(result_ids.to_vec())
+            .bind::<Nullable<Timestamptz>, _>(start_time)
+            .bind::<Nullable<Timestamptz>, _>(end_time)
+            .bind::<Interval, _>(lookback)
+            .bind::<Double, _>(EquityBucket::Daily.periods_per_year())
+            .bind::<BigInt, _>(min_observations.max(1))
+            .bind::<BigInt, _>(MAX_POINTS + 1)
+            .load::<RollingEquityMetric>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error computing rolling equity metrics: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+    let metrics = check_row_cap(metrics, "Rolling equity metrics")?;
+
+    debug!("Computed {} rolling {}-day metrics for {} backtest results in {}ms",
+        metrics.len(), window_days, result_ids.len(), query_start.elapsed().as_millis());
+    Ok(metrics)
+}
+
+/// Underwater curves: each point's value against the running peak. With `bucket` set the
+/// curve is built from bucket closes instead of every stored point.
+pub async fn get_drawdown_series(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_ids: &[Uuid],
+    bucket: Option<EquityBucket>,
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+) -> Result<Vec<DrawdownPoint>, Error> {
+    let query_start = Instant::now();
+    validate_request(result_ids, start_time, end_time)?;
+
+    let source = match bucket {
+        Some(b) => format!(
+            r#"SELECT backtest_result_id,
+                      time_bucket('{}'::INTERVAL, "timestamp") AS "timestamp",
+                      last(portfolio_value, "timestamp") AS portfolio_value
+               FROM backtest_equity_curve
+               WHERE {}
+               GROUP BY 1, 2"#,
+            b.interval(), EQUITY_FILTER
+        ),
+        None => format!(
+            r#"SELECT backtest_result_id, "timestamp", portfolio_value
+               FROM backtest_equity_curve
+               WHERE {}"#,
+            EQUITY_FILTER
+        ),
+    };
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let points = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone()).await.map_err(|e| anyhow::Error::from(e))?;
+
+        diesel::sql_query(format!(
+            r#"
+            WITH equity AS ({})
+            SELECT backtest_result_id, "timestamp", portfolio_value,
+                   MAX(portfolio_value) OVER w AS peak_value,
+                   (portfolio_value / NULLIF(MAX(portfolio_value) OVER w, 0) - 1)::FLOAT8 AS drawdown
+            FROM equity
+            WINDOW w AS (PARTITION BY backtest_result_id ORDER BY "timestamp" ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW)
+            ORDER BY backtest_result_id, "timestamp"
+            LIMIT $4
+            "#,
+            source
+        ))
+            .bind::<Array<SqlUuid>, _>(result_ids.to_vec())
+            .bind::<Nullable<Timestamptz>, _>(start_time)
+            .bind::<Nullable<Timestamptz>, _>(end_time)
+            .bind::<BigInt, _>(MAX_POINTS + 1)
+            .load::<DrawdownPoint>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error computing drawdown series: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await?;
+    let points = check_row_cap(points, "Drawdown points")?;
+
+    debug!("Computed {} drawdown points for {} backtest results in {}ms",
+        points.len(), result_ids.len(), query_start.elapsed().as_millis());
+    Ok(points)
+}
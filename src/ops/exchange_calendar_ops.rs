@@ -0,0 +1,384 @@
+use crate::{
+    get_timescale_connection,
+    models::exchange_calendar::{
+        ExchangeCalendar, ExchangeCalendarDetail, ExchangeHoliday, ExchangeSession,
+        NewExchangeCalendar, NewExchangeHoliday, NewExchangeSession, TradingSession
+    }
+};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Text, Timestamptz};
+use diesel::upsert::excluded;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, error, debug};
+use uuid::Uuid;
+
+const SESSION_TYPES: [&str; 3] = ["pre_market", "regular", "post_market"];
+
+/// How far `next_open` looks ahead before giving up (covers long holiday closures)
+const NEXT_OPEN_LOOKAHEAD_DAYS: i64 = 30;
+
+#[derive(QueryableByName)]
+struct TimezoneExists {
+    #[diesel(sql_type = Bool)]
+    known: bool,
+}
+
+/// Create or update the calendar for an exchange. The timezone must be a
+/// PostgreSQL/IANA zone name such as `America/New_York` or `UTC`.
+pub async fn upsert_exchange_calendar(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    calendar: NewExchangeCalendar,
+) -> Result<ExchangeCalendar, Error> {
+    info!("Upserting calendar for exchange {} ({})", calendar.exchange_id, calendar.timezone);
+
+    // Input validation
+    if calendar.timezone.is_empty() || calendar.timezone.len() > 64 {
+        error!("Invalid timezone length: {}", calendar.timezone.len());
+        return Err(anyhow::anyhow!("Invalid timezone length: {}", calendar.timezone.len()));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::exchange_calendars::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let tz = diesel::sql_query("SELECT EXISTS(SELECT 1 FROM pg_timezone_names WHERE name = $1) AS known")
+            .bind::<Text, _>(&calendar.timezone)
+            .get_result::<TimezoneExists>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        if !tz.known {
+            return Err(anyhow::anyhow!("Unknown timezone: {}", calendar.timezone));
+        }
+
+        diesel::insert_into(exchange_calendars)
+            .values(&calendar)
+            .on_conflict(exchange_id)
+            .do_update()
+            .set((
+                timezone.eq(excluded(timezone)),
+                description.eq(excluded(description)),
+            ))
+            .returning(ExchangeCalendar::as_returning())
+            .get_result::<ExchangeCalendar>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error upserting exchange calendar: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Replace the weekly session template of an exchange in a single transaction
+pub async fn set_exchange_sessions(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    xchange_id: Uuid,
+    sessions: Vec<NewExchangeSession>,
+) -> Result<Vec<ExchangeSession>, Error> {
+    let start_time = Instant::now();
+    info!("Setting {} sessions for exchange {}", sessions.len(), xchange_id);
+
+    // Input validation
+    for session in &sessions {
+        if session.exchange_id != xchange_id {
+            return Err(anyhow::anyhow!("Session belongs to exchange {}, expected {}", session.exchange_id, xchange_id));
+        }
+        if !(1..=7).contains(&session.day_of_week) {
+            return Err(anyhow::anyhow!("Invalid ISO day of week: {}", session.day_of_week));
+        }
+        if !SESSION_TYPES.contains(&session.session_type.as_str()) {
+            return Err(anyhow::anyhow!("Unknown session type: {}", session.session_type));
+        }
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let result = connection.transaction::<_, diesel::result::Error, _>(|conn| Box::pin(async {
+            use crate::schema::exchange_sessions::dsl::*;
+
+            diesel::delete(exchange_sessions.filter(exchange_id.eq(xchange_id)))
+                .execute(conn)
+                .await?;
+
+            diesel::insert_into(exchange_sessions)
+                .values(&sessions)
+                .returning(ExchangeSession::as_returning())
+                .get_results::<ExchangeSession>(conn)
+                .await
+        })).await
+            .map_err(|e| {
+                error!("Error setting exchange sessions: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        debug!("Set {} sessions in {}ms", result.len(), start_time.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
+
+/// Add a holiday, or a half day when `early_close` is set. Re-adding a date overwrites it.
+pub async fn add_exchange_holiday(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    holiday: NewExchangeHoliday,
+) -> Result<ExchangeHoliday, Error> {
+    info!("Adding holiday {} ({}) for exchange {}", holiday.name, holiday.holiday_date, holiday.exchange_id);
+
+    // Input validation
+    if holiday.name.is_empty() || holiday.name.len() > 255 {
+        error!("Invalid holiday name length: {}", holiday.name.len());
+        return Err(anyhow::anyhow!("Invalid holiday name length: {}", holiday.name.len()));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::exchange_holidays::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        diesel::insert_into(exchange_holidays)
+            .values(&holiday)
+            .on_conflict((exchange_id, holiday_date))
+            .do_update()
+            .set((
+                name.eq(excluded(name)),
+                early_close.eq(excluded(early_close)),
+            ))
+            .returning(ExchangeHoliday::as_returning())
+            .get_result::<ExchangeHoliday>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error adding exchange holiday: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Configure an exchange as a 24/7 venue (UTC, one regular session per day, no holidays),
+/// which is how the crypto venues are modelled alongside the equities calendars.
+pub async fn configure_continuous_calendar(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    xchange_id: Uuid,
+) -> Result<ExchangeCalendarDetail, Error> {
+    let calendar = upsert_exchange_calendar(pool.clone(), NewExchangeCalendar {
+        exchange_id: xchange_id,
+        timezone: "UTC".to_string(),
+        description: Some("Continuous trading, 24/7".to_string()),
+    }).await?;
+
+    let midnight = NaiveTime::MIN;
+    let sessions = (1..=7)
+        .map(|day| NewExchangeSession::new(xchange_id, day, "regular", midnight, midnight))
+        .collect();
+    let sessions = set_exchange_sessions(pool, xchange_id, sessions).await?;
+
+    Ok(ExchangeCalendarDetail {
+        calendar,
+        sessions,
+        holidays: Vec::new(),
+    })
+}
+
+/// Get the calendar, weekly sessions and holidays of an exchange by name
+pub async fn get_exchange_calendar(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    xchange: &str,
+) -> Result<Option<ExchangeCalendarDetail>, Error> {
+    let query_start = Instant::now();
+
+    // Input validation
+    if xchange.is_empty() || xchange.len() > 50 {
+        error!("Invalid exchange length: {}", xchange.len());
+        return Err(anyhow::anyhow!("Invalid exchange length: {}", xchange.len()));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::{exchange_calendars, exchange_holidays, exchange_sessions, exchanges};
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let calendar = exchange_calendars::table
+            .inner_join(exchanges::table)
+            .filter(exchanges::exchange.eq(xchange))
+            .select(ExchangeCalendar::as_select())
+            .first::<ExchangeCalendar>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        let Some(calendar) = calendar else {
+            return Ok(None);
+        };
+
+        let sessions = exchange_sessions::table
+            .filter(exchange_sessions::exchange_id.eq(calendar.exchange_id))
+            .order((exchange_sessions::day_of_week.asc(), exchange_sessions::open_time.asc()))
+            .select(ExchangeSession::as_select())
+            .load::<ExchangeSession>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        let holidays = exchange_holidays::table
+            .filter(exchange_holidays::exchange_id.eq(calendar.exchange_id))
+            .order(exchange_holidays::holiday_date.asc())
+            .select(ExchangeHoliday::as_select())
+            .load::<ExchangeHoliday>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        debug!("Fetched calendar for {} in {}ms", xchange, query_start.elapsed().as_millis());
+        Ok(Some(ExchangeCalendarDetail { calendar, sessions, holidays }))
+    }).await
+}
+
+/// Expand an exchange's calendar into concrete sessions overlapping `[start_time, end_time]`.
+/// Full-day holidays are skipped and half days are cut at their early close.
+/// Extended (pre/post market) sessions are only returned when `include_extended` is set.
+pub async fn sessions_between(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    xchange: &str,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    include_extended: bool,
+) -> Result<Vec<TradingSession>, Error> {
+    let query_start = Instant::now();
+
+    // Input validation
+    if xchange.is_empty() || xchange.len() > 50 {
+        error!("Invalid exchange length: {}", xchange.len());
+        return Err(anyhow::anyhow!("Invalid exchange length: {}", xchange.len()));
+    }
+    if start_time > end_time {
+        return Err(anyhow::anyhow!("start_time must not be after end_time"));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        // Dates are walked in venue-local time starting one day early so overnight
+        // sessions that opened the previous evening are included.
+        let result = diesel::sql_query(
+            r#"
+            WITH cal AS (
+                SELECT c.exchange_id, c.timezone
+                FROM exchange_calendars c
+                JOIN exchanges e ON e.exchange_id = c.exchange_id
+                WHERE e.exchange = $1
+            ),
+            days AS (
+                SELECT cal.exchange_id, cal.timezone, d::date AS session_date
+                FROM cal,
+                     generate_series(
+                         (($2 AT TIME ZONE cal.timezone)::date - 1)::timestamp,
+                         ($3 AT TIME ZONE cal.timezone)::date::timestamp,
+                         INTERVAL '1 day'
+                     ) d
+            ),
+            expanded AS (
+                SELECT
+                    days.session_date,
+                    s.session_type,
+                    (days.session_date + s.open_time) AT TIME ZONE days.timezone AS open_at,
+                    LEAST(
+                        CASE WHEN s.close_time <= s.open_time
+                             THEN days.session_date + 1 + s.close_time
+                             ELSE days.session_date + s.close_time
+                        END,
+                        COALESCE(days.session_date + h.early_close, 'infinity'::timestamp)
+                    ) AT TIME ZONE days.timezone AS close_at
+                FROM days
+                JOIN exchange_sessions s
+                  ON s.exchange_id = days.exchange_id
+                 AND s.day_of_week = EXTRACT(ISODOW FROM days.session_date)
+                LEFT JOIN exchange_holidays h
+                  ON h.exchange_id = days.exchange_id
+                 AND h.holiday_date = days.session_date
+                WHERE (h.id IS NULL OR (h.early_close IS NOT NULL AND s.open_time < h.early_close))
+                  AND ($4 OR s.session_type = 'regular')
+            )
+            SELECT session_date, session_type, open_at, close_at
+            FROM expanded
+            WHERE close_at > $2 AND open_at <= $3
+            ORDER BY open_at, session_type
+            LIMIT 10000
+            "#
+        )
+            .bind::<Text, _>(xchange)
+            .bind::<Timestamptz, _>(start_time)
+            .bind::<Timestamptz, _>(end_time)
+            .bind::<Bool, _>(include_extended)
+            .load::<TradingSession>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error expanding sessions for {}: {}", xchange, e);
+                anyhow::Error::from(e)
+            })?;
+
+        debug!("Expanded {} sessions for {} in {}ms", result.len(), xchange, query_start.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
+
+/// Whether the exchange is in session at `ts`. Venues without a calendar are reported closed.
+pub async fn is_open(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    xchange: &str,
+    ts: DateTime<Utc>,
+    include_extended: bool,
+) -> Result<bool, Error> {
+    let sessions = sessions_between(pool, xchange, ts, ts, include_extended).await?;
+    Ok(sessions.iter().any(|session| session.contains(ts)))
+}
+
+/// The next session opening at or after `ts`, if one exists within the lookahead window
+pub async fn next_open(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    xchange: &str,
+    ts: DateTime<Utc>,
+    include_extended: bool,
+) -> Result<Option<TradingSession>, Error> {
+    let horizon = ts + Duration::days(NEXT_OPEN_LOOKAHEAD_DAYS);
+    let sessions = sessions_between(pool, xchange, ts, horizon, include_extended).await?;
+    Ok(sessions.into_iter().find(|session| session.open_at >= ts))
+}
@@ -1,7 +1,7 @@
 use crate::{get_timescale_connection, models::exchange::{Exchange, NewExchange}};
 use diesel_async::pooled_connection::deadpool;
 use diesel_async::AsyncPgConnection;
-use diesel::{prelude::*, result::Error};
+use diesel::{prelude::*, result::Error, upsert::excluded};
 use diesel_async::RunQueryDsl;
 use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
 use std::sync::Arc;
@@ -156,3 +156,52 @@ pub async fn exchange_exists(pool: Arc<deadpool::Pool<AsyncPgConnection>>, name:
     debug!("Exchange existence check completed in {}ms: {}", start_time.elapsed().as_millis(), result);
     result
 }
+
+
+/// Insert the exchange if it is missing and return the stored row in a single statement.
+/// The no-op `DO UPDATE` makes `RETURNING` yield the existing row on conflict, so many pods
+/// can bootstrap the same venue concurrently without a check-then-insert race.
+pub async fn ensure_exchange(pool: Arc<deadpool::Pool<AsyncPgConnection>>, name: &str) -> Result<Exchange, Error> {
+    let start_time = Instant::now();
+    info!("Ensuring exchange exists: {}", name);
+
+    // Input validation
+    if name.is_empty() || name.len() > 50 {
+        error!("Invalid exchange name: empty or too long (max 50 chars)");
+        return Err(Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new("Exchange name must be between 1 and 50 characters".to_string())
+        ));
+    }
+
+    use crate::schema::exchanges::dsl::*;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new(e.to_string())
+                )
+            })?;
+
+        let result = diesel::insert_into(exchanges)
+            .values(exchange.eq(name))
+            .on_conflict(exchange)
+            .do_update()
+            .set(exchange.eq(excluded(exchange)))
+            .get_result::<Exchange>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error ensuring exchange: {}", e);
+                e
+            })?;
+
+        debug!("Ensured exchange in {}ms", start_time.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
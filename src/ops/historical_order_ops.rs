@@ -1,7 +1,16 @@
-use crate::{get_timescale_connection, models::historical_order::{HistoricalOrder, NewHistoricalOrder}};
+use crate::{
+    get_timescale_connection,
+    models::historical_order::{HistoricalOrder, NewHistoricalOrder},
+    ops::candles_ops,
+    order_lifecycle::{LifecycleReport, OrderKey, OrderLifecycleValidator, ValidationMode},
+    resampling::{self, RegimeSampleOptions, ResamplingScheme}
+};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
 use diesel_async::pooled_connection::deadpool;
 use diesel_async::AsyncPgConnection;
 use diesel::{prelude::*, result::Error};
+use diesel::sql_types::{Double, Interval, Text};
 use diesel_async::AsyncConnection;
 use diesel_async::RunQueryDsl;
 use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
@@ -49,15 +58,20 @@ pub async fn create_historical_order(pool: Arc<deadpool::Pool<AsyncPgConnection>
     }).await
 }
 
+/// Largest batch `create_historical_orders` accepts; bigger batches fail with `RollbackTransaction`
+pub const MAX_HISTORICAL_ORDER_BATCH_SIZE: usize = 10000;
+
+/// Raw insert of order events: no lifecycle checks are applied, so out-of-order or
+/// impossible transitions are stored as given. Feeds should use
+/// `create_validated_historical_orders`; this is for trusted replays and the validated path.
 pub async fn create_historical_orders(pool: Arc<deadpool::Pool<AsyncPgConnection>>, orders: Vec<NewHistoricalOrder>) -> Result<Vec<HistoricalOrder>, Error> {
     let start_time = Instant::now();
     info!("Creating {} historical orders", orders.len());
     use crate::schema::historical_orders::dsl::*;
 
     // Security: Validate batch size to prevent resource exhaustion
-    const MAX_BATCH_SIZE: usize = 10000;
-    if orders.len() > MAX_BATCH_SIZE {
-        error!("Batch size {} exceeds maximum {}", orders.len(), MAX_BATCH_SIZE);
+    if orders.len() > MAX_HISTORICAL_ORDER_BATCH_SIZE {
+        error!("Batch size {} exceeds maximum {}", orders.len(), MAX_HISTORICAL_ORDER_BATCH_SIZE);
         return Err(Error::RollbackTransaction);
     }
 
@@ -116,6 +130,174 @@ pub async fn create_historical_orders(pool: Arc<deadpool::Pool<AsyncPgConnection
     }).await
 }
 
+/// Insert order events after checking their lifecycle against what is already stored for
+/// the same orders on the same market. Returns the inserted rows together with the
+/// violation report; in `RejectBatch` mode a batch with violations inserts nothing.
+pub async fn create_validated_historical_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    orders: Vec<NewHistoricalOrder>,
+    mode: ValidationMode,
+) -> Result<(Vec<HistoricalOrder>, LifecycleReport), Error> {
+    if orders.is_empty() {
+        return Ok((Vec::new(), LifecycleReport::default()));
+    }
+
+    // Each order is validated from the events stored before its first event in this batch
+    let mut first_in_batch: HashMap<OrderKey, DateTime<Utc>> = HashMap::new();
+    for order in &orders {
+        first_in_batch
+            .entry((order.symbol.clone(), order.exchange.clone(), order.order_id.clone()))
+            .and_modify(|ts| *ts = (*ts).min(order.timestamp))
+            .or_insert(order.timestamp);
+    }
+    let keys: Vec<OrderKey> = first_in_batch.keys().cloned().collect();
+
+    let stored: Vec<HistoricalOrder> = load_order_histories(pool.clone(), &keys)
+        .await?
+        .into_iter()
+        .filter(|event| {
+            let key = (event.symbol.clone(), event.exchange.clone(), event.order_id.clone());
+            first_in_batch.get(&key).is_some_and(|first| event.timestamp < *first)
+        })
+        .collect();
+
+    let mut validator = OrderLifecycleValidator::new();
+    validator.seed(&stored);
+    let report = validator.validate(&orders);
+
+    if !report.is_clean() {
+        warn!(
+            "{} lifecycle violations across {} historical order events: {:?}",
+            report.violations.len(), report.events_checked, report.counts_by_kind()
+        );
+    }
+
+    let accepted = match mode {
+        ValidationMode::RejectBatch if !report.is_clean() => return Ok((Vec::new(), report)),
+        ValidationMode::SkipInvalid => {
+            let invalid: HashSet<usize> = report.invalid_indices().into_iter().collect();
+            orders
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !invalid.contains(i))
+                .map(|(_, order)| order)
+                .collect()
+        },
+        _ => orders,
+    };
+
+    let inserted = create_historical_orders(pool, accepted).await?;
+    Ok((inserted, report))
+}
+
+/// Audit stored order events for `sym`/`xchange` whose orders were active in
+/// `[start_time, end_time)`. Each order's full history is replayed, so orders that
+/// started before the window are not reported as missing their `new`.
+pub async fn audit_historical_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    xchange: &str,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<LifecycleReport, Error> {
+    let query_start = Instant::now();
+    info!("Auditing historical orders for {} on {} between {} and {}", sym, xchange, start_time, end_time);
+
+    // Security: Input validation
+    if sym.is_empty() || sym.len() > 20 {
+        error!("Invalid symbol length: {}", sym.len());
+        return Err(Error::RollbackTransaction);
+    }
+
+    if xchange.is_empty() || xchange.len() > 50 {
+        error!("Invalid exchange length: {}", xchange.len());
+        return Err(Error::RollbackTransaction);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let order_ids: Vec<String> = Retry::spawn(retry_strategy, || async {
+        use crate::schema::historical_orders::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new(e.to_string())
+                )
+            })?;
+
+        historical_orders
+            .filter(symbol.eq(sym).and(exchange.eq(xchange)))
+            .filter(timestamp.ge(start_time).and(timestamp.lt(end_time)))
+            .select(order_id)
+            .distinct()
+            .limit(100000) // Prevent memory exhaustion
+            .load::<String>(&mut connection)
+            .await
+    }).await?;
+
+    let keys: Vec<OrderKey> = order_ids
+        .into_iter()
+        .map(|id| (sym.to_string(), xchange.to_string(), id))
+        .collect();
+    let events = load_order_histories(pool, &keys).await?;
+    let report = OrderLifecycleValidator::new().validate(&events);
+
+    info!(
+        "Audited {} events across {} orders in {}ms: {} violations",
+        report.events_checked, report.orders_checked, query_start.elapsed().as_millis(), report.violations.len()
+    );
+    Ok(report)
+}
+
+/// Every stored event for the given orders, oldest first
+async fn load_order_histories(pool: Arc<deadpool::Pool<AsyncPgConnection>>, keys: &[OrderKey]) -> Result<Vec<HistoricalOrder>, Error> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut ids_by_market: HashMap<(&str, &str), Vec<&str>> = HashMap::new();
+    for (sym, xchange, id) in keys {
+        ids_by_market.entry((sym.as_str(), xchange.as_str())).or_default().push(id.as_str());
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::historical_orders::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new(e.to_string())
+                )
+            })?;
+
+        let mut events = Vec::new();
+        for ((sym, xchange), ids) in &ids_by_market {
+            for chunk in ids.chunks(1000) {
+                let chunk_events = historical_orders
+                    .filter(symbol.eq(*sym).and(exchange.eq(*xchange)))
+                    .filter(order_id.eq_any(chunk))
+                    .select(HistoricalOrder::as_select())
+                    .load::<HistoricalOrder>(&mut connection)
+                    .await?;
+                events.extend(chunk_events);
+            }
+        }
+        events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.event_id.cmp(&b.event_id)));
+
+        debug!("Loaded {} stored events for {} orders", events.len(), keys.len());
+        Ok(events)
+    }).await
+}
+
 pub async fn get_historical_orders(pool: Arc<deadpool::Pool<AsyncPgConnection>>, sym: &str, xchange: &str) -> Result<Vec<HistoricalOrder>, Error> {
     let start_time = Instant::now();
     info!("Getting historical orders for symbol: {} on exchange: {}", sym, xchange);
@@ -161,8 +343,11 @@ pub async fn get_historical_orders(pool: Arc<deadpool::Pool<AsyncPgConnection>>,
     }).await
 }
 
-/// Get historical orders with randomized sequence for Monte Carlo simulation
-/// This function shuffles orders within time windows while preserving market structure
+/// Get historical orders shuffled within `window_minutes`-wide `time_bucket` windows for Monte
+/// Carlo simulation. Windows and the shuffle are computed server-side in one sort, so cost
+/// grows linearly with history length rather than with history length times window count.
+/// Windows are aligned to the TimescaleDB bucket origin, not to the first event. With a
+/// `seed` the result is reproducible.
 pub async fn get_randomized_historical_orders(
     pool: Arc<deadpool::Pool<AsyncPgConnection>>, 
     sym: &str, 
@@ -170,52 +355,61 @@ pub async fn get_randomized_historical_orders(
     window_minutes: i32,  // Time window for shuffling (e.g., 30 minutes)
     seed: Option<u64>     // Random seed for reproducibility
 ) -> Result<Vec<HistoricalOrder>, Error> {
-    use chrono::Duration;
-    use rand::SeedableRng;
-    use rand::seq::SliceRandom;
-    
-    let orders = get_historical_orders(pool, sym, xchange).await?;
-    
-    if orders.is_empty() {
-        return Ok(orders);
+    let query_start = Instant::now();
+    if sym.is_empty() || sym.len() > 20 {
+        error!("Invalid symbol length: {}", sym.len());
+        return Err(Error::RollbackTransaction);
     }
+    if xchange.is_empty() || xchange.len() > 50 {
+        error!("Invalid exchange length: {}", xchange.len());
+        return Err(Error::RollbackTransaction);
+    }
+    if window_minutes <= 0 {
+        error!("Invalid shuffle window: {} minutes", window_minutes);
+        return Err(Error::RollbackTransaction);
+    }
+    let window = chrono::Duration::minutes(window_minutes as i64);
 
-    // Initialize RNG with seed for reproducibility
-    let mut rng = if let Some(s) = seed {
-        rand::rngs::StdRng::seed_from_u64(s)
-    } else {
-        rand::rngs::StdRng::from_entropy()
-    };
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
 
-    // Group orders into time windows and shuffle within each window
-    let window_duration = Duration::minutes(window_minutes as i64);
-    let start_time = orders[0].timestamp;
-    let end_time = orders[orders.len() - 1].timestamp;
-    
-    let mut randomized_orders = Vec::new();
-    let mut current_window_start = start_time;
-    
-    while current_window_start < end_time {
-        let current_window_end = current_window_start + window_duration;
-        
-        // Extract orders in current window
-        let mut window_orders: Vec<HistoricalOrder> = orders
-            .iter()
-            .filter(|order| {
-                let order_time = order.timestamp;
-                order_time >= current_window_start && order_time < current_window_end
-            })
-            .cloned()
-            .collect();
-            
-        // Shuffle orders within this window
-        window_orders.shuffle(&mut rng);
-        randomized_orders.extend(window_orders);
-        
-        current_window_start = current_window_end;
-    }
-    
-    Ok(randomized_orders)
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new(e.to_string())
+                )
+            })?;
+
+        // setseed only holds for this session, so seeding and the query share a transaction
+        let result = connection.transaction::<_, Error, _>(|conn| Box::pin(async move {
+            diesel::sql_query("SET LOCAL max_parallel_workers_per_gather = 0")
+                .execute(conn)
+                .await?;
+            if let Some(s) = seed {
+                diesel::sql_query("SELECT setseed($1)")
+                    .bind::<Double, _>(resampling::sql_seed(s))
+                    .execute(conn)
+                    .await?;
+            }
+            diesel::sql_query(resampling::window_shuffle_query("historical_orders"))
+                .bind::<Text, _>(sym)
+                .bind::<Text, _>(xchange)
+                .bind::<Interval, _>(window)
+                .load::<HistoricalOrder>(conn)
+                .await
+        }))
+        .await
+        .map_err(|e| {
+            error!("Error randomizing historical orders for {}/{}: {}", sym, xchange, e);
+            e
+        })?;
+
+        info!("Randomized {} historical orders in {}-minute windows in {}ms", result.len(), window_minutes, query_start.elapsed().as_millis());
+        Ok(result)
+    }).await
 }
 
 /// Bootstrap sample historical orders for Monte Carlo simulation
@@ -228,48 +422,56 @@ pub async fn get_bootstrap_historical_orders(
     block_size: Option<usize>, // Block bootstrap size (None for simple bootstrap)
     seed: Option<u64>
 ) -> Result<Vec<HistoricalOrder>, Error> {
-    use rand::{Rng, SeedableRng};
-    use rand::seq::SliceRandom;
-    
     let original_orders = get_historical_orders(pool, sym, xchange).await?;
-    
-    if original_orders.is_empty() {
-        return Ok(vec![]);
-    }
 
-    let mut rng = if let Some(s) = seed {
-        rand::rngs::StdRng::seed_from_u64(s)
-    } else {
-        rand::rngs::StdRng::from_entropy()
+    // A block at least as long as the series always starts at 0 instead of sampling an empty range
+    let scheme = match block_size {
+        Some(block_sz) => ResamplingScheme::FixedBlock { block_size: block_sz },
+        None => ResamplingScheme::Simple,
     };
 
-    let mut bootstrap_orders = Vec::with_capacity(sample_size);
-    
-    match block_size {
-        Some(block_sz) => {
-            // Block bootstrap to preserve autocorrelation
-            let num_blocks = (sample_size + block_sz - 1) / block_sz;
-            
-            for _ in 0..num_blocks {
-                let start_idx = rng.gen_range(0..original_orders.len().saturating_sub(block_sz));
-                let end_idx = (start_idx + block_sz).min(original_orders.len());
-                
-                for idx in start_idx..end_idx {
-                    if bootstrap_orders.len() < sample_size {
-                        bootstrap_orders.push(original_orders[idx].clone());
-                    }
-                }
-            }
-        },
-        None => {
-            // Simple bootstrap - random sampling with replacement
-            for _ in 0..sample_size {
-                if let Some(order) = original_orders.choose(&mut rng) {
-                    bootstrap_orders.push(order.clone());
-                }
-            }
-        }
+    Ok(resampling::resample(&original_orders, sample_size, scheme, seed))
+}
+
+/// Resample historical orders with any `ResamplingScheme`. Resampled orders keep their
+/// original timestamps; the returned sequence order is the sampled order.
+pub async fn get_resampled_historical_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    xchange: &str,
+    sample_size: usize,
+    scheme: ResamplingScheme,
+    seed: Option<u64>
+) -> Result<Vec<HistoricalOrder>, Error> {
+    let original_orders = get_historical_orders(pool, sym, xchange).await?;
+    debug!("Resampling {} historical orders into {} with {:?}", original_orders.len(), sample_size, scheme);
+    Ok(resampling::resample(&original_orders, sample_size, scheme, seed))
+}
+
+/// Resample only the orders placed while the market was in `options.regime`
+pub async fn get_regime_conditioned_historical_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    xchange: &str,
+    options: &RegimeSampleOptions,
+) -> Result<Vec<HistoricalOrder>, Error> {
+    let original_orders = get_historical_orders(pool.clone(), sym, xchange).await?;
+    let (start, end) = match (original_orders.as_slice().first(), original_orders.last()) {
+        (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+        _ => return Ok(vec![]),
+    };
+
+    let (candles, regimes) = candles_ops::get_candle_regimes(pool, sym, xchange, &options.timeframe, start, end, options.regime_window)
+        .await
+        .map_err(|e| {
+            error!("Failed to classify regimes for {}/{}: {}", sym, xchange, e);
+            Error::DatabaseError(diesel::result::DatabaseErrorKind::Unknown, Box::new(e.to_string()))
+        })?;
+
+    let labels = resampling::label_by_candle_regime(&original_orders, |o| o.timestamp, &candles, &regimes);
+    let sampled = resampling::regime_conditioned_resample(&original_orders, &labels, &options.regime, options.sample_size, options.scheme, options.seed);
+    if sampled.is_empty() {
+        warn!("No historical orders for {}/{} fall in the {:?} regime", sym, xchange, options.regime);
     }
-    
-    Ok(bootstrap_orders)
+    Ok(sampled)
 }
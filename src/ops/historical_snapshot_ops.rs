@@ -1,11 +1,18 @@
-use crate::{get_timescale_connection, models::historical_snapshot::{HistoricalSnapshot, NewHistoricalSnapshot}};
+use crate::{
+    get_timescale_connection,
+    models::historical_snapshot::{HistoricalSnapshot, NewHistoricalSnapshot},
+    ops::candles_ops,
+    resampling::{self, RegimeSampleOptions, ResamplingScheme}
+};
 use diesel_async::pooled_connection::deadpool;
 use diesel_async::AsyncPgConnection;
 use diesel::{prelude::*, result::Error};
+use diesel::sql_types::{Double, Interval, Text};
 use diesel_async::{AsyncConnection, RunQueryDsl};
 use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
 use tracing::{info, error, warn};
 use std::sync::Arc;
+use std::time::Instant;
 
 pub async fn create_historical_snapshot(pool: Arc<deadpool::Pool<AsyncPgConnection>>, snapshots: Vec<NewHistoricalSnapshot>) -> Result<Vec<HistoricalSnapshot>, Error> {
     if snapshots.is_empty() {
@@ -107,8 +114,11 @@ pub async fn get_historical_snapshot(pool: Arc<deadpool::Pool<AsyncPgConnection>
     }).await
 }
 
-/// Get historical snapshots with randomized sequence for Monte Carlo simulation
-/// This function shuffles snapshots within time windows while preserving market structure
+/// Get historical snapshots shuffled within `window_minutes`-wide `time_bucket` windows for Monte
+/// Carlo simulation. Windows and the shuffle are computed server-side in one sort, so cost
+/// grows linearly with history length rather than with history length times window count.
+/// Windows are aligned to the TimescaleDB bucket origin, not to the first event. With a
+/// `seed` the result is reproducible.
 pub async fn get_randomized_historical_snapshots(
     pool: Arc<deadpool::Pool<AsyncPgConnection>>, 
     sym: &str, 
@@ -116,52 +126,61 @@ pub async fn get_randomized_historical_snapshots(
     window_minutes: i32,  // Time window for shuffling (e.g., 30 minutes)
     seed: Option<u64>     // Random seed for reproducibility
 ) -> Result<Vec<HistoricalSnapshot>, Error> {
-    use chrono::Duration;
-    use rand::SeedableRng;
-    use rand::seq::SliceRandom;
-    
-    let snapshots = get_historical_snapshot(pool, sym, xchange).await?;
-    
-    if snapshots.is_empty() {
-        return Ok(snapshots);
+    let query_start = Instant::now();
+    if sym.is_empty() || sym.len() > 50 {
+        warn!("Invalid symbol length: {} characters", sym.len());
+        return Err(Error::RollbackTransaction);
+    }
+    if xchange.is_empty() || xchange.len() > 50 {
+        warn!("Invalid exchange length: {} characters", xchange.len());
+        return Err(Error::RollbackTransaction);
+    }
+    if window_minutes <= 0 {
+        error!("Invalid shuffle window: {} minutes", window_minutes);
+        return Err(Error::RollbackTransaction);
     }
+    let window = chrono::Duration::minutes(window_minutes as i64);
 
-    // Initialize RNG with seed for reproducibility
-    let mut rng = if let Some(s) = seed {
-        rand::rngs::StdRng::seed_from_u64(s)
-    } else {
-        rand::rngs::StdRng::from_entropy()
-    };
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
 
-    // Group snapshots into time windows and shuffle within each window
-    let window_duration = Duration::minutes(window_minutes as i64);
-    let start_time = snapshots[0].timestamp;
-    let end_time = snapshots[snapshots.len() - 1].timestamp;
-    
-    let mut randomized_snapshots = Vec::new();
-    let mut current_window_start = start_time;
-    
-    while current_window_start < end_time {
-        let current_window_end = current_window_start + window_duration;
-        
-        // Extract snapshots in current window
-        let mut window_snapshots: Vec<HistoricalSnapshot> = snapshots
-            .iter()
-            .filter(|snapshot| {
-                let snapshot_time = snapshot.timestamp;
-                snapshot_time >= current_window_start && snapshot_time < current_window_end
-            })
-            .cloned()
-            .collect();
-            
-        // Shuffle snapshots within this window
-        window_snapshots.shuffle(&mut rng);
-        randomized_snapshots.extend(window_snapshots);
-        
-        current_window_start = current_window_end;
-    }
-    
-    Ok(randomized_snapshots)
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new(e.to_string())
+                )
+            })?;
+
+        // setseed only holds for this session, so seeding and the query share a transaction
+        let result = connection.transaction::<_, Error, _>(|conn| Box::pin(async move {
+            diesel::sql_query("SET LOCAL max_parallel_workers_per_gather = 0")
+                .execute(conn)
+                .await?;
+            if let Some(s) = seed {
+                diesel::sql_query("SELECT setseed($1)")
+                    .bind::<Double, _>(resampling::sql_seed(s))
+                    .execute(conn)
+                    .await?;
+            }
+            diesel::sql_query(resampling::window_shuffle_query("historical_snapshot"))
+                .bind::<Text, _>(sym)
+                .bind::<Text, _>(xchange)
+                .bind::<Interval, _>(window)
+                .load::<HistoricalSnapshot>(conn)
+                .await
+        }))
+        .await
+        .map_err(|e| {
+            error!("Error randomizing historical snapshots for {}/{}: {}", sym, xchange, e);
+            e
+        })?;
+
+        info!("Randomized {} historical snapshots in {}-minute windows in {}ms", result.len(), window_minutes, query_start.elapsed().as_millis());
+        Ok(result)
+    }).await
 }
 
 /// Bootstrap sample historical snapshots for Monte Carlo simulation
@@ -174,48 +193,55 @@ pub async fn get_bootstrap_historical_snapshots(
     block_size: Option<usize>, // Block bootstrap size (None for simple bootstrap)
     seed: Option<u64>
 ) -> Result<Vec<HistoricalSnapshot>, Error> {
-    use rand::{Rng, SeedableRng};
-    use rand::seq::SliceRandom;
-    
     let original_snapshots = get_historical_snapshot(pool, sym, xchange).await?;
-    
-    if original_snapshots.is_empty() {
-        return Ok(vec![]);
-    }
 
-    let mut rng = if let Some(s) = seed {
-        rand::rngs::StdRng::seed_from_u64(s)
-    } else {
-        rand::rngs::StdRng::from_entropy()
+    // A block at least as long as the series always starts at 0 instead of sampling an empty range
+    let scheme = match block_size {
+        Some(block_sz) => ResamplingScheme::FixedBlock { block_size: block_sz },
+        None => ResamplingScheme::Simple,
     };
 
-    let mut bootstrap_snapshots = Vec::with_capacity(sample_size);
-    
-    match block_size {
-        Some(block_sz) => {
-            // Block bootstrap to preserve autocorrelation
-            let num_blocks = (sample_size + block_sz - 1) / block_sz;
-            
-            for _ in 0..num_blocks {
-                let start_idx = rng.gen_range(0..original_snapshots.len().saturating_sub(block_sz));
-                let end_idx = (start_idx + block_sz).min(original_snapshots.len());
-                
-                for idx in start_idx..end_idx {
-                    if bootstrap_snapshots.len() < sample_size {
-                        bootstrap_snapshots.push(original_snapshots[idx].clone());
-                    }
-                }
-            }
-        },
-        None => {
-            // Simple bootstrap - random sampling with replacement
-            for _ in 0..sample_size {
-                if let Some(snapshot) = original_snapshots.choose(&mut rng) {
-                    bootstrap_snapshots.push(snapshot.clone());
-                }
-            }
-        }
+    Ok(resampling::resample(&original_snapshots, sample_size, scheme, seed))
+}
+
+/// Resample historical snapshots with any `ResamplingScheme`. Resampled snapshots keep
+/// their original timestamps; the returned sequence order is the sampled order.
+pub async fn get_resampled_historical_snapshots(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    xchange: &str,
+    sample_size: usize,
+    scheme: ResamplingScheme,
+    seed: Option<u64>
+) -> Result<Vec<HistoricalSnapshot>, Error> {
+    let original_snapshots = get_historical_snapshot(pool, sym, xchange).await?;
+    Ok(resampling::resample(&original_snapshots, sample_size, scheme, seed))
+}
+
+/// Resample only the snapshots taken while the market was in `options.regime`
+pub async fn get_regime_conditioned_historical_snapshots(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    sym: &str,
+    xchange: &str,
+    options: &RegimeSampleOptions,
+) -> Result<Vec<HistoricalSnapshot>, Error> {
+    let original_snapshots = get_historical_snapshot(pool.clone(), sym, xchange).await?;
+    let (start, end) = match (original_snapshots.as_slice().first(), original_snapshots.last()) {
+        (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+        _ => return Ok(vec![]),
+    };
+
+    let (candles, regimes) = candles_ops::get_candle_regimes(pool, sym, xchange, &options.timeframe, start, end, options.regime_window)
+        .await
+        .map_err(|e| {
+            error!("Failed to classify regimes for {}/{}: {}", sym, xchange, e);
+            Error::DatabaseError(diesel::result::DatabaseErrorKind::Unknown, Box::new(e.to_string()))
+        })?;
+
+    let labels = resampling::label_by_candle_regime(&original_snapshots, |s| s.timestamp, &candles, &regimes);
+    let sampled = resampling::regime_conditioned_resample(&original_snapshots, &labels, &options.regime, options.sample_size, options.scheme, options.seed);
+    if sampled.is_empty() {
+        warn!("No historical snapshots for {}/{} fall in the {:?} regime", sym, xchange, options.regime);
     }
-    
-    Ok(bootstrap_snapshots)
+    Ok(sampled)
 }
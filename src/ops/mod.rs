@@ -1,8 +1,17 @@
+pub mod backtest_lifecycle_ops;
+pub mod backtest_report_ops;
 pub mod backtest_result_ops;
+pub mod backtest_segment_ops;
+pub mod benchmark_ops;
+pub mod bulk_ingest_ops;
 pub mod candles_ops;
+pub mod corporate_action_ops;
+pub mod equity_analytics_ops;
+pub mod exchange_calendar_ops;
 pub mod exchange_ops;
 pub mod historical_order_ops;
 pub mod historical_snapshot_ops;
+pub mod monte_carlo_ops;
 pub mod open_buy_order_ops;
 pub mod open_sell_order_ops;
 pub mod order_book_ops;
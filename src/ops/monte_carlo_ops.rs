@@ -0,0 +1,363 @@
+use crate::{
+    get_timescale_connection,
+    models::monte_carlo::{
+        MetricDistribution, MonteCarloDistribution, MonteCarloPath, MonteCarloRun,
+        NewMonteCarloPath, NewMonteCarloRun
+    }
+};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Uuid as SqlUuid};
+use diesel::upsert::excluded;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use anyhow::Error;
+use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, error, debug};
+use uuid::Uuid;
+
+const DATA_SOURCES: [&str; 4] = ["orders", "snapshots", "candles", "trades"];
+const RUN_STATUSES: [&str; 3] = ["running", "completed", "failed"];
+
+#[derive(QueryableByName)]
+struct PathCounts {
+    #[diesel(sql_type = BigInt)]
+    paths: i64,
+    #[diesel(sql_type = BigInt)]
+    paths_with_results: i64,
+}
+
+/// Record the configuration of a Monte Carlo run before its paths are simulated
+pub async fn create_monte_carlo_run(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    run: NewMonteCarloRun,
+) -> Result<MonteCarloRun, Error> {
+    info!("Creating Monte Carlo run for {} on {}/{} ({} paths, {})",
+        run.strategy_name, run.symbol, run.exchange, run.num_paths, run.resampling_scheme);
+
+    // Input validation
+    if run.strategy_name.is_empty() || run.strategy_name.len() > 255 {
+        error!("Invalid strategy name length: {}", run.strategy_name.len());
+        return Err(anyhow::anyhow!("Invalid strategy name length: {}", run.strategy_name.len()));
+    }
+    if run.symbol.is_empty() || run.symbol.len() > 20 {
+        error!("Invalid symbol length: {}", run.symbol.len());
+        return Err(anyhow::anyhow!("Invalid symbol length: {}", run.symbol.len()));
+    }
+    if run.exchange.is_empty() || run.exchange.len() > 50 {
+        error!("Invalid exchange length: {}", run.exchange.len());
+        return Err(anyhow::anyhow!("Invalid exchange length: {}", run.exchange.len()));
+    }
+    if !DATA_SOURCES.contains(&run.data_source.as_str()) {
+        error!("Invalid data source: {}", run.data_source);
+        return Err(anyhow::anyhow!("Invalid data source: {}", run.data_source));
+    }
+    if run.num_paths <= 0 {
+        return Err(anyhow::anyhow!("A Monte Carlo run needs at least one path"));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::monte_carlo_runs::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        diesel::insert_into(monte_carlo_runs)
+            .values(&run)
+            .returning(MonteCarloRun::as_returning())
+            .get_result::<MonteCarloRun>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error creating Monte Carlo run: {}", e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Record paths of a run. Re-recording a `path_index` updates its seed, scheme and backtest link.
+pub async fn record_monte_carlo_paths(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    paths: Vec<NewMonteCarloPath>,
+) -> Result<Vec<MonteCarloPath>, Error> {
+    let query_start = Instant::now();
+    if paths.is_empty() {
+        return Ok(vec![]);
+    }
+    if paths.len() > 10000 {
+        error!("Too many Monte Carlo paths in one batch: {}", paths.len());
+        return Err(anyhow::anyhow!("Batch size {} exceeds the maximum of 10000 paths", paths.len()));
+    }
+    if let Some(p) = paths.iter().find(|p| p.path_index < 0) {
+        return Err(anyhow::anyhow!("Invalid path index: {}", p.path_index));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::monte_carlo_paths::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let result = diesel::insert_into(monte_carlo_paths)
+            .values(&paths)
+            .on_conflict((run_id, path_index))
+            .do_update()
+            .set((
+                seed.eq(excluded(seed)),
+                resampling_scheme.eq(excluded(resampling_scheme)),
+                scheme_parameters.eq(excluded(scheme_parameters)),
+                backtest_id.eq(excluded(backtest_id)),
+            ))
+            .returning(MonteCarloPath::as_returning())
+            .get_results::<MonteCarloPath>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error recording Monte Carlo paths: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        debug!("Recorded {} Monte Carlo paths in {}ms", result.len(), query_start.elapsed().as_millis());
+        Ok(result)
+    }).await
+}
+
+/// Link a path to the backtest result it produced
+pub async fn link_monte_carlo_path(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    run: Uuid,
+    index: i32,
+    result_backtest_id: Uuid,
+) -> Result<MonteCarloPath, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::monte_carlo_paths::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        diesel::update(monte_carlo_paths.filter(run_id.eq(run)).filter(path_index.eq(index)))
+            .set(backtest_id.eq(Some(result_backtest_id)))
+            .returning(MonteCarloPath::as_returning())
+            .get_result::<MonteCarloPath>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error linking path {} of run {} to backtest {}: {}", index, run, result_backtest_id, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+/// Mark a run `completed` or `failed`
+pub async fn finish_monte_carlo_run(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    run: Uuid,
+    final_status: &str,
+) -> Result<MonteCarloRun, Error> {
+    if !RUN_STATUSES.contains(&final_status) || final_status == "running" {
+        error!("Invalid final Monte Carlo run status: {}", final_status);
+        return Err(anyhow::anyhow!("Invalid final run status: {}", final_status));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::monte_carlo_runs::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        diesel::update(monte_carlo_runs.filter(id.eq(run)))
+            .set((status.eq(final_status), completed_at.eq(Some(chrono::Utc::now()))))
+            .returning(MonteCarloRun::as_returning())
+            .get_result::<MonteCarloRun>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error finishing Monte Carlo run {}: {}", run, e);
+                anyhow::Error::from(e)
+            })
+    }).await
+}
+
+pub async fn get_monte_carlo_run(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    run: Uuid,
+) -> Result<Option<MonteCarloRun>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::monte_carlo_runs::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        monte_carlo_runs
+            .filter(id.eq(run))
+            .select(MonteCarloRun::as_select())
+            .first::<MonteCarloRun>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Paths of a run ordered by `path_index`
+pub async fn get_monte_carlo_paths(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    run: Uuid,
+) -> Result<Vec<MonteCarloPath>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::monte_carlo_paths::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        monte_carlo_paths
+            .filter(run_id.eq(run))
+            .order(path_index.asc())
+            .select(MonteCarloPath::as_select())
+            .load::<MonteCarloPath>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// The Monte Carlo path that produced a backtest, if any
+pub async fn get_monte_carlo_path_for_backtest(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    result_backtest_id: Uuid,
+) -> Result<Option<MonteCarloPath>, Error> {
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        use crate::schema::monte_carlo_paths::dsl::*;
+
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        monte_carlo_paths
+            .filter(backtest_id.eq(result_backtest_id))
+            .select(MonteCarloPath::as_select())
+            .first::<MonteCarloPath>(&mut connection)
+            .await
+            .optional()
+            .map_err(|e| anyhow::Error::from(e))
+    }).await
+}
+
+/// Percentiles, mean and spread of `total_return`, `max_drawdown` and `sharpe_ratio` across
+/// the paths of a run that are linked to a backtest result. Paths with a NULL sharpe ratio
+/// are left out of that metric only.
+pub async fn get_monte_carlo_distribution(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    run: Uuid,
+) -> Result<MonteCarloDistribution, Error> {
+    let query_start = Instant::now();
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to get database connection: {}", e);
+                anyhow::Error::from(e)
+            })?;
+
+        let counts = diesel::sql_query(
+            "SELECT COUNT(*) AS paths, COUNT(backtest_id) AS paths_with_results \
+             FROM monte_carlo_paths WHERE run_id = $1"
+        )
+            .bind::<SqlUuid, _>(run)
+            .get_result::<PathCounts>(&mut connection)
+            .await
+            .map_err(|e| anyhow::Error::from(e))?;
+
+        let metrics = diesel::sql_query(
+            r#"
+            WITH results AS (
+                SELECT br.total_return::FLOAT8 AS total_return,
+                       br.max_drawdown::FLOAT8 AS max_drawdown,
+                       br.sharpe_ratio::FLOAT8 AS sharpe_ratio
+                FROM monte_carlo_paths p
+                JOIN backtest_results br ON br.backtest_id = p.backtest_id
+                WHERE p.run_id = $1
+            ),
+            observations AS (
+                SELECT 'total_return' AS metric, total_return AS value FROM results
+                UNION ALL
+                SELECT 'max_drawdown', max_drawdown FROM results
+                UNION ALL
+                SELECT 'sharpe_ratio', sharpe_ratio FROM results
+            )
+            SELECT metric,
+                   COUNT(value) AS observations,
+                   AVG(value) AS mean,
+                   STDDEV_SAMP(value) AS std_dev,
+                   MIN(value) AS min,
+                   percentile_cont(0.05) WITHIN GROUP (ORDER BY value) AS p5,
+                   percentile_cont(0.25) WITHIN GROUP (ORDER BY value) AS p25,
+                   percentile_cont(0.50) WITHIN GROUP (ORDER BY value) AS p50,
+                   percentile_cont(0.75) WITHIN GROUP (ORDER BY value) AS p75,
+                   percentile_cont(0.95) WITHIN GROUP (ORDER BY value) AS p95,
+                   MAX(value) AS max
+            FROM observations
+            GROUP BY metric
+            "#
+        )
+            .bind::<SqlUuid, _>(run)
+            .load::<MetricDistribution>(&mut connection)
+            .await
+            .map_err(|e| {
+                error!("Error computing Monte Carlo distribution for run {}: {}", run, e);
+                anyhow::Error::from(e)
+            })?;
+
+        let take = |name: &str| metrics.iter().find(|m| m.metric == name).cloned();
+        let distribution = MonteCarloDistribution {
+            run_id: run,
+            paths: counts.paths,
+            paths_with_results: counts.paths_with_results,
+            total_return: take("total_return"),
+            max_drawdown: take("max_drawdown"),
+            sharpe_ratio: take("sharpe_ratio"),
+        };
+
+        info!("Computed distribution over {} of {} paths for run {} in {}ms",
+            distribution.paths_with_results, distribution.paths, run, query_start.elapsed().as_millis());
+        Ok(distribution)
+    }).await
+}
@@ -214,7 +214,9 @@ pub async fn get_orderbook_by_exchange_id_and_security_id(pool: Arc<deadpool::Po
     }
 }
 
-pub async fn orderbook_exists(pool: Arc<deadpool::Pool<AsyncPgConnection>>, s_id: Uuid) -> bool {
+/// Whether an order book exists for `s_id`. Database errors are returned rather than read as
+/// "missing", so callers don't try to create a book that is already there.
+pub async fn orderbook_exists(pool: Arc<deadpool::Pool<AsyncPgConnection>>, s_id: Uuid) -> Result<bool, Error> {
     let start_time = Instant::now();
     let logger = UltraLogger::new("databaseschema".to_string()); let _ = logger.info(format!("Checking if orderbook exists for security_id: {}", s_id)).await;
     use crate::schema::order_books::dsl::*;
@@ -235,12 +237,98 @@ pub async fn orderbook_exists(pool: Arc<deadpool::Pool<AsyncPgConnection>>, s_id
         .filter(security_id.eq(s_id))
         .first::<OrderBook>(&mut connection)
         .await
+        .optional()
+    }).await;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    match result {
+        Ok(book) => {
+            let _ = logger.debug(format!("Orderbook existence check completed in {}ms: {}", start_time.elapsed().as_millis(), book.is_some())).await;
+            Ok(book.is_some())
+        }
+        Err(e) => {
+            let _ = logger.error(format!("Error checking orderbook for security_id {}: {}", s_id, e)).await;
+            Err(e)
+        }
+    }
+}
+
+
+/// Ensure the security, exchange and order book for `sym` on `xchange` exist and return the book.
+/// Everything happens in one statement: each CTE upserts with a no-op `DO UPDATE` so `RETURNING`
+/// always yields a row, which keeps concurrent bootstraps from many pods race-free. A new
+/// security gets its initial `symbol_history` window, as in `create_security`.
+/// Order books are unique per symbol, so a symbol already booked on another exchange is an
+/// error rather than that exchange's book.
+pub async fn ensure_orderbook(pool: Arc<deadpool::Pool<AsyncPgConnection>>, sym: &str, xchange: &str) -> Result<OrderBook, Error> {
+    let start_time = Instant::now();
+    let logger = UltraLogger::new("databaseschema".to_string()); let _ = logger.info(format!("Ensuring orderbook exists for {} on {}", sym, xchange)).await;
+
+    // Input validation
+    if sym.is_empty() || sym.len() > 20 || xchange.is_empty() || xchange.len() > 50 {
+        let logger = UltraLogger::new("databaseschema".to_string());
+        let _ = logger.error(format!("Invalid symbol or exchange for orderbook: {} / {}", sym, xchange)).await;
+        return Err(Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new("Symbol must be 1-20 characters and exchange 1-50 characters".to_string())
+        ));
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let result = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+        .await
         .map_err(|e| {
-            e
-        })
-    }).await.is_ok();
-    
-    let logger = UltraLogger::new("databaseschema".to_string()); 
-    let _ = logger.debug(format!("Orderbook existence check completed in {}ms: {}", start_time.elapsed().as_millis(), result)).await;
-    result
+            Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string())
+            )
+        })?;
+
+    diesel::sql_query(
+        "WITH sec AS ( \
+            INSERT INTO securities (symbol) VALUES ($1) \
+            ON CONFLICT (symbol) DO UPDATE SET symbol = EXCLUDED.symbol \
+            RETURNING security_id, symbol, created_at \
+        ), hist AS ( \
+            INSERT INTO symbol_history (security_id, symbol, valid_from, status) \
+            SELECT security_id, symbol, created_at, 'active' FROM sec \
+            ON CONFLICT (security_id, valid_from) DO NOTHING \
+        ), ex AS ( \
+            INSERT INTO exchanges (exchange) VALUES ($2) \
+            ON CONFLICT (exchange) DO UPDATE SET exchange = EXCLUDED.exchange \
+            RETURNING exchange_id, exchange \
+        ) \
+        INSERT INTO order_books (symbol, exchange, security_id, exchange_id) \
+        SELECT sec.symbol, ex.exchange, sec.security_id, ex.exchange_id FROM sec, ex \
+        ON CONFLICT (symbol) DO UPDATE SET symbol = EXCLUDED.symbol \
+        RETURNING *"
+    )
+        .bind::<diesel::sql_types::VarChar, _>(sym)
+        .bind::<diesel::sql_types::VarChar, _>(xchange)
+        .get_result::<OrderBook>(&mut connection)
+        .await
+    }).await;
+
+    match result {
+        Ok(orderbook) if orderbook.exchange != xchange => {
+            let logger = UltraLogger::new("databaseschema".to_string());
+            let _ = logger.error(format!("Orderbook for {} already exists on {}, not {}", sym, orderbook.exchange, xchange)).await;
+            Err(Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                Box::new(format!("Orderbook for {} already exists on exchange {}", sym, orderbook.exchange))
+            ))
+        }
+        Ok(orderbook) => {
+            let logger = UltraLogger::new("databaseschema".to_string());
+            let _ = logger.debug(format!("Orderbook ensured in {}ms", start_time.elapsed().as_millis())).await;
+            Ok(orderbook)
+        }
+        Err(e) => {
+            let logger = UltraLogger::new("databaseschema".to_string());
+            let _ = logger.error(format!("Error ensuring orderbook for {} on {}: {}", sym, xchange, e)).await;
+            Err(e)
+        }
+    }
 }
@@ -1,10 +1,10 @@
 use std::sync::Arc;
 use std::time::Instant;
 
-use crate::{get_timescale_connection, models::security::{NewSecurity, Security}};
+use crate::{get_timescale_connection, models::{corporate_action::NewSymbolHistory, security::{NewSecurity, Security}}};
 use diesel_async::pooled_connection::deadpool;
-use diesel_async::AsyncPgConnection;
-use diesel::{prelude::*, result::Error};
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use diesel::{prelude::*, result::Error, upsert::excluded};
 use diesel_async::RunQueryDsl;
 use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
 use ultra_logger::UltraLogger;
@@ -29,23 +29,28 @@ pub async fn create_security(pool: Arc<deadpool::Pool<AsyncPgConnection>>, new_s
             )
         })?;
         
-        diesel::insert_into(securities)
-        .values(&new_security)
-        .on_conflict(security_id)
-        .do_update()
-        .set(&new_security)
-        .execute(&mut connection)
-        .await?;
-
-        let result = securities
-        .filter(symbol.eq(&new_security.symbol))
-        .first(&mut connection)
-        .await
-        .map_err(|e| {
-            let logger = UltraLogger::new("databaseschema".to_string());
-            let _ = logger.error(format!("Error fetching new security: {}", e));
-            e
-        })?;
+        let result = connection.transaction::<_, Error, _>(|conn| Box::pin(async {
+            diesel::insert_into(securities)
+            .values(&new_security)
+            .on_conflict(security_id)
+            .do_update()
+            .set(&new_security)
+            .execute(conn)
+            .await?;
+
+            let created: Security = securities
+            .filter(symbol.eq(&new_security.symbol))
+            .first(conn)
+            .await
+            .map_err(|e| {
+                let logger = UltraLogger::new("databaseschema".to_string());
+                let _ = logger.error(format!("Error fetching new security: {}", e));
+                e
+            })?;
+
+            open_initial_symbol_window(conn, &created).await?;
+            Ok(created)
+        })).await?;
         
         let logger = UltraLogger::new("databaseschema".to_string());
         let _ = logger.debug(format!("Created security in {}ms", start_time.elapsed().as_millis())).await;
@@ -211,3 +216,83 @@ pub async fn security_exists(pool: Arc<deadpool::Pool<AsyncPgConnection>>, sym:
     let _ = logger.debug(format!("Security existence check completed in {}ms: {}", start_time.elapsed().as_millis(), result)).await;
     result
 }
+
+
+/// Insert the security if it is missing and return the stored row in a single statement.
+/// The no-op `DO UPDATE` makes `RETURNING` yield the existing row on conflict, so many pods
+/// can bootstrap the same symbol concurrently without a check-then-insert race. A new
+/// security also gets its first `symbol_history` window in the same transaction.
+pub async fn ensure_security(pool: Arc<deadpool::Pool<AsyncPgConnection>>, sym: &str) -> Result<Security, Error> {
+    let start_time = Instant::now();
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.info(format!("Ensuring security exists: {}", sym)).await;
+
+    // Input validation
+    if sym.is_empty() || sym.len() > 20 {
+        let logger = UltraLogger::new("databaseschema".to_string());
+        let _ = logger.error("Invalid symbol: empty or too long (max 20 chars)".to_string()).await;
+        return Err(Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new("Symbol must be between 1 and 20 characters".to_string())
+        ));
+    }
+
+    use crate::schema::securities::dsl::*;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+        .await
+        .map_err(|e| {
+            let logger = UltraLogger::new("databaseschema".to_string());
+            let _ = logger.error(format!("Failed to get database connection: {}", e));
+            Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string())
+            )
+        })?;
+
+        let result = connection.transaction::<_, Error, _>(|conn| Box::pin(async {
+            let ensured = diesel::insert_into(securities)
+            .values(symbol.eq(sym))
+            .on_conflict(symbol)
+            .do_update()
+            .set(symbol.eq(excluded(symbol)))
+            .get_result::<Security>(conn)
+            .await?;
+
+            open_initial_symbol_window(conn, &ensured).await?;
+            Ok(ensured)
+        })).await
+        .map_err(|e| {
+            let logger = UltraLogger::new("databaseschema".to_string());
+            let _ = logger.error(format!("Error ensuring security: {}", e));
+            e
+        })?;
+
+        let logger = UltraLogger::new("databaseschema".to_string());
+        let _ = logger.debug(format!("Ensured security in {}ms", start_time.elapsed().as_millis())).await;
+        Ok(result)
+    }).await
+}
+
+/// Open the first `symbol_history` window of a security, starting at its `created_at`.
+/// A no-op when the window already exists, so it is safe on the upsert paths.
+async fn open_initial_symbol_window(conn: &mut AsyncPgConnection, security: &Security) -> Result<(), Error> {
+    use crate::schema::symbol_history;
+
+    diesel::insert_into(symbol_history::table)
+    .values(&NewSymbolHistory {
+        security_id: security.security_id,
+        symbol: security.symbol.clone(),
+        valid_from: security.created_at,
+        valid_to: None,
+        status: "active".to_string(),
+    })
+    .on_conflict((symbol_history::security_id, symbol_history::valid_from))
+    .do_nothing()
+    .execute(conn)
+    .await?;
+    Ok(())
+}
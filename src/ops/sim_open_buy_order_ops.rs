@@ -1,7 +1,9 @@
 use std::sync::Arc;
+use std::{cmp::Reverse, collections::{BTreeMap, HashSet}};
 use std::time::Instant;
+use bigdecimal::BigDecimal;
 use diesel::prelude::*;
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncConnection, RunQueryDsl};
 use diesel_async::pooled_connection::deadpool;
 use diesel_async::AsyncPgConnection;
 use anyhow::Error;
@@ -87,3 +89,259 @@ pub async fn delete_sim_open_buy_order(
 
     Ok(result)
 }
+
+/// Create simulation open buy orders in batches. Orders whose `(backtest_id, unique_id)` is
+/// already stored, or repeated within the batch, are skipped and returned as stored.
+pub async fn create_sim_open_buy_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    orders: Vec<NewSimOpenBuyOrder>,
+) -> Result<Vec<SimOpenBuyOrder>, Error> {
+    if orders.is_empty() {
+        return Err(anyhow::anyhow!("Cannot create orders with empty input"));
+    }
+    if orders.len() > 1000 {
+        return Err(anyhow::anyhow!("Batch size too large (max 1000)"));
+    }
+
+    let start_time = Instant::now();
+    let mut conn = get_timescale_connection(pool).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    use crate::schema::sim_open_buy_orders::dsl::*;
+
+    // Process in smaller batches to reduce deadlock probability
+    const BATCH_SIZE: usize = 25;
+    let mut all_results = Vec::with_capacity(orders.len());
+
+    for chunk in orders.chunks(BATCH_SIZE) {
+        let batch_results = conn.transaction::<_, diesel::result::Error, _>(|conn| Box::pin(async move {
+            let backtest_ids: Vec<Uuid> = chunk.iter().map(|order| order.backtest_id).collect();
+            let unique_ids: Vec<&str> = chunk.iter().map(|order| order.unique_id.as_str()).collect();
+
+            // The primary key includes created_at, which defaults to now(), so no conflict
+            // clause can catch a resent order; skip the ids already stored instead
+            let existing: HashSet<(Uuid, String)> = sim_open_buy_orders
+                .filter(backtest_id.eq_any(&backtest_ids))
+                .filter(unique_id.eq_any(&unique_ids))
+                .select((backtest_id, unique_id))
+                .load::<(Uuid, String)>(conn)
+                .await?
+                .into_iter()
+                .collect();
+            let mut seen = HashSet::new();
+            let fresh: Vec<&NewSimOpenBuyOrder> = chunk
+                .iter()
+                .filter(|order| !existing.contains(&(order.backtest_id, order.unique_id.clone())))
+                .filter(|order| seen.insert((order.backtest_id, order.unique_id.as_str())))
+                .collect();
+
+            if !fresh.is_empty() {
+                diesel::insert_into(sim_open_buy_orders)
+                    .values(fresh)
+                    .execute(conn)
+                    .await?;
+            }
+
+            sim_open_buy_orders
+                .filter(backtest_id.eq_any(backtest_ids))
+                .filter(unique_id.eq_any(unique_ids))
+                .load::<SimOpenBuyOrder>(conn)
+                .await
+        })).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+        all_results.extend(batch_results);
+    }
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.info(format!("Created {} simulation buy orders in {:?}", all_results.len(), start_time.elapsed())).await;
+
+    Ok(all_results)
+}
+
+/// Change the price and quantity of a simulation open buy order
+pub async fn modify_sim_open_buy_order(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    backtest_id_param: Uuid,
+    unique_order_id: &str,
+    new_price_level: &BigDecimal,
+    new_buy_quantity: &BigDecimal,
+) -> Result<SimOpenBuyOrder, Error> {
+    if unique_order_id.is_empty() || unique_order_id.len() > 255 {
+        return Err(anyhow::anyhow!("Order ID must be between 1 and 255 characters"));
+    }
+
+    let start_time = Instant::now();
+    let mut conn = get_timescale_connection(pool).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    use crate::schema::sim_open_buy_orders::dsl::*;
+
+    let result = diesel::update(
+        sim_open_buy_orders
+            .filter(backtest_id.eq(backtest_id_param))
+            .filter(unique_id.eq(unique_order_id))
+    )
+    .set((price_level.eq(new_price_level), buy_quantity.eq(new_buy_quantity)))
+    .get_result::<SimOpenBuyOrder>(&mut conn)
+    .await
+    .map_err(|e| anyhow::Error::from(e))?;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.debug(format!("Modified simulation buy order {} in {:?}", unique_order_id, start_time.elapsed())).await;
+
+    Ok(result)
+}
+
+/// Change several simulation open buy orders; each batch of 50 is applied atomically
+pub async fn modify_sim_open_buy_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    backtest_id_param: Uuid,
+    updates: Vec<(&String, &BigDecimal, &BigDecimal)>,
+) -> Result<Vec<SimOpenBuyOrder>, Error> {
+    if updates.is_empty() {
+        return Ok(vec![]);
+    }
+    if updates.len() > 1000 {
+        return Err(anyhow::anyhow!("Batch size too large (max 1000)"));
+    }
+
+    let start_time = Instant::now();
+    let mut conn = get_timescale_connection(pool).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    use crate::schema::sim_open_buy_orders::dsl::*;
+
+    const BATCH_SIZE: usize = 50;
+    let mut all_results = Vec::with_capacity(updates.len());
+
+    for chunk in updates.chunks(BATCH_SIZE) {
+        let batch_results = conn.transaction::<_, diesel::result::Error, _>(|conn| Box::pin(async move {
+            let mut chunk_results = Vec::with_capacity(chunk.len());
+            for (id, new_price, new_quantity) in chunk {
+                let result = diesel::update(
+                    sim_open_buy_orders
+                        .filter(backtest_id.eq(backtest_id_param))
+                        .filter(unique_id.eq(*id))
+                )
+                .set((price_level.eq(*new_price), buy_quantity.eq(*new_quantity)))
+                .get_result::<SimOpenBuyOrder>(conn)
+                .await?;
+                chunk_results.push(result);
+            }
+            Ok(chunk_results)
+        })).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+        all_results.extend(batch_results);
+    }
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.info(format!("Modified {} simulation buy orders in {:?}", all_results.len(), start_time.elapsed())).await;
+
+    Ok(all_results)
+}
+
+/// Delete several simulation open buy orders of one backtest
+pub async fn delete_sim_open_buy_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    backtest_id_param: Uuid,
+    ids: &[String],
+) -> Result<usize, Error> {
+    let start_time = Instant::now();
+    let mut conn = get_timescale_connection(pool).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    use crate::schema::sim_open_buy_orders::dsl::*;
+
+    // Process in batches for large deletions
+    const BATCH_SIZE: usize = 100;
+    let mut total_deleted = 0;
+
+    for chunk in ids.chunks(BATCH_SIZE) {
+        total_deleted += diesel::delete(
+            sim_open_buy_orders
+                .filter(backtest_id.eq(backtest_id_param))
+                .filter(unique_id.eq_any(chunk))
+        )
+        .execute(&mut conn)
+        .await
+        .map_err(|e| anyhow::Error::from(e))?;
+    }
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.info(format!("Deleted {} simulation buy orders in {:?}", total_deleted, start_time.elapsed())).await;
+
+    Ok(total_deleted)
+}
+
+/// Delete every simulation open buy order of a backtest
+pub async fn clear_sim_open_buy_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    backtest_id_param: Uuid,
+) -> Result<usize, Error> {
+    let mut conn = get_timescale_connection(pool).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    use crate::schema::sim_open_buy_orders::dsl::*;
+
+    let result = diesel::delete(sim_open_buy_orders.filter(backtest_id.eq(backtest_id_param)))
+        .execute(&mut conn)
+        .await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.info(format!("Cleared {} simulation buy orders for backtest {}", result, backtest_id_param)).await;
+
+    Ok(result)
+}
+
+/// Simulation buy book of a backtest, best (highest) price first, each level in time priority
+pub async fn get_sim_open_buy_orders(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    backtest_id_param: Uuid,
+) -> Result<BTreeMap<Reverse<BigDecimal>, Vec<SimOpenBuyOrder>>, Error> {
+    let orders = get_sim_open_buy_orders_by_backtest(pool, backtest_id_param).await?;
+    Ok(build_buy_book(orders))
+}
+
+/// Simulation buy book of a backtest for one symbol
+pub async fn get_sim_open_buy_orders_by_symbol(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    backtest_id_param: Uuid,
+    sym: &str,
+) -> Result<BTreeMap<Reverse<BigDecimal>, Vec<SimOpenBuyOrder>>, Error> {
+    let start_time = Instant::now();
+    let mut conn = get_timescale_connection(pool).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    use crate::schema::sim_open_buy_orders::dsl::*;
+
+    let orders = sim_open_buy_orders
+        .filter(backtest_id.eq(backtest_id_param))
+        .filter(symbol.eq(sym))
+        .order(price_level.desc())
+        .load::<SimOpenBuyOrder>(&mut conn)
+        .await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    let logger = UltraLogger::new("databaseschema".to_string());
+    let _ = logger.info(format!("Retrieved {} simulation buy orders for {} in {:?}", orders.len(), sym, start_time.elapsed())).await;
+
+    Ok(build_buy_book(orders))
+}
+
+fn build_buy_book(orders: Vec<SimOpenBuyOrder>) -> BTreeMap<Reverse<BigDecimal>, Vec<SimOpenBuyOrder>> {
+    let mut buy_orderbook = BTreeMap::new();
+    for order in orders {
+        buy_orderbook.entry(Reverse(order.get_price_level().clone()))
+            .or_insert_with(Vec::new)
+            .push(order);
+    }
+
+    for orders_at_price in buy_orderbook.values_mut() {
+        orders_at_price.sort_by(|a: &SimOpenBuyOrder, b| a.get_timestamp().cmp(b.get_timestamp()));
+    }
+
+    buy_orderbook
+}
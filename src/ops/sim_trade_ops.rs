@@ -111,3 +111,27 @@ pub async fn get_sim_trades_by_symbol_and_backtest(
 
     Ok(result)
 }
+
+/// Count the taker-side simulation trades of a symbol in a backtest, i.e. the number of
+/// fills the matching engine has recorded for it
+pub async fn count_sim_taker_trades(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    symbol_param: &str,
+    backtest_id_param: Uuid,
+) -> Result<i64, Error> {
+    let mut conn = get_timescale_connection(pool).await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    use crate::schema::sim_trades::dsl::*;
+
+    let result = sim_trades
+        .filter(symbol.eq(symbol_param))
+        .filter(backtest_id.eq(backtest_id_param))
+        .filter(matched_trader.eq(false))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await
+        .map_err(|e| anyhow::Error::from(e))?;
+
+    Ok(result)
+}
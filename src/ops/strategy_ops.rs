@@ -11,7 +11,9 @@ use crate::models::strategy::{
     OptimizationIteration, NewOptimizationIteration, StrategyComparison, NewStrategyComparison,
     StrategyWithParameters, FullStrategyInstance, ParameterValidationResult
 };
+use crate::comparison::{self, BacktestComparison, ComparisonConfig, ComparisonInput};
 use crate::errors::DatabaseError;
+use crate::models::backtest_result::BacktestEquityCurve;
 use crate::schema::{
     strategies, strategy_parameters, strategy_instances, 
     optimization_runs, optimization_iterations, strategy_comparisons
@@ -654,6 +656,52 @@ impl StrategyOperations {
             })
     }
 
+    /// Compare stored backtests on their shared equity curve timestamps and store the typed
+    /// results (see `comparison::ComparisonResults`) through `create_strategy_comparison`
+    pub async fn compare_backtests(
+        conn: &mut AsyncPgConnection,
+        comparison_name: &str,
+        description: Option<&str>,
+        backtest_result_ids: &[Uuid],
+        config: &ComparisonConfig,
+        created_by: Option<&str>,
+    ) -> Result<(StrategyComparison, BacktestComparison), DatabaseError> {
+        use crate::schema::{backtest_equity_curve, backtest_results};
+
+        if backtest_result_ids.len() < 2 || backtest_result_ids.len() > 50 {
+            return Err(DatabaseError::InvalidInput("Between 2 and 50 backtest results can be compared".to_string()));
+        }
+
+        let mut inputs = Vec::with_capacity(backtest_result_ids.len());
+        for result_id in backtest_result_ids {
+            let (name, instance_id, benchmark) = backtest_results::table
+                .find(result_id)
+                .select((backtest_results::strategy_name, backtest_results::strategy_instance_id, backtest_results::benchmark))
+                .first::<(String, Option<Uuid>, Option<String>)>(conn)
+                .await
+                .map_err(|e| match e {
+                    DieselError::NotFound => DatabaseError::NotFound(format!("Backtest result with ID {} not found", result_id)),
+                    _ => DatabaseError::DatabaseError(e.to_string()),
+                })?;
+            let equity = backtest_equity_curve::table
+                .filter(backtest_equity_curve::backtest_result_id.eq(result_id))
+                .order(backtest_equity_curve::timestamp.asc())
+                .select(BacktestEquityCurve::as_select())
+                .load::<BacktestEquityCurve>(conn)
+                .await
+                .map_err(|e| DatabaseError::DatabaseError(e.to_string()))?;
+            inputs.push(ComparisonInput::new(*result_id, &name, instance_id, benchmark, &equity));
+        }
+
+        let computed = comparison::compare_backtests(&inputs, config)
+            .map_err(|e| DatabaseError::InvalidInput(e.to_string()))?;
+        let new_comparison = computed
+            .to_new_comparison(comparison_name, description, created_by)
+            .map_err(|e| DatabaseError::SerializationError(e.to_string()))?;
+        let stored = Self::create_strategy_comparison(conn, new_comparison).await?;
+        Ok((stored, computed))
+    }
+
     /// Get strategy comparison
     pub async fn get_strategy_comparison(
         conn: &mut AsyncPgConnection,
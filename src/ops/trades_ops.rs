@@ -1,62 +1,24 @@
-use crate::{get_timescale_connection, models::trade::{NewTrade, Trade}};
+use crate::{get_timescale_connection, models::trade::{NewTrade, Trade, TradeImbalanceBucket, TradeIngestReport, TradeOrdering, TradeQuery, TradeSummary}};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, Utc};
 use diesel_async::pooled_connection::deadpool;
 use diesel_async::AsyncPgConnection;
 use diesel::{prelude::*, result::Error};
+use diesel::sql_types::{Array, Bool, Interval, Nullable, Numeric, Text, Timestamptz};
 use diesel_async::{AsyncConnection, RunQueryDsl};
 use tokio_retry::{strategy::{jitter, ExponentialBackoff}, Retry};
+use std::collections::HashSet;
 use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
 
+/// Insert trades, skipping any whose `(exchange_id, trade_id)` was already stored.
+/// Shorthand for `ingest_trades` when the caller does not need the ingest report.
 pub async fn create_trades(pool: Arc<deadpool::Pool<AsyncPgConnection>>, new_trades: Vec<NewTrade>) -> Result<(), Error> {
-    use crate::schema::trades::dsl::*;
-
-    // Security: Validate batch size to prevent resource exhaustion
-    const MAX_BATCH_SIZE: usize = 50000;
-    if new_trades.len() > MAX_BATCH_SIZE {
-        return Err(Error::RollbackTransaction);
-    }
-
-    if new_trades.is_empty() {
-        return Ok(());
-    }
-
-    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
-
-    Retry::spawn(retry_strategy, || async {
-        let mut connection = get_timescale_connection(pool.clone())
-        .await
-        .map_err(|_e| {
-            Error::DatabaseError(
-                diesel::result::DatabaseErrorKind::UnableToSendCommand,
-                Box::new("Failed to get database connection".to_string())
-            )
-        })?;
-
-        // Process in smaller batches to reduce deadlock probability
-        const BATCH_SIZE: usize = 50;
-        
-        for chunk in new_trades.chunks(BATCH_SIZE) {
-            // Sort by trade_id to ensure consistent lock ordering across pods
-            let mut sorted_chunk = chunk.to_vec();
-            sorted_chunk.sort_by(|a, b| a.trade_id.cmp(&b.trade_id));
-
-            connection.transaction::<_, Error, _>(|conn| Box::pin(async {
-                // Use DO NOTHING to avoid deadlocks on concurrent inserts
-                diesel::insert_into(trades)
-                    .values(&sorted_chunk)
-                    .on_conflict((created_at, trade_id))
-                    .do_nothing()
-                    .execute(conn)
-                    .await
-                    .map_err(|e| {
-                        e
-                    })
-            })).await?;
-        }
-        
-        Ok(())
-    }).await
+    ingest_trades(pool, new_trades).await.map(|_| ())
 }
 
+/// Unbounded read of every trade for a symbol/exchange. Prefer `query_trades` with a time range.
 pub async fn get_trades_by_symbol(pool: Arc<deadpool::Pool<AsyncPgConnection>>, sym: &str, xchange: &str) -> Result<Vec<Trade>, Error> {
     use crate::schema::trades::dsl::*;
 
@@ -95,3 +57,431 @@ pub async fn get_trades_by_symbol(pool: Arc<deadpool::Pool<AsyncPgConnection>>,
         Ok(result)
     }).await
 }
+
+// Shared WHERE clause for the aggregate queries, binds $1..$7 in `TradeQuery` field order.
+// Time bounds fall back to +/-infinity rather than `IS NULL OR` so chunk exclusion still applies.
+const TRADE_QUERY_FILTER: &str = "
+    symbol = $1
+    AND exchange = $2
+    AND created_at >= COALESCE($3, '-infinity'::timestamptz)
+    AND created_at < COALESCE($4, 'infinity'::timestamptz)
+    AND ($5::text IS NULL OR side = $5)
+    AND ($6::numeric IS NULL OR quantity >= $6)
+    AND ($7::numeric IS NULL OR quantity <= $7)
+";
+
+fn validate_trade_query(query: &TradeQuery) -> Result<(), Error> {
+    // Security: Input validation
+    if query.symbol.is_empty() || query.symbol.len() > 20 {
+        return Err(Error::RollbackTransaction);
+    }
+
+    if query.exchange.is_empty() || query.exchange.len() > 50 {
+        return Err(Error::RollbackTransaction);
+    }
+
+    if let (Some(start), Some(end)) = (query.start_time, query.end_time) {
+        if start >= end {
+            return Err(Error::RollbackTransaction);
+        }
+    }
+
+    if let (Some(min), Some(max)) = (&query.min_quantity, &query.max_quantity) {
+        if min > max {
+            return Err(Error::RollbackTransaction);
+        }
+    }
+
+    if query.limit.is_some_and(|l| l <= 0) {
+        return Err(Error::RollbackTransaction);
+    }
+
+    Ok(())
+}
+
+/// Load raw trades matching `query`. Results are capped at 100,000 rows regardless of `limit`.
+pub async fn query_trades(pool: Arc<deadpool::Pool<AsyncPgConnection>>, query: &TradeQuery) -> Result<Vec<Trade>, Error> {
+    use crate::schema::trades::dsl::*;
+
+    validate_trade_query(query)?;
+
+    const MAX_LIMIT: i64 = 100000;
+    let safe_limit = query.limit.map_or(MAX_LIMIT, |l| l.min(MAX_LIMIT));
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|_e| {
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new("Failed to get database connection".to_string())
+                )
+            })?;
+
+        let mut sql = trades
+            .filter(symbol.eq(&query.symbol).and(exchange.eq(&query.exchange)))
+            .into_boxed();
+
+        if let Some(start) = query.start_time {
+            sql = sql.filter(created_at.ge(start));
+        }
+        if let Some(end) = query.end_time {
+            sql = sql.filter(created_at.lt(end));
+        }
+        if let Some(trade_side) = &query.side {
+            sql = sql.filter(side.eq(trade_side));
+        }
+        if let Some(min) = &query.min_quantity {
+            sql = sql.filter(quantity.ge(min));
+        }
+        if let Some(max) = &query.max_quantity {
+            sql = sql.filter(quantity.le(max));
+        }
+
+        sql = match query.ordering {
+            TradeOrdering::OldestFirst => sql.order((created_at.asc(), trade_id.asc())),
+            TradeOrdering::NewestFirst => sql.order((created_at.desc(), trade_id.desc())),
+        };
+
+        sql
+            .limit(safe_limit)
+            .select(Trade::as_select())
+            .load::<Trade>(&mut connection)
+            .await
+    }).await
+}
+
+/// Trade count, VWAP and volume by side for the trades matching `query`, computed in the database.
+/// `limit` and `ordering` are ignored.
+pub async fn get_trade_summary(pool: Arc<deadpool::Pool<AsyncPgConnection>>, query: &TradeQuery) -> Result<TradeSummary, Error> {
+    validate_trade_query(query)?;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|_e| {
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new("Failed to get database connection".to_string())
+                )
+            })?;
+
+        diesel::sql_query(format!(
+            "SELECT
+                COUNT(*) AS trade_count,
+                COALESCE(SUM(quantity), 0) AS total_volume,
+                COALESCE(SUM(quantity) FILTER (WHERE LOWER(side) = 'buy'), 0) AS buy_volume,
+                COALESCE(SUM(quantity) FILTER (WHERE LOWER(side) = 'sell'), 0) AS sell_volume,
+                SUM(price * quantity) / NULLIF(SUM(quantity), 0) AS vwap,
+                MIN(created_at) AS first_trade_at,
+                MAX(created_at) AS last_trade_at
+            FROM trades
+            WHERE {}",
+            TRADE_QUERY_FILTER
+        ))
+            .bind::<Text, _>(&query.symbol)
+            .bind::<Text, _>(&query.exchange)
+            .bind::<Nullable<Timestamptz>, _>(query.start_time)
+            .bind::<Nullable<Timestamptz>, _>(query.end_time)
+            .bind::<Nullable<Text>, _>(query.side.as_deref())
+            .bind::<Nullable<Numeric>, _>(query.min_quantity.as_ref())
+            .bind::<Nullable<Numeric>, _>(query.max_quantity.as_ref())
+            .get_result::<TradeSummary>(&mut connection)
+            .await
+    }).await
+}
+
+/// Volume-weighted average price of the trades matching `query`, `None` when nothing traded
+pub async fn get_vwap(pool: Arc<deadpool::Pool<AsyncPgConnection>>, query: &TradeQuery) -> Result<Option<BigDecimal>, Error> {
+    Ok(get_trade_summary(pool, query).await?.vwap)
+}
+
+/// Buy/sell imbalance per `bucket_width` time bucket, oldest first. Empty buckets are omitted.
+pub async fn get_trade_imbalance(
+    pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+    query: &TradeQuery,
+    bucket_width: Duration,
+) -> Result<Vec<TradeImbalanceBucket>, Error> {
+    validate_trade_query(query)?;
+
+    if bucket_width <= Duration::zero() {
+        return Err(Error::RollbackTransaction);
+    }
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|_e| {
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new("Failed to get database connection".to_string())
+                )
+            })?;
+
+        diesel::sql_query(format!(
+            "SELECT
+                bucket,
+                trade_count,
+                buy_volume,
+                sell_volume,
+                vwap,
+                (buy_volume - sell_volume) / NULLIF(buy_volume + sell_volume, 0) AS imbalance
+            FROM (
+                SELECT
+                    time_bucket($8, created_at) AS bucket,
+                    COUNT(*) AS trade_count,
+                    COALESCE(SUM(quantity) FILTER (WHERE LOWER(side) = 'buy'), 0) AS buy_volume,
+                    COALESCE(SUM(quantity) FILTER (WHERE LOWER(side) = 'sell'), 0) AS sell_volume,
+                    SUM(price * quantity) / NULLIF(SUM(quantity), 0) AS vwap
+                FROM trades
+                WHERE {}
+                GROUP BY bucket
+            ) buckets
+            ORDER BY bucket ASC
+            LIMIT 10000",
+            TRADE_QUERY_FILTER
+        ))
+            .bind::<Text, _>(&query.symbol)
+            .bind::<Text, _>(&query.exchange)
+            .bind::<Nullable<Timestamptz>, _>(query.start_time)
+            .bind::<Nullable<Timestamptz>, _>(query.end_time)
+            .bind::<Nullable<Text>, _>(query.side.as_deref())
+            .bind::<Nullable<Numeric>, _>(query.min_quantity.as_ref())
+            .bind::<Nullable<Numeric>, _>(query.max_quantity.as_ref())
+            .bind::<Interval, _>(bucket_width)
+            .load::<TradeImbalanceBucket>(&mut connection)
+            .await
+    }).await
+}
+
+#[derive(QueryableByName)]
+struct ClaimedTradeKey {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    exchange_id: Uuid,
+    #[diesel(sql_type = Text)]
+    trade_id: String,
+    #[diesel(sql_type = Bool)]
+    fresh: bool,
+}
+
+#[derive(QueryableByName)]
+struct CompressedChunk {
+    #[diesel(sql_type = Text)]
+    chunk: String,
+    #[diesel(sql_type = Timestamptz)]
+    range_start: DateTime<Utc>,
+    #[diesel(sql_type = Timestamptz)]
+    range_end: DateTime<Utc>,
+}
+
+/// Largest batch `ingest_trades` accepts; bigger batches fail with `RollbackTransaction`
+pub const MAX_INGEST_BATCH_SIZE: usize = 50000;
+
+/// Ingest trades with exchange-level deduplication and late-arrival handling.
+///
+/// Each trade first claims its `(exchange_id, trade_id)` in `trade_keys`; trades that were
+/// already claimed are counted as duplicates (and their key's `duplicate_count` bumped)
+/// instead of being inserted. Trades whose event time falls in an already compressed chunk
+/// have that chunk decompressed and written to inside the claim transaction; the chunks are
+/// recompressed after it commits, and one that fails to recompress is left for the
+/// compression policy. Trades without an event time are stamped with the current time.
+pub async fn ingest_trades(pool: Arc<deadpool::Pool<AsyncPgConnection>>, new_trades: Vec<NewTrade>) -> Result<TradeIngestReport, Error> {
+    // Security: Validate batch size to prevent resource exhaustion
+    if new_trades.len() > MAX_INGEST_BATCH_SIZE {
+        return Err(Error::RollbackTransaction);
+    }
+
+    if new_trades.is_empty() {
+        return Ok(TradeIngestReport::default());
+    }
+
+    let received = new_trades.len();
+    let ingest_time = Utc::now();
+
+    // Repeats inside the batch never reach the database
+    let mut seen = HashSet::with_capacity(received);
+    let mut batch: Vec<NewTrade> = new_trades
+        .into_iter()
+        .filter(|t| seen.insert((t.exchange_id, t.trade_id.clone())))
+        .map(|mut t| {
+            t.event_time = Some(t.event_time.unwrap_or(ingest_time));
+            t
+        })
+        .collect();
+    let batch_duplicates = received - batch.len();
+    // Sort by key so the trade_keys claim locks rows in the same order across pods
+    batch.sort_by(|a, b| a.exchange_id.cmp(&b.exchange_id).then_with(|| a.trade_id.cmp(&b.trade_id)));
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    let (report, late_chunks) = Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|_e| {
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new("Failed to get database connection".to_string())
+                )
+            })?;
+
+        connection.transaction::<_, Error, _>(|conn| Box::pin(async {
+            use crate::schema::trades::dsl::*;
+
+            let key_exchange_ids: Vec<Uuid> = batch.iter().map(|t| t.exchange_id).collect();
+            let key_trade_ids: Vec<String> = batch.iter().map(|t| t.trade_id.clone()).collect();
+            let key_event_times: Vec<DateTime<Utc>> = batch.iter().map(|t| t.event_time.unwrap_or(ingest_time)).collect();
+
+            // xmax = 0 only for rows this statement inserted, i.e. keys not seen before
+            let claimed = diesel::sql_query(
+                "INSERT INTO trade_keys (exchange_id, trade_id, event_time)
+                 SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::timestamptz[])
+                 ON CONFLICT (exchange_id, trade_id) DO UPDATE
+                 SET duplicate_count = trade_keys.duplicate_count + 1,
+                     last_seen_at = NOW()
+                 RETURNING exchange_id, trade_id::text AS trade_id, (xmax = 0) AS fresh"
+            )
+                .bind::<Array<diesel::sql_types::Uuid>, _>(&key_exchange_ids)
+                .bind::<Array<Text>, _>(&key_trade_ids)
+                .bind::<Array<Timestamptz>, _>(&key_event_times)
+                .load::<ClaimedTradeKey>(conn)
+                .await?;
+
+            let fresh_keys: HashSet<(Uuid, String)> = claimed
+                .into_iter()
+                .filter(|k| k.fresh)
+                .map(|k| (k.exchange_id, k.trade_id))
+                .collect();
+
+            let fresh: Vec<NewTrade> = batch
+                .iter()
+                .filter(|t| fresh_keys.contains(&(t.exchange_id, t.trade_id.clone())))
+                .cloned()
+                .collect();
+
+            let duplicates = batch_duplicates + (batch.len() - fresh.len());
+            if fresh.is_empty() {
+                return Ok((TradeIngestReport { received, inserted: 0, duplicates, late: 0, decompressed_chunks: 0 }, Vec::new()));
+            }
+
+            let earliest = fresh.iter().filter_map(|t| t.event_time).min().unwrap_or(ingest_time);
+            let latest = fresh.iter().filter_map(|t| t.event_time).max().unwrap_or(ingest_time);
+
+            let compressed = diesel::sql_query(
+                "SELECT format('%I.%I', chunk_schema, chunk_name) AS chunk, range_start, range_end
+                 FROM timescaledb_information.chunks
+                 WHERE hypertable_name = 'trades'
+                   AND is_compressed
+                   AND range_end > $1
+                   AND range_start <= $2
+                 ORDER BY range_start"
+            )
+                .bind::<Timestamptz, _>(earliest)
+                .bind::<Timestamptz, _>(latest)
+                .load::<CompressedChunk>(conn)
+                .await?;
+
+            let in_chunk = |t: &NewTrade, c: &CompressedChunk| {
+                t.event_time.is_some_and(|ts| c.range_start <= ts && ts < c.range_end)
+            };
+            let late_chunks: Vec<&CompressedChunk> = compressed
+                .iter()
+                .filter(|c| fresh.iter().any(|t| in_chunk(t, c)))
+                .collect();
+            let late = fresh
+                .iter()
+                .filter(|t| late_chunks.iter().any(|c| in_chunk(t, c)))
+                .count();
+
+            for c in &late_chunks {
+                diesel::sql_query("SELECT decompress_chunk($1::regclass, if_compressed => true)")
+                    .bind::<Text, _>(&c.chunk)
+                    .execute(conn)
+                    .await?;
+            }
+
+            let mut inserted = 0;
+            for chunk in fresh.chunks(1000) {
+                inserted += diesel::insert_into(trades)
+                    .values(chunk)
+                    .on_conflict((created_at, trade_id))
+                    .do_nothing()
+                    .execute(conn)
+                    .await?;
+            }
+
+            Ok((
+                TradeIngestReport {
+                    received,
+                    inserted,
+                    duplicates,
+                    late,
+                    decompressed_chunks: late_chunks.len(),
+                },
+                late_chunks.iter().map(|c| c.chunk.clone()).collect::<Vec<String>>(),
+            ))
+        })).await
+    }).await?;
+
+    if !late_chunks.is_empty() {
+        recompress_trade_chunks(pool, &late_chunks).await;
+    }
+
+    if report.duplicates > 0 {
+        warn!("Skipped {} duplicate trades out of {}", report.duplicates, report.received);
+    }
+    if report.late > 0 {
+        info!("Routed {} late trades through {} decompressed chunks", report.late, report.decompressed_chunks);
+    }
+
+    Ok(report)
+}
+
+/// Recompress chunks `ingest_trades` decompressed. The trades are already committed, so a
+/// failure is only logged and the chunk is left for the compression policy.
+async fn recompress_trade_chunks(pool: Arc<deadpool::Pool<AsyncPgConnection>>, chunks: &[String]) {
+    let mut connection = match get_timescale_connection(pool).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            warn!("Leaving {} trade chunks decompressed, no database connection: {}", chunks.len(), e);
+            return;
+        }
+    };
+
+    for chunk in chunks {
+        if let Err(e) = diesel::sql_query("SELECT compress_chunk($1::regclass, if_not_compressed => true)")
+            .bind::<Text, _>(chunk)
+            .execute(&mut connection)
+            .await
+        {
+            warn!("Failed to recompress trade chunk {}: {}", chunk, e);
+        }
+    }
+}
+
+/// Drop dedup keys for trades older than `cutoff`, typically the trades retention horizon
+pub async fn prune_trade_keys(pool: Arc<deadpool::Pool<AsyncPgConnection>>, cutoff: DateTime<Utc>) -> Result<usize, Error> {
+    use crate::schema::trade_keys::dsl::*;
+
+    let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
+
+    Retry::spawn(retry_strategy, || async {
+        let mut connection = get_timescale_connection(pool.clone())
+            .await
+            .map_err(|_e| {
+                Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    Box::new("Failed to get database connection".to_string())
+                )
+            })?;
+
+        diesel::delete(trade_keys.filter(event_time.lt(cutoff)))
+            .execute(&mut connection)
+            .await
+    }).await
+}
@@ -0,0 +1,305 @@
+use crate::models::historical_order::{HistoricalOrder, NewHistoricalOrder};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Read access to the fields the lifecycle rules look at, shared by stored and pending events
+pub trait LifecycleEvent {
+    fn symbol(&self) -> &str;
+    fn exchange(&self) -> &str;
+    fn order_id(&self) -> &str;
+    fn timestamp(&self) -> DateTime<Utc>;
+    fn event_type(&self) -> &str;
+    fn status(&self) -> &str;
+    fn price_level(&self) -> &BigDecimal;
+    fn quantity(&self) -> &BigDecimal;
+    fn prev_price(&self) -> Option<&BigDecimal>;
+    fn prev_quantity(&self) -> Option<&BigDecimal>;
+}
+
+/// `(symbol, exchange, order_id)`: order ids are only unique per market
+pub type OrderKey = (String, String, String);
+
+fn order_key<E: LifecycleEvent>(event: &E) -> OrderKey {
+    (event.symbol().to_string(), event.exchange().to_string(), event.order_id().to_string())
+}
+
+impl LifecycleEvent for HistoricalOrder {
+    fn symbol(&self) -> &str { &self.symbol }
+    fn exchange(&self) -> &str { &self.exchange }
+    fn order_id(&self) -> &str { &self.order_id }
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn event_type(&self) -> &str { &self.event_type }
+    fn status(&self) -> &str { &self.status }
+    fn price_level(&self) -> &BigDecimal { &self.price_level }
+    fn quantity(&self) -> &BigDecimal { &self.quantity }
+    fn prev_price(&self) -> Option<&BigDecimal> { self.prev_price.as_ref() }
+    fn prev_quantity(&self) -> Option<&BigDecimal> { self.prev_quantity.as_ref() }
+}
+
+impl LifecycleEvent for NewHistoricalOrder {
+    fn symbol(&self) -> &str { &self.symbol }
+    fn exchange(&self) -> &str { &self.exchange }
+    fn order_id(&self) -> &str { &self.order_id }
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn event_type(&self) -> &str { &self.event_type }
+    fn status(&self) -> &str { &self.status }
+    fn price_level(&self) -> &BigDecimal { &self.price_level }
+    fn quantity(&self) -> &BigDecimal { &self.quantity }
+    fn prev_price(&self) -> Option<&BigDecimal> { self.prev_price.as_ref() }
+    fn prev_quantity(&self) -> Option<&BigDecimal> { self.prev_quantity.as_ref() }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// A modify, cancel or trade for an order with no preceding `new`
+    MissingNew,
+    /// A second `new` for an order that is still live
+    DuplicateNew,
+    /// Any event after the order was filled or canceled
+    EventAfterTerminal { terminal_status: String },
+    /// A modify without `prev_price`/`prev_quantity`
+    MissingPrevState,
+    PrevPriceMismatch { expected: BigDecimal, actual: BigDecimal },
+    PrevQuantityMismatch { expected: BigDecimal, actual: BigDecimal },
+    /// The event's own quantity is negative
+    NegativeQuantity { quantity: BigDecimal },
+    /// A trade executed more than the order had remaining
+    Overfill { remaining: BigDecimal, executed: BigDecimal },
+    UnknownEventType,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleViolation {
+    pub symbol: String,
+    pub exchange: String,
+    pub order_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub event_type: String,
+    /// Position of the offending event in the validated input
+    pub index: usize,
+    pub violation: ViolationKind,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LifecycleReport {
+    pub events_checked: usize,
+    pub orders_checked: usize,
+    pub violations: Vec<LifecycleViolation>,
+}
+
+impl LifecycleReport {
+    pub fn is_clean(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Indices of input events with at least one violation
+    pub fn invalid_indices(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = self.violations.iter().map(|v| v.index).collect();
+        indices.dedup();
+        indices
+    }
+
+    /// Violation counts keyed by kind, e.g. `{"missing_new": 3}`
+    pub fn counts_by_kind(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for v in &self.violations {
+            let kind = match &v.violation {
+                ViolationKind::MissingNew => "missing_new",
+                ViolationKind::DuplicateNew => "duplicate_new",
+                ViolationKind::EventAfterTerminal { .. } => "event_after_terminal",
+                ViolationKind::MissingPrevState => "missing_prev_state",
+                ViolationKind::PrevPriceMismatch { .. } => "prev_price_mismatch",
+                ViolationKind::PrevQuantityMismatch { .. } => "prev_quantity_mismatch",
+                ViolationKind::NegativeQuantity { .. } => "negative_quantity",
+                ViolationKind::Overfill { .. } => "overfill",
+                ViolationKind::UnknownEventType => "unknown_event_type",
+            };
+            *counts.entry(kind.to_string()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// Last known state of an order while replaying its events
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderState {
+    pub price: BigDecimal,
+    pub remaining: BigDecimal,
+    pub status: String,
+}
+
+impl OrderState {
+    fn is_terminal(&self) -> bool {
+        self.status == "filled" || self.status == "canceled"
+    }
+}
+
+/// Replays order events and checks lifecycle rules per order, keyed by
+/// `(symbol, exchange, order_id)`:
+/// - `modify`, `cancel` and `trade` must follow a `new`
+/// - nothing may follow an event that left the order `filled` or `canceled`
+/// - `prev_price`/`prev_quantity` (required on `modify`) must match the prior state
+/// - quantities never go negative; a `trade` carries the executed size and may not
+///   exceed what remains
+///
+/// Events that violate a rule are not applied, so later events are checked as if the
+/// offending event had been dropped. Validators can be seeded with state loaded from
+/// the database so an ingest batch is checked against what is already stored.
+#[derive(Debug, Clone, Default)]
+pub struct OrderLifecycleValidator {
+    orders: HashMap<OrderKey, OrderState>,
+}
+
+impl OrderLifecycleValidator {
+    pub fn new() -> OrderLifecycleValidator {
+        OrderLifecycleValidator::default()
+    }
+
+    /// Replay already-stored events to build the starting state, ignoring their violations
+    pub fn seed<E: LifecycleEvent>(&mut self, events: &[E]) {
+        let mut ordered: Vec<&E> = events.iter().collect();
+        ordered.sort_by_key(|e| e.timestamp());
+        for event in ordered {
+            self.apply(event);
+        }
+    }
+
+    pub fn state(&self, symbol: &str, exchange: &str, order_id: &str) -> Option<&OrderState> {
+        self.orders.get(&(symbol.to_string(), exchange.to_string(), order_id.to_string()))
+    }
+
+    /// Validate `events` in timestamp order (ties keep input order) and return the report.
+    /// Violation indices refer to positions in `events`.
+    pub fn validate<E: LifecycleEvent>(&mut self, events: &[E]) -> LifecycleReport {
+        let mut order: Vec<usize> = (0..events.len()).collect();
+        order.sort_by_key(|&i| events[i].timestamp());
+
+        let mut report = LifecycleReport {
+            events_checked: events.len(),
+            ..LifecycleReport::default()
+        };
+        let mut seen_orders = HashSet::new();
+
+        for i in order {
+            let event = &events[i];
+            seen_orders.insert((event.symbol(), event.exchange(), event.order_id()));
+            for violation in self.check(event) {
+                report.violations.push(LifecycleViolation {
+                    symbol: event.symbol().to_string(),
+                    exchange: event.exchange().to_string(),
+                    order_id: event.order_id().to_string(),
+                    timestamp: event.timestamp(),
+                    event_type: event.event_type().to_string(),
+                    index: i,
+                    violation,
+                });
+            }
+        }
+
+        report.orders_checked = seen_orders.len();
+        report.violations.sort_by_key(|v| v.index);
+        report
+    }
+
+    /// Check one event against the current state, applying it only if it is valid
+    pub fn check<E: LifecycleEvent>(&mut self, event: &E) -> Vec<ViolationKind> {
+        let violations = self.violations_for(event);
+        if violations.is_empty() {
+            self.apply(event);
+        }
+        violations
+    }
+
+    fn violations_for<E: LifecycleEvent>(&self, event: &E) -> Vec<ViolationKind> {
+        let mut violations = Vec::new();
+        let zero = BigDecimal::zero();
+
+        if event.quantity() < &zero {
+            violations.push(ViolationKind::NegativeQuantity { quantity: event.quantity().clone() });
+        }
+
+        let prior = self.orders.get(&order_key(event));
+        if let Some(state) = prior.filter(|s| s.is_terminal()) {
+            violations.push(ViolationKind::EventAfterTerminal { terminal_status: state.status.clone() });
+            return violations;
+        }
+
+        match event.event_type() {
+            "new" => {
+                if prior.is_some() {
+                    violations.push(ViolationKind::DuplicateNew);
+                }
+            },
+            "modify" | "cancel" | "trade" => {
+                let Some(state) = prior else {
+                    violations.push(ViolationKind::MissingNew);
+                    return violations;
+                };
+
+                if event.event_type() == "modify" && (event.prev_price().is_none() || event.prev_quantity().is_none()) {
+                    violations.push(ViolationKind::MissingPrevState);
+                }
+                if let Some(prev_price) = event.prev_price().filter(|p| *p != &state.price) {
+                    violations.push(ViolationKind::PrevPriceMismatch {
+                        expected: state.price.clone(),
+                        actual: prev_price.clone(),
+                    });
+                }
+                if let Some(prev_quantity) = event.prev_quantity().filter(|q| *q != &state.remaining) {
+                    violations.push(ViolationKind::PrevQuantityMismatch {
+                        expected: state.remaining.clone(),
+                        actual: prev_quantity.clone(),
+                    });
+                }
+                if event.event_type() == "trade" && event.quantity() > &state.remaining {
+                    violations.push(ViolationKind::Overfill {
+                        remaining: state.remaining.clone(),
+                        executed: event.quantity().clone(),
+                    });
+                }
+            },
+            _ => violations.push(ViolationKind::UnknownEventType),
+        }
+
+        violations
+    }
+
+    fn apply<E: LifecycleEvent>(&mut self, event: &E) {
+        let key = order_key(event);
+        match event.event_type() {
+            "new" | "modify" => {
+                self.orders.insert(key, OrderState {
+                    price: event.price_level().clone(),
+                    remaining: event.quantity().clone(),
+                    status: event.status().to_string(),
+                });
+            },
+            "trade" => {
+                if let Some(state) = self.orders.get_mut(&key) {
+                    state.remaining = &state.remaining - event.quantity();
+                    state.status = event.status().to_string();
+                }
+            },
+            "cancel" => {
+                if let Some(state) = self.orders.get_mut(&key) {
+                    state.status = "canceled".to_string();
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// What `create_validated_historical_orders` does with a batch that has violations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValidationMode {
+    /// Insert nothing if any event violates a rule
+    RejectBatch,
+    /// Insert only the events without violations
+    SkipInvalid,
+    /// Insert everything and only report
+    AuditOnly,
+}
@@ -0,0 +1,415 @@
+use crate::{
+    get_timescale_connection,
+    models::{historical_order::HistoricalOrder, historical_snapshot::HistoricalSnapshot, trade::Trade}
+};
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::pooled_connection::deadpool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{info, debug};
+
+/// One market event in a replay stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    Snapshot(HistoricalSnapshot),
+    Order(HistoricalOrder),
+    Trade(Trade),
+}
+
+impl ReplayEvent {
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            ReplayEvent::Snapshot(s) => s.timestamp,
+            ReplayEvent::Order(o) => o.timestamp,
+            ReplayEvent::Trade(t) => t.created_at,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            ReplayEvent::Snapshot(s) => &s.symbol,
+            ReplayEvent::Order(o) => &o.symbol,
+            ReplayEvent::Trade(t) => &t.symbol,
+        }
+    }
+
+    pub fn exchange(&self) -> &str {
+        match self {
+            ReplayEvent::Snapshot(s) => &s.exchange,
+            ReplayEvent::Order(o) => &o.exchange,
+            ReplayEvent::Trade(t) => &t.exchange,
+        }
+    }
+
+    /// Snapshots establish book state before the order flow and trades at the same instant
+    fn source_rank(&self) -> u8 {
+        match self {
+            ReplayEvent::Snapshot(_) => 0,
+            ReplayEvent::Order(_) => 1,
+            ReplayEvent::Trade(_) => 2,
+        }
+    }
+
+    fn event_rank(&self) -> u8 {
+        let event_type = match self {
+            ReplayEvent::Snapshot(s) => s.event_type.as_str(),
+            ReplayEvent::Order(o) => o.event_type.as_str(),
+            ReplayEvent::Trade(_) => return 0,
+        };
+        match event_type {
+            "new" => 0,
+            "modify" => 1,
+            "trade" => 2,
+            "cancel" => 3,
+            _ => 4,
+        }
+    }
+
+    fn identity(&self) -> &str {
+        match self {
+            ReplayEvent::Snapshot(s) => &s.order_id,
+            ReplayEvent::Order(o) => &o.order_id,
+            ReplayEvent::Trade(t) => &t.trade_id,
+        }
+    }
+
+    /// Total order used for ties on timestamp. Built only from event content so the
+    /// same data replays identically regardless of the order the database returned it in.
+    fn canonical_cmp(&self, other: &ReplayEvent) -> Ordering {
+        self.timestamp().cmp(&other.timestamp())
+            .then_with(|| self.source_rank().cmp(&other.source_rank()))
+            .then_with(|| self.symbol().cmp(other.symbol()))
+            .then_with(|| self.exchange().cmp(other.exchange()))
+            .then_with(|| self.identity().cmp(other.identity()))
+            .then_with(|| self.event_rank().cmp(&other.event_rank()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Pacing {
+    /// Events are released at the pace they originally happened
+    WallClock,
+    /// Like `WallClock`, sped up by the given factor (e.g. 10.0 plays an hour in six minutes)
+    Accelerated(f64),
+    /// No waiting between events
+    AsFastAsPossible,
+}
+
+impl Pacing {
+    fn speed(&self) -> Option<f64> {
+        match self {
+            Pacing::WallClock => Some(1.0),
+            Pacing::Accelerated(factor) if *factor > 0.0 => Some(*factor),
+            Pacing::Accelerated(_) | Pacing::AsFastAsPossible => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TieBreak {
+    /// Same-timestamp events ordered by source, symbol, exchange and id
+    Canonical,
+    /// Same-timestamp events in a seeded random order, reproducible for a given seed
+    Seeded(u64),
+}
+
+/// What to load for a replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaySpec {
+    /// (symbol, exchange) pairs to merge into the stream
+    pub instruments: Vec<(String, String)>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub include_orders: bool,
+    pub include_snapshots: bool,
+    pub include_trades: bool,
+}
+
+impl ReplaySpec {
+    pub fn new(instruments: Vec<(String, String)>, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> ReplaySpec {
+        ReplaySpec {
+            instruments,
+            start_time,
+            end_time,
+            include_orders: true,
+            include_snapshots: true,
+            include_trades: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ReplayCommand {
+    Pause,
+    Resume,
+    Seek(DateTime<Utc>),
+    SetPacing(Pacing),
+}
+
+/// Cloneable handle to pause, resume, seek or re-pace a `Replayer` from another task
+#[derive(Debug, Clone)]
+pub struct ReplayController {
+    commands: mpsc::UnboundedSender<ReplayCommand>,
+}
+
+impl ReplayController {
+    pub fn pause(&self) {
+        let _ = self.commands.send(ReplayCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.commands.send(ReplayCommand::Resume);
+    }
+
+    pub fn seek(&self, to: DateTime<Utc>) {
+        let _ = self.commands.send(ReplayCommand::Seek(to));
+    }
+
+    pub fn set_pacing(&self, pacing: Pacing) {
+        let _ = self.commands.send(ReplayCommand::SetPacing(pacing));
+    }
+}
+
+/// Replays a fixed, totally ordered event stream against a clock.
+///
+/// The stream is sorted once up front (see `TieBreak`), so two replays of the same data
+/// with the same tie-break always yield the same sequence; pacing only affects when
+/// events are released, never their order.
+pub struct Replayer {
+    events: Vec<ReplayEvent>,
+    chronological: bool,
+    cursor: usize,
+    pacing: Pacing,
+    paused: bool,
+    // Wall-clock instant and event time that pacing is measured from
+    anchor: Option<(Instant, DateTime<Utc>)>,
+    commands: mpsc::UnboundedReceiver<ReplayCommand>,
+    controller: ReplayController,
+}
+
+impl Replayer {
+    /// Build a replayer from events already in memory, sorted into replay order
+    pub fn from_events(mut events: Vec<ReplayEvent>, pacing: Pacing, tie_break: TieBreak) -> Replayer {
+        events.sort_by(|a, b| a.canonical_cmp(b));
+
+        if let TieBreak::Seeded(seed) = tie_break {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let mut start = 0;
+            while start < events.len() {
+                let ts = events[start].timestamp();
+                let end = events[start..].iter().position(|e| e.timestamp() != ts).map_or(events.len(), |n| start + n);
+                events[start..end].shuffle(&mut rng);
+                start = end;
+            }
+        }
+
+        Replayer::with_events(events, pacing, true)
+    }
+
+    /// Replay events in exactly the given order, e.g. the output of
+    /// `get_randomized_historical_orders` wrapped in `ReplayEvent::Order`. Pacing follows
+    /// the timestamps but never waits for an event that is earlier than the one before it.
+    pub fn from_sequence(events: Vec<ReplayEvent>, pacing: Pacing) -> Replayer {
+        Replayer::with_events(events, pacing, false)
+    }
+
+    fn with_events(events: Vec<ReplayEvent>, pacing: Pacing, chronological: bool) -> Replayer {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Replayer {
+            events,
+            chronological,
+            cursor: 0,
+            pacing,
+            paused: false,
+            anchor: None,
+            commands: receiver,
+            controller: ReplayController { commands: sender },
+        }
+    }
+
+    /// Load and merge orders, snapshots and trades for every instrument in `spec`
+    pub async fn load(
+        pool: Arc<deadpool::Pool<AsyncPgConnection>>,
+        spec: &ReplaySpec,
+        pacing: Pacing,
+        tie_break: TieBreak,
+    ) -> Result<Replayer, Error> {
+        let load_start = std::time::Instant::now();
+
+        if spec.start_time >= spec.end_time {
+            return Err(anyhow::anyhow!("start_time must be before end_time"));
+        }
+        for (sym, xchange) in &spec.instruments {
+            if sym.is_empty() || sym.len() > 20 {
+                return Err(anyhow::anyhow!("Invalid symbol length: {}", sym.len()));
+            }
+            if xchange.is_empty() || xchange.len() > 50 {
+                return Err(anyhow::anyhow!("Invalid exchange length: {}", xchange.len()));
+            }
+        }
+
+        let mut connection = get_timescale_connection(pool.clone()).await?;
+        let mut events = Vec::new();
+
+        for (sym, xchange) in &spec.instruments {
+            if spec.include_orders {
+                use crate::schema::historical_orders::dsl::*;
+                let rows = historical_orders
+                    .filter(symbol.eq(sym).and(exchange.eq(xchange)))
+                    .filter(timestamp.ge(spec.start_time).and(timestamp.lt(spec.end_time)))
+                    .order(timestamp.asc())
+                    .limit(1000000) // Prevent memory exhaustion
+                    .select(HistoricalOrder::as_select())
+                    .load::<HistoricalOrder>(&mut connection)
+                    .await?;
+                events.extend(rows.into_iter().map(ReplayEvent::Order));
+            }
+
+            if spec.include_snapshots {
+                use crate::schema::historical_snapshot::dsl::*;
+                let rows = historical_snapshot
+                    .filter(symbol.eq(sym).and(exchange.eq(xchange)))
+                    .filter(timestamp.ge(spec.start_time).and(timestamp.lt(spec.end_time)))
+                    .order(timestamp.asc())
+                    .limit(1000000) // Prevent memory exhaustion
+                    .select(HistoricalSnapshot::as_select())
+                    .load::<HistoricalSnapshot>(&mut connection)
+                    .await?;
+                events.extend(rows.into_iter().map(ReplayEvent::Snapshot));
+            }
+
+            if spec.include_trades {
+                use crate::schema::trades::dsl::*;
+                let rows = trades
+                    .filter(symbol.eq(sym).and(exchange.eq(xchange)))
+                    .filter(created_at.ge(spec.start_time).and(created_at.lt(spec.end_time)))
+                    .order(created_at.asc())
+                    .limit(1000000) // Prevent memory exhaustion
+                    .select(Trade::as_select())
+                    .load::<Trade>(&mut connection)
+                    .await?;
+                events.extend(rows.into_iter().map(ReplayEvent::Trade));
+            }
+        }
+
+        info!(
+            "Loaded {} replay events for {} instruments in {}ms",
+            events.len(), spec.instruments.len(), load_start.elapsed().as_millis()
+        );
+        Ok(Replayer::from_events(events, pacing, tie_break))
+    }
+
+    pub fn controller(&self) -> ReplayController {
+        self.controller.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Events not yet released
+    pub fn remaining(&self) -> usize {
+        self.events.len() - self.cursor
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Event time of the next event to be released
+    pub fn position(&self) -> Option<DateTime<Utc>> {
+        self.events.get(self.cursor).map(|e| e.timestamp())
+    }
+
+    pub fn events(&self) -> &[ReplayEvent] {
+        &self.events
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.anchor = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.anchor = None;
+    }
+
+    /// Move the cursor to the first event at or after `to`
+    pub fn seek(&mut self, to: DateTime<Utc>) {
+        self.cursor = if self.chronological {
+            self.events.partition_point(|e| e.timestamp() < to)
+        } else {
+            self.events.iter().position(|e| e.timestamp() >= to).unwrap_or(self.events.len())
+        };
+        self.anchor = None;
+        debug!("Replay seeked to {} (event {})", to, self.cursor);
+    }
+
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacing = pacing;
+        self.anchor = None;
+    }
+
+    fn apply(&mut self, command: ReplayCommand) {
+        match command {
+            ReplayCommand::Pause => self.pause(),
+            ReplayCommand::Resume => self.resume(),
+            ReplayCommand::Seek(to) => self.seek(to),
+            ReplayCommand::SetPacing(pacing) => self.set_pacing(pacing),
+        }
+    }
+
+    /// Release the next event, waiting as the pacing requires and while paused.
+    /// Returns `None` once the stream is exhausted.
+    pub async fn next(&mut self) -> Option<ReplayEvent> {
+        loop {
+            while let Ok(command) = self.commands.try_recv() {
+                self.apply(command);
+            }
+
+            if self.paused {
+                // The replayer holds a sender itself, so this only returns on a command
+                match self.commands.recv().await {
+                    Some(command) => self.apply(command),
+                    None => return None,
+                }
+                continue;
+            }
+
+            let event_time = self.events.get(self.cursor)?.timestamp();
+
+            if let Some(speed) = self.pacing.speed() {
+                let (anchor_wall, anchor_event) = *self.anchor.get_or_insert((Instant::now(), event_time));
+                let offset = (event_time - anchor_event).to_std().unwrap_or_default();
+                let due = anchor_wall + offset.div_f64(speed);
+
+                tokio::select! {
+                    _ = tokio::time::sleep_until(due) => {},
+                    command = self.commands.recv() => {
+                        if let Some(command) = command {
+                            self.apply(command);
+                        }
+                        continue;
+                    },
+                }
+            }
+
+            let event = self.events[self.cursor].clone();
+            self.cursor += 1;
+            return Some(event);
+        }
+    }
+}
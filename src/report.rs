@@ -0,0 +1,337 @@
+use anyhow::Error;
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+use crate::models::backtest_result::{
+    BacktestDrawdownPeriod, BacktestEquityCurve, BacktestPositionHistory, BacktestResult, BacktestTrade,
+    ReportFormat,
+};
+
+/// Version of the layouts below, stored in `backtest_reports.template_version`
+pub const REPORT_TEMPLATE_VERSION: &str = "1.0";
+
+/// Points drawn in the HTML equity chart; longer curves are thinned evenly
+const CHART_POINTS: usize = 1000;
+const CHART_WIDTH: f64 = 960.0;
+const CHART_HEIGHT: f64 = 300.0;
+
+const BASE_CSS: &str = "body{font-family:-apple-system,Segoe UI,Helvetica,Arial,sans-serif;margin:2em;color:#222}\
+h1{font-size:1.5em}h2{font-size:1.15em;margin-top:2em}\
+table{border-collapse:collapse;margin:.5em 0}td,th{border:1px solid #ddd;padding:4px 10px;text-align:right}\
+th{background:#f4f4f4}td.key{text-align:left}svg{border:1px solid #ddd}";
+
+/// Everything a report is rendered from. `trades` and `positions` are empty unless the
+/// report includes trades.
+#[derive(Debug, Clone)]
+pub struct ReportData {
+    pub report_id: String,
+    pub report_name: String,
+    pub timeframe: String,
+    pub generated_at: DateTime<Utc>,
+    pub result: BacktestResult,
+    pub equity_curve: Vec<BacktestEquityCurve>,
+    pub drawdown_periods: Vec<BacktestDrawdownPeriod>,
+    pub trades: Vec<BacktestTrade>,
+    pub positions: Vec<BacktestPositionHistory>,
+    pub include_trades: bool,
+    pub include_charts: bool,
+    pub custom_css: Option<String>,
+}
+
+/// The `performance_summary`, `risk_summary` and `trade_summary` columns. Ratios are
+/// fractions and missing metrics are null.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportSummaries {
+    pub performance: Value,
+    pub risk: Value,
+    pub trade: Value,
+}
+
+fn num(value: &BigDecimal) -> Value {
+    value.to_f64().map(Value::from).unwrap_or(Value::Null)
+}
+
+fn opt_num(value: &Option<BigDecimal>) -> Value {
+    value.as_ref().map(num).unwrap_or(Value::Null)
+}
+
+/// Summaries of a stored result. Net profit is taken from the last equity point when
+/// there is one, otherwise from the total return.
+pub fn summarize(result: &BacktestResult, equity: &[BacktestEquityCurve], drawdowns: &[BacktestDrawdownPeriod]) -> ReportSummaries {
+    let initial = result.initial_capital.to_f64().unwrap_or(0.0);
+    let final_value = equity
+        .last()
+        .and_then(|p| p.portfolio_value.to_f64())
+        .unwrap_or_else(|| initial * (1.0 + result.total_return.to_f64().unwrap_or(0.0)));
+
+    ReportSummaries {
+        performance: json!({
+            "initial_capital": initial,
+            "final_value": final_value,
+            "net_profit": final_value - initial,
+            "total_return": num(&result.total_return),
+            "annualized_return": num(&result.annualized_return),
+            "volatility": num(&result.volatility),
+            "sharpe_ratio": opt_num(&result.sharpe_ratio),
+            "sortino_ratio": opt_num(&result.sortino_ratio),
+            "calmar_ratio": opt_num(&result.calmar_ratio),
+            "benchmark": result.benchmark,
+            "benchmark_return": opt_num(&result.benchmark_return),
+            "excess_return": opt_num(&result.excess_return),
+            "jensen_alpha": opt_num(&result.jensen_alpha),
+            "beta": opt_num(&result.beta),
+        }),
+        risk: json!({
+            "max_drawdown": num(&result.max_drawdown),
+            "max_drawdown_duration_days": result.max_drawdown_duration_days,
+            "current_drawdown": num(&result.current_drawdown),
+            "avg_drawdown": opt_num(&result.avg_drawdown),
+            "drawdown_periods": drawdowns.len(),
+            "value_at_risk_95": opt_num(&result.value_at_risk_95),
+            "expected_shortfall_95": opt_num(&result.expected_shortfall_95),
+            "tracking_error": opt_num(&result.tracking_error),
+            "information_ratio": opt_num(&result.information_ratio),
+            "correlation_with_benchmark": opt_num(&result.correlation_with_benchmark),
+        }),
+        trade: json!({
+            "total_trades": result.total_trades,
+            "win_rate": num(&result.win_rate),
+            "profit_factor": num(&result.profit_factor),
+            "avg_trade_return": num(&result.avg_trade_return),
+            "best_trade": opt_num(&result.best_trade),
+            "worst_trade": opt_num(&result.worst_trade),
+            "avg_time_in_trade": opt_num(&result.avg_time_in_trade),
+            "total_orders": result.total_orders,
+            "filled_orders": result.filled_orders,
+            "cancelled_orders": result.cancelled_orders,
+            "total_commission_paid": num(&result.total_commission_paid),
+            "avg_slippage": num(&result.avg_slippage),
+        }),
+    }
+}
+
+/// Render one report file
+pub fn render(data: &ReportData, summaries: &ReportSummaries, format: ReportFormat) -> Result<Vec<u8>, Error> {
+    match format {
+        ReportFormat::Html => Ok(render_html(data, summaries).into_bytes()),
+        ReportFormat::Json => render_json(data, summaries),
+        ReportFormat::Csv => Ok(render_csv(data, summaries).into_bytes()),
+    }
+}
+
+#[derive(Serialize)]
+struct ReportDocument<'a> {
+    report_id: &'a str,
+    report_name: &'a str,
+    template_version: &'a str,
+    timeframe: &'a str,
+    generated_at: DateTime<Utc>,
+    summaries: &'a ReportSummaries,
+    result: &'a BacktestResult,
+    equity_curve: &'a [BacktestEquityCurve],
+    drawdown_periods: &'a [BacktestDrawdownPeriod],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trades: Option<&'a [BacktestTrade]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    positions: Option<&'a [BacktestPositionHistory]>,
+}
+
+fn render_json(data: &ReportData, summaries: &ReportSummaries) -> Result<Vec<u8>, Error> {
+    let document = ReportDocument {
+        report_id: &data.report_id,
+        report_name: &data.report_name,
+        template_version: REPORT_TEMPLATE_VERSION,
+        timeframe: &data.timeframe,
+        generated_at: data.generated_at,
+        summaries,
+        result: &data.result,
+        equity_curve: &data.equity_curve,
+        drawdown_periods: &data.drawdown_periods,
+        trades: data.include_trades.then_some(data.trades.as_slice()),
+        positions: data.include_trades.then_some(data.positions.as_slice()),
+    };
+    Ok(serde_json::to_vec_pretty(&document)?)
+}
+
+/// Running drawdown from the peak at each equity point, as a negative fraction
+fn underwater(equity: &[BacktestEquityCurve]) -> Vec<f64> {
+    let mut peak = f64::MIN;
+    equity
+        .iter()
+        .map(|p| {
+            let value = p.portfolio_value.to_f64().unwrap_or(0.0);
+            peak = peak.max(value);
+            if peak > 0.0 { value / peak - 1.0 } else { 0.0 }
+        })
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Sections separated by a blank line, each starting with a `[name]` row and a header row
+fn render_csv(data: &ReportData, summaries: &ReportSummaries) -> String {
+    let mut out = String::new();
+
+    out.push_str("[summary]\nsection,metric,value\n");
+    for (section, values) in [("performance", &summaries.performance), ("risk", &summaries.risk), ("trade", &summaries.trade)] {
+        if let Some(map) = values.as_object() {
+            for (key, value) in map {
+                let _ = writeln!(out, "{},{},{}", section, key, csv_field(&value_text(value)));
+            }
+        }
+    }
+
+    out.push_str("\n[equity_curve]\ntimestamp,portfolio_value,drawdown\n");
+    for (point, drawdown) in data.equity_curve.iter().zip(underwater(&data.equity_curve)) {
+        let _ = writeln!(out, "{},{},{}", point.timestamp.to_rfc3339(), point.portfolio_value, drawdown);
+    }
+
+    out.push_str("\n[drawdown_periods]\nstart_date,end_date,duration_days,magnitude,recovery_date\n");
+    for d in &data.drawdown_periods {
+        let recovery = d.recovery_date.map(|r| r.to_rfc3339()).unwrap_or_default();
+        let _ = writeln!(out, "{},{},{},{},{}", d.start_date.to_rfc3339(), d.end_date.to_rfc3339(), d.duration_days, d.magnitude, recovery);
+    }
+
+    if data.include_trades {
+        out.push_str("\n[trades]\ntimestamp,trade_id,order_id,symbol,side,quantity,price,commission\n");
+        for t in &data.trades {
+            let _ = writeln!(out, "{},{},{},{},{},{},{},{}",
+                t.timestamp.to_rfc3339(), t.trade_id, t.order_id, csv_field(&t.symbol), csv_field(&t.side),
+                t.quantity, t.price, t.commission);
+        }
+    }
+    out
+}
+
+fn escape_html(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::Null => "&ndash;".to_string(),
+        Value::Number(n) if n.is_f64() => n.as_f64().map(|f| format!("{:.4}", f)).unwrap_or_default(),
+        other => escape_html(&value_text(other)),
+    }
+}
+
+fn summary_table(out: &mut String, title: &str, values: &Value) {
+    let _ = write!(out, "<h2>{}</h2><table>", title);
+    if let Some(map) = values.as_object() {
+        for (key, value) in map {
+            let _ = write!(out, "<tr><td class=\"key\">{}</td><td>{}</td></tr>", key.replace('_', " "), display_value(value));
+        }
+    }
+    out.push_str("</table>");
+}
+
+/// Inline SVG line chart of the equity curve
+fn equity_chart(equity: &[BacktestEquityCurve]) -> Option<String> {
+    let step = equity.len().div_ceil(CHART_POINTS).max(1);
+    let mut points: Vec<(i64, f64)> = equity
+        .iter()
+        .step_by(step)
+        .filter_map(|p| p.portfolio_value.to_f64().map(|v| (p.timestamp.timestamp(), v)))
+        .collect();
+    // Always end the line at the final point
+    if let Some(last) = equity.last().filter(|p| points.last().map(|(t, _)| *t) != Some(p.timestamp.timestamp())) {
+        points.extend(last.portfolio_value.to_f64().map(|v| (last.timestamp.timestamp(), v)));
+    }
+    let (t0, t1) = (points.first()?.0, points.last()?.0);
+    if points.len() < 2 || t1 <= t0 {
+        return None;
+    }
+    let low = points.iter().map(|(_, v)| *v).fold(f64::INFINITY, f64::min);
+    let high = points.iter().map(|(_, v)| *v).fold(f64::NEG_INFINITY, f64::max);
+    let span = if high > low { high - low } else { 1.0 };
+
+    let path: Vec<String> = points
+        .iter()
+        .map(|(t, v)| {
+            let x = (t - t0) as f64 / (t1 - t0) as f64 * CHART_WIDTH;
+            let y = CHART_HEIGHT - (v - low) / span * CHART_HEIGHT;
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect();
+    Some(format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\
+<polyline fill=\"none\" stroke=\"#1f77b4\" stroke-width=\"1.5\" points=\"{p}\"/></svg>\
+<p>{low:.2} &ndash; {high:.2}</p>",
+        w = CHART_WIDTH, h = CHART_HEIGHT, p = path.join(" "), low = low, high = high,
+    ))
+}
+
+fn render_html(data: &ReportData, summaries: &ReportSummaries) -> String {
+    let r = &data.result;
+    let mut out = String::new();
+    let _ = write!(out, "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title><style>{}{}</style></head><body>",
+        escape_html(&data.report_name), BASE_CSS,
+        // A stylesheet cannot close the <style> element it is embedded in
+        data.custom_css.as_deref().unwrap_or("").replace("</", "<\\/"));
+    let _ = write!(out, "<h1>{}</h1><p>{} on {} ({}), {} to {}. Report {} generated {}.</p>",
+        escape_html(&data.report_name), escape_html(&r.strategy_name), escape_html(&r.symbol), escape_html(&data.timeframe),
+        r.start_date.format("%Y-%m-%d"), r.end_date.format("%Y-%m-%d"),
+        escape_html(&data.report_id), data.generated_at.format("%Y-%m-%d %H:%M:%S UTC"));
+
+    summary_table(&mut out, "Performance", &summaries.performance);
+    summary_table(&mut out, "Risk", &summaries.risk);
+    summary_table(&mut out, "Trading", &summaries.trade);
+
+    if data.include_charts {
+        if let Some(chart) = equity_chart(&data.equity_curve) {
+            let _ = write!(out, "<h2>Equity curve</h2>{}", chart);
+        }
+    }
+
+    if !data.drawdown_periods.is_empty() {
+        out.push_str("<h2>Drawdown periods</h2><table><tr><th>Start</th><th>End</th><th>Days</th><th>Magnitude</th><th>Recovered</th></tr>");
+        for d in &data.drawdown_periods {
+            let recovery = d.recovery_date.map(|r| r.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "&ndash;".to_string());
+            let _ = write!(out, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.4}</td><td>{}</td></tr>",
+                d.start_date.format("%Y-%m-%d"), d.end_date.format("%Y-%m-%d"), d.duration_days,
+                d.magnitude.to_f64().unwrap_or(0.0), recovery);
+        }
+        out.push_str("</table>");
+    }
+
+    if data.include_trades && !data.trades.is_empty() {
+        out.push_str("<h2>Trades</h2><table><tr><th>Time</th><th>Symbol</th><th>Side</th><th>Quantity</th><th>Price</th><th>Commission</th></tr>");
+        for t in &data.trades {
+            let _ = write!(out, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                t.timestamp.format("%Y-%m-%d %H:%M:%S"), escape_html(&t.symbol), escape_html(&t.side),
+                t.quantity, t.price, t.commission);
+        }
+        out.push_str("</table>");
+    }
+
+    out.push_str("</body></html>");
+    out
+}
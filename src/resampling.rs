@@ -0,0 +1,383 @@
+use crate::models::candles::{Candle, CandleData};
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use chrono::{DateTime, Duration, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// How a series is resampled for Monte Carlo runs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ResamplingScheme {
+    /// Independent draws with replacement
+    Simple,
+    /// Fixed-length blocks starting anywhere a full block fits
+    FixedBlock { block_size: usize },
+    /// Fixed-length blocks that wrap from the end of the series back to the start,
+    /// so every observation is equally likely to be drawn
+    CircularBlock { block_size: usize },
+    /// Politis-Romano stationary bootstrap: wrapping blocks with geometrically
+    /// distributed lengths of the given mean
+    Stationary { mean_block_length: f64 },
+}
+
+impl ResamplingScheme {
+    /// Stable name recorded alongside Monte Carlo runs
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResamplingScheme::Simple => "simple",
+            ResamplingScheme::FixedBlock { .. } => "fixed_block",
+            ResamplingScheme::CircularBlock { .. } => "circular_block",
+            ResamplingScheme::Stationary { .. } => "stationary",
+        }
+    }
+
+    pub fn parameters(&self) -> serde_json::Value {
+        match self {
+            ResamplingScheme::Simple => serde_json::json!({}),
+            ResamplingScheme::FixedBlock { block_size } | ResamplingScheme::CircularBlock { block_size } => {
+                serde_json::json!({ "block_size": block_size })
+            },
+            ResamplingScheme::Stationary { mean_block_length } => serde_json::json!({ "mean_block_length": mean_block_length }),
+        }
+    }
+}
+
+/// Seed for path `path_index` of a run seeded with `base_seed` (SplitMix64), so every path
+/// of a run can be regenerated on its own
+pub fn path_seed(base_seed: u64, path_index: u32) -> u64 {
+    splitmix64(base_seed.wrapping_add((path_index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+}
+
+/// SplitMix64 finalizer: spreads nearby inputs across all 64 output bits
+fn splitmix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Seeded RNG when `seed` is given, entropy-seeded otherwise
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(s) => StdRng::seed_from_u64(s),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Map a u64 seed onto the `[-1, 1]` range PostgreSQL's `setseed` accepts. The seed is mixed
+/// first and only the top 53 bits are kept, so nearby seeds land far apart and every
+/// value is exact in an f64.
+pub fn sql_seed(seed: u64) -> f64 {
+    (splitmix64(seed) >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+/// Query shuffling the rows of `table` (`historical_orders` or `historical_snapshot`) for
+/// `$1` symbol and `$2` exchange within `$3`-wide `time_bucket` windows. Rows reach
+/// `random()` in `(timestamp, event_id)` order (`OFFSET 0` keeps the planner from flattening
+/// the subquery), so after `setseed` with parallel workers disabled the order is reproducible.
+pub(crate) fn window_shuffle_query(table: &str) -> String {
+    format!(
+        r#"
+        SELECT shuffled.*
+        FROM (
+            SELECT t.*, time_bucket($3, t.timestamp) AS shuffle_window
+            FROM {} t
+            WHERE t.symbol = $1 AND t.exchange = $2
+            ORDER BY t.timestamp, t.event_id
+            OFFSET 0
+        ) shuffled
+        ORDER BY shuffled.shuffle_window, random()
+        "#,
+        table
+    )
+}
+
+/// Draw `sample_size` indices into a series of length `len`
+pub fn resample_indices(len: usize, sample_size: usize, scheme: ResamplingScheme, rng: &mut StdRng) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(sample_size);
+    if len == 0 {
+        return indices;
+    }
+
+    match scheme {
+        ResamplingScheme::Simple => {
+            for _ in 0..sample_size {
+                indices.push(rng.gen_range(0..len));
+            }
+        },
+        ResamplingScheme::FixedBlock { block_size } => {
+            // Series shorter than a block always start at 0 rather than sampling an empty range
+            let block_size = block_size.clamp(1, len);
+            while indices.len() < sample_size {
+                let start = rng.gen_range(0..=len - block_size);
+                indices.extend((start..start + block_size).take(sample_size - indices.len()));
+            }
+        },
+        ResamplingScheme::CircularBlock { block_size } => {
+            let block_size = block_size.max(1);
+            while indices.len() < sample_size {
+                let start = rng.gen_range(0..len);
+                indices.extend((0..block_size).map(|k| (start + k) % len).take(sample_size - indices.len()));
+            }
+        },
+        ResamplingScheme::Stationary { mean_block_length } => {
+            let restart_probability = 1.0 / mean_block_length.max(1.0);
+            let mut current = rng.gen_range(0..len);
+            indices.push(current);
+            while indices.len() < sample_size {
+                current = if rng.gen_bool(restart_probability) {
+                    rng.gen_range(0..len)
+                } else {
+                    (current + 1) % len
+                };
+                indices.push(current);
+            }
+        },
+    }
+
+    indices.truncate(sample_size);
+    indices
+}
+
+/// Resample any series (orders, snapshots, candles) with the given scheme
+pub fn resample<T: Clone>(data: &[T], sample_size: usize, scheme: ResamplingScheme, seed: Option<u64>) -> Vec<T> {
+    let mut rng = seeded_rng(seed);
+    resample_indices(data.len(), sample_size, scheme, &mut rng)
+        .into_iter()
+        .map(|i| data[i].clone())
+        .collect()
+}
+
+/// Resample only from the observations labelled `target`. The matching observations are
+/// treated as one series in their original order, so blocks may join separate episodes
+/// of the same regime.
+pub fn regime_conditioned_resample<T: Clone, R: PartialEq>(
+    data: &[T],
+    regimes: &[R],
+    target: &R,
+    sample_size: usize,
+    scheme: ResamplingScheme,
+    seed: Option<u64>,
+) -> Vec<T> {
+    let in_regime: Vec<usize> = regimes
+        .iter()
+        .enumerate()
+        .filter(|(i, r)| *i < data.len() && *r == target)
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut rng = seeded_rng(seed);
+    resample_indices(in_regime.len(), sample_size, scheme, &mut rng)
+        .into_iter()
+        .map(|i| data[in_regime[i]].clone())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VolatilityRegime {
+    Low,
+    Normal,
+    High,
+}
+
+/// Label each candle by the rolling volatility of log close-to-close returns over `window`
+/// candles: the lowest third of the series is `Low`, the highest third `High`.
+pub fn classify_volatility_regimes<C: CandleData>(candles: &[C], window: usize) -> Vec<VolatilityRegime> {
+    let returns: Vec<f64> = candles
+        .windows(2)
+        .map(|pair| {
+            let prev = pair[0].close_price().to_f64().unwrap_or(0.0);
+            let next = pair[1].close_price().to_f64().unwrap_or(0.0);
+            if prev > 0.0 && next > 0.0 { (next / prev).ln() } else { 0.0 }
+        })
+        .collect();
+
+    let window = window.max(2);
+    // Volatility at candle i uses the returns ending at i (candle 0 has none)
+    let volatility: Vec<Option<f64>> = (0..candles.len())
+        .map(|i| {
+            let end = i.min(returns.len());
+            let start = end.saturating_sub(window);
+            let slice = &returns[start..end];
+            if slice.len() < 2 {
+                return None;
+            }
+            let mean = slice.iter().sum::<f64>() / slice.len() as f64;
+            let variance = slice.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (slice.len() - 1) as f64;
+            Some(variance.sqrt())
+        })
+        .collect();
+
+    let mut sorted: Vec<f64> = volatility.iter().flatten().copied().collect();
+    if sorted.is_empty() {
+        return vec![VolatilityRegime::Normal; candles.len()];
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let lower = sorted[sorted.len() / 3];
+    let upper = sorted[(sorted.len() * 2) / 3];
+
+    volatility
+        .into_iter()
+        .map(|v| match v {
+            Some(v) if v < lower => VolatilityRegime::Low,
+            Some(v) if v > upper => VolatilityRegime::High,
+            _ => VolatilityRegime::Normal,
+        })
+        .collect()
+}
+
+/// Label timestamped items with the regime of the candle they fall in. Items before the
+/// first candle take the first candle's regime. `candles` must be sorted by timestamp.
+pub fn label_by_candle_regime<T>(
+    items: &[T],
+    timestamp: impl Fn(&T) -> DateTime<Utc>,
+    candles: &[Candle],
+    regimes: &[VolatilityRegime],
+) -> Vec<VolatilityRegime> {
+    items
+        .iter()
+        .map(|item| {
+            let ts = timestamp(item);
+            let idx = candles.partition_point(|c| c.timestamp <= ts).saturating_sub(1);
+            regimes.get(idx).copied().unwrap_or(VolatilityRegime::Normal)
+        })
+        .collect()
+}
+
+/// Regime filter and draw settings for the `get_regime_conditioned_*` loaders. Rows are
+/// labelled with the regime of the rolling volatility of `timeframe` candles over
+/// `regime_window` bars, and only rows labelled `regime` are drawn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegimeSampleOptions {
+    pub regime: VolatilityRegime,
+    pub timeframe: String,
+    pub regime_window: usize,
+    pub sample_size: usize,
+    pub scheme: ResamplingScheme,
+    pub seed: Option<u64>,
+}
+
+/// One historical bar expressed relative to the previous close
+#[derive(Debug, Clone, Copy)]
+struct CandleShape {
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+    trade_count: i32,
+}
+
+/// Shape of the synthetic paths built by `synthesize_candle_paths`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CandlePathOptions {
+    pub n_paths: usize,
+    /// Bars per path
+    pub path_len: usize,
+    pub scheme: ResamplingScheme,
+    /// Only draw bars from this volatility regime
+    pub regime: Option<VolatilityRegime>,
+    /// Bars of rolling volatility used to classify regimes
+    pub regime_window: usize,
+    pub seed: Option<u64>,
+}
+
+/// Build `n_paths` synthetic candle paths of `path_len` bars by resampling historical bars
+/// as ratios to the prior close and chaining them from the first candle's close. Bar spacing
+/// follows the first two input candles. When `regime` is set only bars from that volatility
+/// regime (see `classify_volatility_regimes` with `regime_window`) are drawn.
+pub fn synthesize_candle_paths(candles: &[Candle], options: &CandlePathOptions) -> Vec<Vec<Candle>> {
+    let CandlePathOptions { n_paths, path_len, scheme, regime, regime_window, seed } = *options;
+    if candles.len() < 2 || path_len == 0 {
+        return Vec::new();
+    }
+
+    let ratio = |value: &BigDecimal, base: f64| value.to_f64().unwrap_or(0.0) / base;
+    let mut shapes = Vec::with_capacity(candles.len() - 1);
+    let mut shape_regimes = Vec::with_capacity(candles.len() - 1);
+    let regimes = classify_volatility_regimes(candles, regime_window);
+    for (i, pair) in candles.windows(2).enumerate() {
+        let base = pair[0].close_price.to_f64().unwrap_or(0.0);
+        if base <= 0.0 {
+            continue;
+        }
+        let bar = &pair[1];
+        shapes.push(CandleShape {
+            open: ratio(&bar.open_price, base),
+            high: ratio(&bar.high_price, base),
+            low: ratio(&bar.low_price, base),
+            close: ratio(&bar.close_price, base),
+            volume: bar.volume.to_f64().unwrap_or(0.0),
+            trade_count: bar.trade_count,
+        });
+        shape_regimes.push(regimes[i + 1]);
+    }
+
+    let pool: Vec<CandleShape> = match regime {
+        Some(target) => shapes
+            .iter()
+            .zip(&shape_regimes)
+            .filter(|(_, r)| **r == target)
+            .map(|(s, _)| *s)
+            .collect(),
+        None => shapes,
+    };
+    if pool.is_empty() {
+        return Vec::new();
+    }
+
+    let template = &candles[0];
+    let interval = candles[1].timestamp - candles[0].timestamp;
+    let interval = if interval > Duration::zero() { interval } else { Duration::minutes(1) };
+    let start_close = template.close_price.to_f64().unwrap_or(0.0);
+    let to_decimal = |v: f64| BigDecimal::from_f64(v).unwrap_or_default().round(8);
+
+    let mut rng = seeded_rng(seed);
+    (0..n_paths)
+        .map(|_| {
+            let mut prev_close = start_close;
+            resample_indices(pool.len(), path_len, scheme, &mut rng)
+                .into_iter()
+                .enumerate()
+                .map(|(step, i)| {
+                    let shape = pool[i];
+                    let close = prev_close * shape.close;
+                    let candle = Candle {
+                        timestamp: template.timestamp + interval * (step as i32 + 1),
+                        symbol: template.symbol.clone(),
+                        exchange: template.exchange.clone(),
+                        security_id: template.security_id,
+                        exchange_id: template.exchange_id,
+                        open_price: to_decimal(prev_close * shape.open),
+                        high_price: to_decimal(prev_close * shape.high),
+                        low_price: to_decimal(prev_close * shape.low),
+                        close_price: to_decimal(close),
+                        volume: to_decimal(shape.volume),
+                        trade_count: shape.trade_count,
+                        timeframe: template.timeframe.clone(),
+                        created_at: template.created_at,
+                    };
+                    prev_close = close;
+                    candle
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sql_seed_separates_small_seeds() {
+        let seeds = [0u64, 1, 2, 42, 511, 512, u64::MAX];
+        let values: Vec<f64> = seeds.iter().map(|&s| sql_seed(s)).collect();
+        for (i, a) in values.iter().enumerate() {
+            assert!((-1.0..=1.0).contains(a), "seed {} mapped outside [-1, 1]: {}", seeds[i], a);
+            for (j, b) in values.iter().enumerate().skip(i + 1) {
+                assert_ne!(a, b, "seeds {} and {} share a setseed value", seeds[i], seeds[j]);
+            }
+        }
+        assert_eq!(sql_seed(42), sql_seed(42));
+    }
+}
@@ -197,6 +197,33 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    backtest_segments (id) {
+        id -> Uuid,
+        walk_forward_id -> Uuid,
+        strategy_instance_id -> Nullable<Uuid>,
+        #[max_length = 10]
+        window_type -> Varchar,
+        fold_index -> Int4,
+        #[max_length = 20]
+        segment_type -> Varchar,
+        backtest_result_id -> Uuid,
+        window_start -> Timestamptz,
+        window_end -> Timestamptz,
+        parameters -> Nullable<Jsonb>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    backtest_tags (backtest_id, tag) {
+        backtest_id -> Uuid,
+        #[max_length = 100]
+        tag -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     backtest_trades (id) {
         id -> Uuid,
@@ -237,6 +264,60 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    corporate_actions (id) {
+        id -> Uuid,
+        security_id -> Uuid,
+        #[max_length = 20]
+        symbol -> Varchar,
+        #[max_length = 20]
+        action_type -> Varchar,
+        effective_date -> Timestamptz,
+        split_ratio -> Nullable<Numeric>,
+        dividend_amount -> Nullable<Numeric>,
+        #[max_length = 20]
+        new_symbol -> Nullable<Varchar>,
+        notes -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    exchange_calendars (exchange_id) {
+        exchange_id -> Uuid,
+        #[max_length = 64]
+        timezone -> Varchar,
+        description -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    exchange_holidays (id) {
+        id -> Uuid,
+        exchange_id -> Uuid,
+        holiday_date -> Date,
+        #[max_length = 255]
+        name -> Varchar,
+        early_close -> Nullable<Time>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    exchange_sessions (id) {
+        id -> Uuid,
+        exchange_id -> Uuid,
+        day_of_week -> Int2,
+        #[max_length = 20]
+        session_type -> Varchar,
+        open_time -> Time,
+        close_time -> Time,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     exchanges (exchange_id) {
         created_at -> Timestamptz,
@@ -282,6 +363,44 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    monte_carlo_paths (id) {
+        id -> Uuid,
+        run_id -> Uuid,
+        path_index -> Int4,
+        seed -> Int8,
+        #[max_length = 50]
+        resampling_scheme -> Varchar,
+        scheme_parameters -> Jsonb,
+        backtest_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    monte_carlo_runs (id) {
+        id -> Uuid,
+        #[max_length = 255]
+        strategy_name -> Varchar,
+        #[max_length = 50]
+        symbol -> Varchar,
+        #[max_length = 50]
+        exchange -> Varchar,
+        #[max_length = 20]
+        data_source -> Varchar,
+        #[max_length = 50]
+        resampling_scheme -> Varchar,
+        scheme_parameters -> Jsonb,
+        base_seed -> Nullable<Int8>,
+        num_paths -> Int4,
+        #[max_length = 20]
+        status -> Varchar,
+        notes -> Nullable<Text>,
+        created_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     open_buy_orders (created_at, unique_id) {
         created_at -> Timestamptz,
@@ -641,6 +760,32 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    symbol_history (id) {
+        id -> Uuid,
+        security_id -> Uuid,
+        #[max_length = 20]
+        symbol -> Varchar,
+        valid_from -> Timestamptz,
+        valid_to -> Nullable<Timestamptz>,
+        #[max_length = 20]
+        status -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    trade_keys (exchange_id, trade_id) {
+        exchange_id -> Uuid,
+        #[max_length = 255]
+        trade_id -> Varchar,
+        event_time -> Timestamptz,
+        first_seen_at -> Timestamptz,
+        last_seen_at -> Timestamptz,
+        duplicate_count -> Int4,
+    }
+}
+
 diesel::table! {
     trades (created_at, trade_id) {
         created_at -> Timestamptz,
@@ -656,6 +801,7 @@ diesel::table! {
         side -> Varchar,
         price -> Numeric,
         quantity -> Numeric,
+        ingested_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -665,17 +811,26 @@ diesel::joinable!(backtest_position_history -> backtest_results (backtest_result
 diesel::joinable!(backtest_report_access_log -> backtest_reports (report_id));
 diesel::joinable!(backtest_reports -> backtest_results (backtest_result_id));
 diesel::joinable!(backtest_results -> strategy_instances (strategy_instance_id));
+diesel::joinable!(backtest_segments -> backtest_results (backtest_result_id));
+diesel::joinable!(backtest_segments -> strategy_instances (strategy_instance_id));
 diesel::joinable!(backtest_trades -> backtest_results (backtest_result_id));
 diesel::joinable!(candles -> exchanges (exchange_id));
 diesel::joinable!(candles -> securities (security_id));
+diesel::joinable!(corporate_actions -> securities (security_id));
+diesel::joinable!(exchange_calendars -> exchanges (exchange_id));
+diesel::joinable!(exchange_holidays -> exchange_calendars (exchange_id));
+diesel::joinable!(exchange_sessions -> exchange_calendars (exchange_id));
 diesel::joinable!(historical_orders -> exchanges (exchange_id));
 diesel::joinable!(historical_orders -> securities (security_id));
 diesel::joinable!(historical_snapshot -> exchanges (exchange_id));
 diesel::joinable!(historical_snapshot -> securities (security_id));
+diesel::joinable!(monte_carlo_paths -> monte_carlo_runs (run_id));
 diesel::joinable!(optimization_iterations -> optimization_runs (optimization_run_id));
 diesel::joinable!(optimization_runs -> strategies (strategy_id));
 diesel::joinable!(strategy_instances -> strategies (strategy_id));
 diesel::joinable!(strategy_parameters -> strategies (strategy_id));
+diesel::joinable!(symbol_history -> securities (security_id));
+diesel::joinable!(trade_keys -> exchanges (exchange_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     backtest_drawdown_periods,
@@ -684,11 +839,19 @@ diesel::allow_tables_to_appear_in_same_query!(
     backtest_report_access_log,
     backtest_reports,
     backtest_results,
+    backtest_segments,
+    backtest_tags,
     backtest_trades,
     candles,
+    corporate_actions,
+    exchange_calendars,
+    exchange_holidays,
+    exchange_sessions,
     exchanges,
     historical_orders,
     historical_snapshot,
+    monte_carlo_paths,
+    monte_carlo_runs,
     open_buy_orders,
     open_sell_orders,
     optimization_iterations,
@@ -705,5 +868,7 @@ diesel::allow_tables_to_appear_in_same_query!(
     strategy_order_state_changes,
     strategy_orders,
     strategy_parameters,
+    symbol_history,
+    trade_keys,
     trades,
 );